@@ -0,0 +1,14 @@
+use gumdrop::Options;
+
+#[derive(Options)]
+pub struct ArmouryCommand {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(help = "list all firmware attributes and their values")]
+    pub list: bool,
+    #[options(
+        free,
+        help = "<attribute> to get the value, or <attribute> <value> to set it, e.g: boot_sound 0"
+    )]
+    pub free: Vec<String>,
+}
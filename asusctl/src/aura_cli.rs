@@ -146,6 +146,32 @@ pub struct SingleColour {
     pub zone: AuraZone,
 }
 
+#[derive(Debug, Clone, Options)]
+pub struct AuraFlash {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(meta = "", help = "set the RGB value e.g, ff0000")]
+    pub colour: Colour,
+    #[options(
+        short = 'n',
+        meta = "",
+        default = "3",
+        help = "how many times to flash, default 3"
+    )]
+    pub count: u32,
+    #[options(
+        meta = "",
+        default = "500",
+        help = "milliseconds for each on and off, default 500"
+    )]
+    pub period: u32,
+    #[options(
+        meta = "",
+        help = "set the zone to flash e.g, 0, 1, one, logo, lightbar-left"
+    )]
+    pub zone: AuraZone,
+}
+
 #[derive(Debug, Clone, Default, Options)]
 pub struct SingleColourSpeed {
     #[options(help = "print help message")]
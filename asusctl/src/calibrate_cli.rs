@@ -0,0 +1,220 @@
+use std::io::{BufRead, Write};
+
+use gumdrop::Options;
+use rog_aura::keyboard::{
+    calibration_leds, lit_packets, AuraLaptopUsbPackets, LayoutCalibration, LedCode,
+};
+use rog_aura::Colour;
+
+#[derive(Options)]
+pub struct AuraCalibrate {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(
+        meta = "",
+        help = "name of the layout to write, e.g g634j-per-key, defaults to <board name>-per-key"
+    )]
+    pub layout_name: Option<String>,
+    #[options(
+        meta = "",
+        default = "US",
+        help = "locale of the keyboard e.g, US, UK, DE"
+    )]
+    pub locale: String,
+    #[options(meta = "", help = "directory to write the files to, defaults to here")]
+    pub output: Option<String>,
+    #[options(
+        meta = "",
+        default = "ffffff",
+        help = "the RGB value to light each LED with e.g, ff0000"
+    )]
+    pub colour: Colour,
+}
+
+pub const INSTRUCTIONS: &str = "Each LED of the keyboard is lit in turn. For each, type the key \
+                                that lit up and press Enter:
+  - a printable key such as a, 5, [ or a space
+  - the LedCode or name of any other key, e.g LShift, Return, F5, Escape, LidLogo
+  - any other name for a key with no LedCode, e.g <>, add :<evdev code> to make it react to key \
+                                presses, e.g <>:86
+  - just Enter if nothing lit up
+  - undo to go back one LED
+Press Ctrl+D to finish early.";
+
+/// What the user said lit up
+#[derive(Debug, PartialEq)]
+pub enum Answer {
+    Nothing,
+    Undo,
+    Key(LedCode),
+    Extra(String, Option<u16>),
+}
+
+/// Parse a line typed for an LED, without the line ending
+pub fn parse_answer(line: &str) -> Answer {
+    if line == " " {
+        return Answer::Key(LedCode::Spacebar);
+    }
+    let text = line.trim();
+    let mut chars = text.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if let Some(key) = key_for_char(c) {
+            return Answer::Key(key);
+        }
+    }
+    if text.is_empty() {
+        return Answer::Nothing;
+    }
+    if text.eq_ignore_ascii_case("undo") {
+        return Answer::Undo;
+    }
+    if let Some(key) = LedCode::ALL.into_iter().find(|key| {
+        format!("{key:?}").eq_ignore_ascii_case(text)
+            || <&str>::from(key).eq_ignore_ascii_case(text)
+    }) {
+        return Answer::Key(key);
+    }
+    match text.rsplit_once(':') {
+        Some((name, code)) if !name.is_empty() && code.parse::<u16>().is_ok() => {
+            Answer::Extra(name.to_owned(), code.parse().ok())
+        }
+        _ => Answer::Extra(text.to_owned(), None),
+    }
+}
+
+/// The key typed as `c`
+fn key_for_char(c: char) -> Option<LedCode> {
+    let key = match c.to_ascii_uppercase() {
+        '`' | '~' => LedCode::Tilde,
+        '1' => LedCode::N1,
+        '2' => LedCode::N2,
+        '3' => LedCode::N3,
+        '4' => LedCode::N4,
+        '5' => LedCode::N5,
+        '6' => LedCode::N6,
+        '7' => LedCode::N7,
+        '8' => LedCode::N8,
+        '9' => LedCode::N9,
+        '0' => LedCode::N0,
+        '-' | '_' => LedCode::Hyphen,
+        '=' => LedCode::Equals,
+        '[' | '{' => LedCode::LBracket,
+        ']' | '}' => LedCode::RBracket,
+        '\\' | '|' => LedCode::BackSlash,
+        ';' | ':' => LedCode::SemiColon,
+        '\'' | '"' => LedCode::Quote,
+        ',' => LedCode::Comma,
+        '.' => LedCode::Period,
+        '/' | '?' => LedCode::FwdSlash,
+        '*' => LedCode::Star,
+        '+' => LedCode::NumPadPlus,
+        c if c.is_ascii_uppercase() => {
+            let name = c.to_string();
+            return LedCode::ALL
+                .into_iter()
+                .find(|key| format!("{key:?}") == name);
+        }
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Light each LED with `send` and ask which key lit up, reading the answers
+/// from `input`
+pub fn calibrate(
+    mut send: impl FnMut(AuraLaptopUsbPackets) -> Result<(), Box<dyn std::error::Error>>,
+    mut input: impl BufRead,
+    colour: Colour,
+) -> Result<LayoutCalibration, Box<dyn std::error::Error>> {
+    let leds = calibration_leds();
+    let mut calibration = LayoutCalibration::new();
+    let mut i = 0;
+    while let Some(led) = leds.get(i) {
+        send(lit_packets(*led, colour))?;
+        print!("[{}/{}] {led:?}: ", i + 1, leds.len());
+        std::io::stdout().flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            println!();
+            break;
+        }
+        match parse_answer(line.trim_end_matches([
+            '\r', '\n',
+        ])) {
+            Answer::Nothing => calibration.forget(*led),
+            Answer::Undo => {
+                i = i.saturating_sub(1);
+                calibration.forget(leds[i]);
+                continue;
+            }
+            Answer::Key(key) => {
+                let used = calibration.found(*led, key);
+                if used != key {
+                    println!("  {key:?} is not addressed here, the layout uses {used:?}");
+                }
+            }
+            Answer::Extra(name, evdev_key) => calibration.found_extra(*led, &name, evdev_key),
+        }
+        i += 1;
+    }
+    Ok(calibration)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rog_aura::keyboard::LedCode;
+    use rog_aura::Colour;
+
+    use super::{calibrate, parse_answer, Answer};
+
+    #[test]
+    fn answers() {
+        assert_eq!(parse_answer(""), Answer::Nothing);
+        assert_eq!(parse_answer(" "), Answer::Key(LedCode::Spacebar));
+        assert_eq!(parse_answer("a"), Answer::Key(LedCode::A));
+        assert_eq!(parse_answer("Q"), Answer::Key(LedCode::Q));
+        assert_eq!(parse_answer("5"), Answer::Key(LedCode::N5));
+        assert_eq!(parse_answer("["), Answer::Key(LedCode::LBracket));
+        assert_eq!(parse_answer("lshift"), Answer::Key(LedCode::LShift));
+        assert_eq!(parse_answer("Escape"), Answer::Key(LedCode::Esc));
+        assert_eq!(parse_answer("F5 "), Answer::Key(LedCode::F5));
+        assert_eq!(parse_answer("undo"), Answer::Undo);
+        assert_eq!(parse_answer("<>"), Answer::Extra("<>".to_owned(), None));
+        assert_eq!(
+            parse_answer("<>:86"),
+            Answer::Extra("<>".to_owned(), Some(86))
+        );
+        assert_eq!(parse_answer("a:b"), Answer::Extra("a:b".to_owned(), None));
+    }
+
+    #[test]
+    fn calibrate_answers() {
+        let mut sent = Vec::new();
+        // Esc at the first LED, nothing at the second, a mistake at the third
+        // undone and answered again, then finished early
+        let input = "esc\n\nx\nundo\nz\n";
+        let calibration = calibrate(
+            |packets| {
+                sent.push(packets);
+                Ok(())
+            },
+            Cursor::new(input),
+            Colour { r: 255, g: 0, b: 0 },
+        )
+        .unwrap();
+        // The third and fourth LEDs lit twice for the undo
+        assert_eq!(sent.len(), 6);
+        assert_eq!(sent[0][0][9], 255);
+        assert_eq!(sent[1][0][12], 255);
+        assert_eq!(sent[2][0][15], 255);
+        assert_eq!(sent[3][0][18], 255);
+        assert_eq!(sent[4][0][15], 255);
+        assert_eq!(calibration.len(), 2);
+        let layout = calibration.layout("US");
+        assert_eq!(layout.led_name(LedCode::Raw(0, 9)), "Escape");
+        assert_eq!(layout.led_name(LedCode::Raw(0, 15)), "Z");
+    }
+}
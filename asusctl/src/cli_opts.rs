@@ -1,6 +1,13 @@
 use gumdrop::Options;
 
-use crate::aura_cli::{LedBrightness, LedPowerCommand1, LedPowerCommand2, SetAuraBuiltin};
+use crate::armoury_cli::ArmouryCommand;
+use crate::aura_cli::{
+    AuraFlash, LedBrightness, LedPowerCommand1, LedPowerCommand2, SetAuraBuiltin,
+};
+use crate::calibrate_cli::AuraCalibrate;
+use crate::dissect_cli::DissectCommand;
+use crate::scene_cli::SceneCommand;
+use crate::schedule_cli::ScheduleCommand;
 use crate::scsi_cli::ScsiCommand;
 use crate::slash_cli::SlashCommand;
 
@@ -18,6 +25,10 @@ pub struct CliStart {
     pub next_kbd_bright: bool,
     #[options(help = "Toggle to previous keyboard brightness")]
     pub prev_kbd_bright: bool,
+    #[options(meta = "", help = "Set your battery charge limit <20-100>")]
+    pub chg_limit: Option<u8>,
+    #[options(help = "Toggle one-shot battery charge to 100%")]
+    pub one_shot_chg: bool,
     #[options(command)]
     pub command: Option<CliCommand>,
 }
@@ -30,10 +41,28 @@ pub enum CliCommand {
     AuraPowerOld(LedPowerCommand1),
     #[options(help = "Set the LED power states")]
     AuraPower(LedPowerCommand2),
+    #[options(help = "Flash the keyboard, logo or lightbar then restore the lighting")]
+    AuraFlash(AuraFlash),
+    #[options(help = "Light each LED in turn to write the layout of a new per-key keyboard")]
+    AuraCalibrate(AuraCalibrate),
     #[options(name = "slash", help = "Manage Slash Ledbar")]
     Slash(SlashCommand),
     #[options(name = "scsi", help = "Manage SCSI external drive")]
     Scsi(ScsiCommand),
+    #[options(name = "armoury", help = "Manage firmware attributes")]
+    Armoury(ArmouryCommand),
+    #[options(
+        name = "scene",
+        help = "Save and apply lighting scenes for all devices"
+    )]
+    Scene(SceneCommand),
+    #[options(name = "schedule", help = "Change the lighting at set times of day")]
+    Schedule(ScheduleCommand),
+    #[options(
+        name = "dissect",
+        help = "Decode raw Aura, Slash or SCSI packets (does not need asusd)"
+    )]
+    Dissect(DissectCommand),
 }
 
 #[derive(Options)]
@@ -50,6 +79,11 @@ pub struct LedModeCommand {
         help = "get current aura mode and all mode options"
     )]
     pub get: bool,
+    #[options(
+        meta = "",
+        help = "crossfade for <ms> when changing mode on per-key and zoned keyboards, 0 to disable"
+    )]
+    pub transition: Option<u32>,
     #[options(command)]
     pub command: Option<SetAuraBuiltin>,
 }
@@ -0,0 +1,179 @@
+use std::fs;
+
+use gumdrop::Options;
+use rog_aura::AuraDeviceType;
+
+#[derive(Options)]
+pub struct DissectCommand {
+    #[options(help = "print help message")]
+    pub help: bool,
+
+    #[options(
+        meta = "",
+        help = "read packets from a hex dump or usbmon text file, one packet per line"
+    )]
+    pub file: Option<String>,
+
+    #[options(
+        meta = "",
+        help = "decode as <aura, slash, scsi>, the default tries each in turn"
+    )]
+    pub device: Option<String>,
+
+    #[options(
+        meta = "",
+        help = "USB product ID of the Aura device, e.g 19b6, required to decode power packets"
+    )]
+    pub prod_id: Option<String>,
+
+    #[options(free, help = "a single packet given as hex bytes")]
+    pub bytes: Vec<String>,
+}
+
+/// Parse a hex dump in to packets, one per line. Accepts plain hex with or
+/// without `0x` prefixes and comma or space separators, lines prefixed with an
+/// offset such as `0010:`, and usbmon text lines where the data follows `=`.
+/// usbmon lines without data, such as most completions, are skipped. Anything
+/// after a `#` is ignored.
+pub fn parse_hex_dump(text: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut packets = Vec::new();
+    for (num, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let line = match line.split_once('=') {
+            Some((_, data)) => data,
+            None if is_usbmon(line) => continue,
+            None => line,
+        };
+        let mut pkt = Vec::new();
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            let token = token.trim_start_matches("0x").trim_start_matches("0X");
+            if token.is_empty() || token.ends_with(':') {
+                continue;
+            }
+            if token.len() % 2 != 0 {
+                return Err(format!("line {}: odd length hex '{token}'", num + 1));
+            }
+            for i in (0..token.len()).step_by(2) {
+                let byte = token
+                    .get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+                    .ok_or_else(|| format!("line {}: invalid hex '{token}'", num + 1))?;
+                pkt.push(byte);
+            }
+        }
+        if !pkt.is_empty() {
+            packets.push(pkt);
+        }
+    }
+    Ok(packets)
+}
+
+/// If `line` is a usbmon text line: a URB tag, timestamp, event type and an
+/// address such as `Co:3:002:0`
+fn is_usbmon(line: &str) -> bool {
+    let mut tokens = line.split_whitespace().skip(2);
+    let (Some(event), Some(address)) = (tokens.next(), tokens.next()) else {
+        return false;
+    };
+    matches!(event, "S" | "C" | "E")
+        && address
+            .split_once(':')
+            .is_some_and(|(kind, _)| kind.len() == 2 && "CIBZ".contains(&kind[..1]))
+}
+
+fn print_decoded(
+    pkt: &[u8],
+    device: Option<&str>,
+    dev_type: AuraDeviceType,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let aura = || rog_aura::dissect::decode(pkt, dev_type);
+    let slash = || rog_slash::dissect::decode(pkt);
+    let scsi = || rog_scsi::dissect::decode(pkt);
+
+    match device {
+        Some("aura") => println!("{}", aura()),
+        Some("slash") => println!("{}", slash()),
+        Some("scsi") => println!("{}", scsi()),
+        Some(d) => return Err(format!("Unknown device '{d}', use aura, slash or scsi").into()),
+        None => {
+            let (aura, slash, scsi) = (aura(), slash(), scsi());
+            if !aura.is_unknown() {
+                println!("{aura}");
+            } else if !slash.is_unknown() {
+                println!("{slash}");
+            } else if !scsi.is_unknown() {
+                println!("{scsi}");
+            } else {
+                println!("Unknown");
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_dissect(cmd: &DissectCommand) -> Result<(), Box<dyn std::error::Error>> {
+    if (cmd.file.is_none() && cmd.bytes.is_empty()) || cmd.help {
+        println!("Missing arg or command\n\n{}", cmd.self_usage());
+        return Ok(());
+    }
+
+    let mut packets = parse_hex_dump(&cmd.bytes.join(" "))?;
+    if let Some(file) = &cmd.file {
+        packets.extend(parse_hex_dump(&fs::read_to_string(file)?)?);
+    }
+    let dev_type = cmd
+        .prod_id
+        .as_deref()
+        .map(AuraDeviceType::from)
+        .unwrap_or_default();
+
+    let mut packets = packets.into_iter().peekable();
+    while let Some(pkt) = packets.next() {
+        println!("{}", hex(&pkt));
+        // A usbmon capture of a SCSI device has the CDB in a Command Block
+        // Wrapper, with the data following in the next packet
+        if let Some(cdb) = rog_scsi::dissect::cdb_from_cbw(&pkt) {
+            let mut combined = cdb.to_vec();
+            if let Some(data) = packets.next_if(|p| rog_scsi::dissect::cdb_from_cbw(p).is_none()) {
+                combined.extend_from_slice(&data);
+            }
+            println!("{}", rog_scsi::dissect::decode(&combined));
+            continue;
+        }
+        print_decoded(&pkt, cmd.device.as_deref(), dev_type)?;
+    }
+    Ok(())
+}
+
+fn hex(pkt: &[u8]) -> String {
+    pkt.iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_hex_dump;
+
+    #[test]
+    fn hex_dump_formats() {
+        let text = "5d b3 00 01 # static\n0x5d, 0xb5\n0000: 5db4\nffff8881 3410613840 S \
+                    Co:3:002:0 s 21 09 035d 0002 0011 17 = 5db30001\n\n";
+        assert_eq!(parse_hex_dump(text).unwrap(), vec![
+            vec![0x5d, 0xb3, 0x00, 0x01],
+            vec![0x5d, 0xb5],
+            vec![0x5d, 0xb4],
+            vec![0x5d, 0xb3, 0x00, 0x01],
+        ]);
+        // A submission with its data, then its completion without any
+        let capture = "ffff88810a9e5e40 3410613840 S Co:3:002:0 s 21 09 035d 0002 0011 17 = \
+                       5db3000100ff0000\nffff88810a9e5e40 3410614000 C Co:3:002:0 0 17 \
+                       >\nffff88810a9e6a80 3410614100 C Ii:3:002:4 -2:8 0\n";
+        assert_eq!(parse_hex_dump(capture).unwrap(), vec![vec![
+            0x5d, 0xb3, 0x00, 0x01, 0x00, 0xff, 0x00, 0x00
+        ]]);
+        assert!(parse_hex_dump("5d b").is_err());
+        assert!(parse_hex_dump("zz").is_err());
+    }
+}
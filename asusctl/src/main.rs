@@ -1,18 +1,27 @@
 use std::env::args;
 use std::process::Command;
 
-use aura_cli::{LedPowerCommand1, LedPowerCommand2};
+use armoury_cli::ArmouryCommand;
+use aura_cli::{AuraFlash, LedPowerCommand1, LedPowerCommand2};
+use calibrate_cli::AuraCalibrate;
 use dmi_id::DMIID;
 use gumdrop::{Opt, Options};
 use log::error;
-use rog_aura::keyboard::{AuraPowerState, LaptopAuraPower};
-use rog_aura::{self, AuraDeviceType, AuraEffect, PowerZones};
+use rog_aura::aura_detection::LedSupportData;
+use rog_aura::keyboard::{AdvancedAuraType, AuraPowerState, LaptopAuraPower};
+use rog_aura::{self, AuraDeviceType, AuraEffect, AuraModeNum, Colour, PowerZones};
+use rog_dbus::asus_armoury::AsusArmouryProxyBlocking;
 use rog_dbus::list_iface_blocking;
 use rog_dbus::scsi_aura::ScsiAuraProxyBlocking;
 use rog_dbus::zbus_aura::AuraProxyBlocking;
+use rog_dbus::zbus_platform::PlatformProxyBlocking;
+use rog_dbus::zbus_scenes::ScenesProxyBlocking;
+use rog_dbus::zbus_schedules::{RuleBrightness, ScheduleRule, SchedulesProxyBlocking};
 use rog_dbus::zbus_slash::SlashProxyBlocking;
 use rog_scsi::AuraMode;
 use rog_slash::SlashMode;
+use scene_cli::{SceneCommand, SceneSubCommand};
+use schedule_cli::{ScheduleCommand, ScheduleSubCommand};
 use scsi_cli::ScsiCommand;
 use zbus::blocking::proxy::ProxyImpl;
 use zbus::blocking::Connection;
@@ -21,8 +30,13 @@ use crate::aura_cli::{AuraPowerStates, LedBrightness};
 use crate::cli_opts::*;
 use crate::slash_cli::SlashCommand;
 
+mod armoury_cli;
 mod aura_cli;
+mod calibrate_cli;
 mod cli_opts;
+mod dissect_cli;
+mod scene_cli;
+mod schedule_cli;
 mod scsi_cli;
 mod slash_cli;
 
@@ -52,6 +66,14 @@ fn main() {
         }
     };
 
+    // Decoding packets is entirely offline
+    if let Some(CliCommand::Dissect(cmd)) = &parsed.command {
+        if let Err(err) = dissect_cli::handle_dissect(cmd) {
+            println!("\nError: {}\n", err);
+        }
+        return;
+    }
+
     let conn = Connection::system().unwrap();
     let supported_interfaces = match list_iface_blocking() {
         Ok(ifaces) => ifaces,
@@ -150,7 +172,7 @@ where
 fn do_parsed(
     parsed: &CliStart,
     supported_interfaces: &[String],
-    _conn: Connection,
+    conn: Connection,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match &parsed.command {
         Some(CliCommand::Aura(mode)) => handle_led_mode(mode)?,
@@ -158,11 +180,19 @@ fn do_parsed(
         Some(CliCommand::AuraPower(pow)) => handle_led_power2(pow)?,
         Some(CliCommand::Slash(cmd)) => handle_slash(cmd)?,
         Some(CliCommand::Scsi(cmd)) => handle_scsi(cmd)?,
+        Some(CliCommand::Armoury(cmd)) => handle_armoury(cmd)?,
+        Some(CliCommand::Scene(cmd)) => handle_scene(cmd, &conn)?,
+        Some(CliCommand::Schedule(cmd)) => handle_schedule(cmd, &conn)?,
+        Some(CliCommand::AuraFlash(flash)) => handle_aura_flash(flash)?,
+        Some(CliCommand::AuraCalibrate(cmd)) => handle_aura_calibrate(cmd)?,
+        Some(CliCommand::Dissect(cmd)) => dissect_cli::handle_dissect(cmd)?,
         None => {
             if (!parsed.show_supported
                 && parsed.kbd_bright.is_none()
                 && !parsed.next_kbd_bright
-                && !parsed.prev_kbd_bright)
+                && !parsed.prev_kbd_bright
+                && parsed.chg_limit.is_none()
+                && !parsed.one_shot_chg)
                 || parsed.help
             {
                 println!("{}", CliStart::usage());
@@ -253,6 +283,22 @@ fn do_parsed(
         }
     }
 
+    if let Some(limit) = parsed.chg_limit {
+        let platform = find_iface::<PlatformProxyBlocking>("xyz.ljones.Platform")?;
+        for proxy in platform.iter() {
+            proxy.set_charge_control_end_threshold(limit)?;
+        }
+    }
+
+    if parsed.one_shot_chg {
+        let platform = find_iface::<PlatformProxyBlocking>("xyz.ljones.Platform")?;
+        for proxy in platform.iter() {
+            proxy.one_shot_full_charge()?;
+            let limit = proxy.charge_control_end_threshold()?;
+            println!("Battery charge limit is now {limit}%");
+        }
+    }
+
     if parsed.show_supported {
         println!("Supported Core Functions:\n{:#?}", supported_interfaces);
         if let Ok(aura) = find_iface::<AuraProxyBlocking>("xyz.ljones.Aura") {
@@ -406,6 +452,157 @@ fn handle_slash(cmd: &SlashCommand) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn handle_scene(cmd: &SceneCommand, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(command) = cmd.command.as_ref().filter(|_| !cmd.help) else {
+        println!("Missing arg or command\n\n{}", cmd.self_usage());
+        if let Some(lst) = cmd.self_command_list() {
+            println!("\n{}", lst);
+        }
+        return Ok(());
+    };
+
+    let scenes = ScenesProxyBlocking::new(conn)?;
+    let name = |args: &scene_cli::SceneName| -> Result<String, Box<dyn std::error::Error>> {
+        match args.free.as_slice() {
+            [name] if !args.help => Ok(name.clone()),
+            _ => Err(format!("A scene name is required\n\n{}", args.self_usage()).into()),
+        }
+    };
+    match command {
+        SceneSubCommand::List(_) => {
+            for scene in scenes.list_scenes()? {
+                println!("{scene}");
+            }
+        }
+        SceneSubCommand::Save(args) => {
+            let name = name(args)?;
+            scenes.save_scene(&name)?;
+            println!("Saved scene {name}");
+        }
+        SceneSubCommand::Apply(args) => scenes.apply_scene(&name(args)?)?,
+        SceneSubCommand::Delete(args) => scenes.delete_scene(&name(args)?)?,
+    }
+    Ok(())
+}
+
+fn handle_schedule(
+    cmd: &ScheduleCommand,
+    conn: &Connection,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(command) = cmd.command.as_ref().filter(|_| !cmd.help) else {
+        println!("Missing arg or command\n\n{}", cmd.self_usage());
+        if let Some(lst) = cmd.self_command_list() {
+            println!("\n{}", lst);
+        }
+        return Ok(());
+    };
+
+    let schedules = SchedulesProxyBlocking::new(conn)?;
+    match command {
+        ScheduleSubCommand::List(_) => {
+            let active = schedules.active_rule()?;
+            for (name, rule) in schedules.list_rules()? {
+                let mark = if name == active { "*" } else { " " };
+                print!(
+                    "{mark} {name}: {}-{}, priority {}",
+                    rule.start, rule.end, rule.priority
+                );
+                if !rule.scene.is_empty() {
+                    print!(", scene {}", rule.scene);
+                }
+                if rule.brightness != RuleBrightness::Unchanged {
+                    print!(", brightness {:?}", rule.brightness);
+                }
+                for effect in &rule.effects {
+                    let Colour { r, g, b } = effect.colour1;
+                    print!(", {} {r:02x}{g:02x}{b:02x}", effect.mode_name());
+                }
+                println!();
+            }
+        }
+        ScheduleSubCommand::Set(args) => {
+            let (Some(start), Some(end), [name], false) =
+                (args.start, args.end, args.free.as_slice(), args.help)
+            else {
+                println!(
+                    "A name, start and end are required\n\n{}",
+                    args.self_usage()
+                );
+                return Ok(());
+            };
+            let rule = ScheduleRule {
+                start,
+                end,
+                priority: args.priority,
+                scene: args.scene.clone().unwrap_or_default(),
+                brightness: args.brightness.unwrap_or_default(),
+                effects: args
+                    .colour
+                    .map(|colour| AuraEffect {
+                        mode: AuraModeNum::Static,
+                        colour1: colour,
+                        ..Default::default()
+                    })
+                    .into_iter()
+                    .collect(),
+            };
+            schedules.set_rule(name, &rule)?;
+        }
+        ScheduleSubCommand::Delete(args) => match args.free.as_slice() {
+            [name] if !args.help => schedules.delete_rule(name)?,
+            _ => println!("A rule name is required\n\n{}", args.self_usage()),
+        },
+    }
+    Ok(())
+}
+
+fn handle_armoury(cmd: &ArmouryCommand) -> Result<(), Box<dyn std::error::Error>> {
+    if (!cmd.list && cmd.free.is_empty()) || cmd.free.len() > 2 || cmd.help {
+        println!("Missing arg or command\n\n{}", cmd.self_usage());
+        return Ok(());
+    }
+
+    let attrs = find_iface::<AsusArmouryProxyBlocking>("xyz.ljones.AsusArmoury")?;
+    let attr_name = |proxy: &AsusArmouryProxyBlocking| {
+        proxy
+            .inner()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    if cmd.list {
+        for proxy in attrs.iter() {
+            let name = attr_name(proxy);
+            let value = proxy.current_value()?;
+            let (min, max) = (proxy.min_value()?, proxy.max_value()?);
+            let possible = proxy.possible_values()?;
+            if !possible.is_empty() {
+                println!("{name}: {value} {possible:?}");
+            } else if min != -1 && max != -1 {
+                println!("{name}: {value} [{min}-{max}]");
+            } else {
+                println!("{name}: {value}");
+            }
+        }
+    }
+
+    if let Some(name) = cmd.free.first() {
+        let proxy = attrs
+            .iter()
+            .find(|proxy| attr_name(proxy) == *name)
+            .ok_or_else(|| format!("No firmware attribute named {name}"))?;
+        if let Some(value) = cmd.free.get(1) {
+            proxy.set_current_value(value.parse()?)?;
+        }
+        println!("{name}: {}", proxy.current_value()?);
+    }
+
+    Ok(())
+}
+
 fn handle_scsi(cmd: &ScsiCommand) -> Result<(), Box<dyn std::error::Error>> {
     if (!cmd.list && cmd.enable.is_none() && cmd.mode.is_none() && cmd.colours.is_empty())
         || cmd.help
@@ -527,6 +724,15 @@ fn handle_led_mode(mode: &LedModeCommand) -> Result<(), Box<dyn std::error::Erro
         return Ok(());
     }
 
+    if let Some(millis) = mode.transition {
+        for aura in &aura {
+            aura.set_transition_time(millis)?;
+        }
+        if mode.command.is_none() && !mode.prev_mode && !mode.next_mode {
+            return Ok(());
+        }
+    }
+
     if mode.command.is_none() && !mode.prev_mode && !mode.next_mode {
         if !mode.help {
             println!("Missing arg or command\n");
@@ -601,6 +807,83 @@ fn handle_led_mode(mode: &LedModeCommand) -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
+fn handle_aura_flash(flash: &AuraFlash) -> Result<(), Box<dyn std::error::Error>> {
+    if flash.help {
+        println!("{}", flash.self_usage());
+        return Ok(());
+    }
+    for aura in find_iface::<AuraProxyBlocking>("xyz.ljones.Aura")? {
+        aura.flash(flash.colour, flash.count, flash.period, flash.zone)?;
+    }
+    Ok(())
+}
+
+fn handle_aura_calibrate(cmd: &AuraCalibrate) -> Result<(), Box<dyn std::error::Error>> {
+    if cmd.help {
+        println!("{}", cmd.self_usage());
+        return Ok(());
+    }
+    let aura = find_iface::<AuraProxyBlocking>("xyz.ljones.Aura")?;
+    let aura = aura.first().ok_or("No Aura device found")?;
+    let board_name = DMIID::new()
+        .unwrap_or_default()
+        .board_name
+        .trim()
+        .to_owned();
+    let layout_name = cmd
+        .layout_name
+        .clone()
+        .unwrap_or_else(|| format!("{}-per-key", board_name.to_lowercase()));
+
+    println!("{}\n", calibrate_cli::INSTRUCTIONS);
+    let mode = aura.led_mode()?;
+    let calibration = calibrate_cli::calibrate(
+        |packets| Ok(aura.direct_addressing_raw(packets)?),
+        std::io::stdin().lock(),
+        cmd.colour,
+    );
+    // Put back the lighting from before
+    aura.set_led_mode(mode)?;
+    let calibration = calibration?;
+    if calibration.is_empty() {
+        println!("No keys were found, nothing written");
+        return Ok(());
+    }
+
+    let dir = std::path::PathBuf::from(cmd.output.as_deref().unwrap_or("."));
+    let pretty = ron::ser::PrettyConfig::new().depth_limit(4);
+    let layout_file = dir.join(format!("{layout_name}_{}.ron", cmd.locale.to_uppercase()));
+    let layout = calibration.layout(&cmd.locale.to_uppercase());
+    std::fs::write(
+        &layout_file,
+        ron::ser::to_string_pretty(&layout, pretty.clone())?,
+    )?;
+
+    let support = LedSupportData {
+        device_name: board_name,
+        product_id: String::new(),
+        layout_name: layout_name.clone(),
+        basic_modes: aura.supported_basic_modes()?,
+        basic_zones: aura.supported_basic_zones()?,
+        advanced_type: AdvancedAuraType::PerKey,
+        power_zones: aura.supported_power_zones()?,
+    };
+    let support_file = dir.join(format!("{layout_name}_aura_support.ron"));
+    std::fs::write(&support_file, ron::ser::to_string_pretty(&support, pretty)?)?;
+
+    println!(
+        "Found {} keys. Wrote the layout to {} and the entry for aura_support.ron to {}",
+        calibration.len(),
+        layout_file.display(),
+        support_file.display()
+    );
+    println!(
+        "All keys are the same size in the layout, so adjust the key shapes before installing it \
+         in /usr/share/rog-gui/layouts/"
+    );
+    Ok(())
+}
+
 fn handle_led_power1(power: &LedPowerCommand1) -> Result<(), Box<dyn std::error::Error>> {
     let aura = find_iface::<AuraProxyBlocking>("xyz.ljones.Aura")?;
     for aura in aura {
@@ -0,0 +1,35 @@
+use gumdrop::Options;
+
+#[derive(Options)]
+pub struct SceneCommand {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(command)]
+    pub command: Option<SceneSubCommand>,
+}
+
+#[derive(Options)]
+pub enum SceneSubCommand {
+    #[options(help = "list the saved scenes")]
+    List(SceneList),
+    #[options(help = "save the lighting of all devices as a scene")]
+    Save(SceneName),
+    #[options(help = "apply a saved scene to all devices")]
+    Apply(SceneName),
+    #[options(help = "delete a saved scene")]
+    Delete(SceneName),
+}
+
+#[derive(Options)]
+pub struct SceneList {
+    #[options(help = "print help message")]
+    pub help: bool,
+}
+
+#[derive(Options)]
+pub struct SceneName {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(free, help = "<name> of the scene, e.g: gaming")]
+    pub free: Vec<String>,
+}
@@ -0,0 +1,61 @@
+use gumdrop::Options;
+use rog_aura::Colour;
+use rog_dbus::zbus_schedules::{RuleBrightness, TimeOfDay};
+
+#[derive(Options)]
+pub struct ScheduleCommand {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(command)]
+    pub command: Option<ScheduleSubCommand>,
+}
+
+#[derive(Options)]
+pub enum ScheduleSubCommand {
+    #[options(help = "list the schedule rules, the rule in force is marked with *")]
+    List(ScheduleList),
+    #[options(help = "add a rule, or replace the rule of the same name")]
+    Set(ScheduleSet),
+    #[options(help = "delete a rule")]
+    Delete(ScheduleName),
+}
+
+#[derive(Options)]
+pub struct ScheduleList {
+    #[options(help = "print help message")]
+    pub help: bool,
+}
+
+#[derive(Options)]
+pub struct ScheduleSet {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(meta = "", help = "<HH:MM> the rule starts, e.g: 22:00")]
+    pub start: Option<TimeOfDay>,
+    #[options(
+        meta = "",
+        help = "<HH:MM> the rule ends, may be after midnight. The same as start for all day"
+    )]
+    pub end: Option<TimeOfDay>,
+    #[options(
+        meta = "",
+        help = "the highest priority wins when rules overlap, default 0"
+    )]
+    pub priority: i32,
+    #[options(meta = "", help = "a saved scene to apply")]
+    pub scene: Option<String>,
+    #[options(meta = "", help = "keyboard brightness <off, low, med, high>")]
+    pub brightness: Option<RuleBrightness>,
+    #[options(meta = "", help = "a static keyboard colour, e.g: ffd6aa")]
+    pub colour: Option<Colour>,
+    #[options(free, help = "<name> of the rule, e.g: night")]
+    pub free: Vec<String>,
+}
+
+#[derive(Options)]
+pub struct ScheduleName {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(free, help = "<name> of the rule, e.g: night")]
+    pub free: Vec<String>,
+}
@@ -104,12 +104,16 @@ impl StdConfigLoad for ConfigAura {}
 pub struct ConfigBase {
     /// Name of active aura config file in the user config directory
     pub active_aura: Option<String>,
+    /// Keyboard layout locale such as `UK` or `DE`. If not set it's found from
+    /// the system keymap.
+    pub layout_locale: Option<String>,
 }
 
 impl StdConfig for ConfigBase {
     fn new() -> Self {
         Self {
             active_aura: Some("aura-default".to_owned()),
+            layout_locale: None,
         }
     }
 
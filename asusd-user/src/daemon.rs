@@ -1,10 +1,14 @@
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 use asusd_user::config::*;
+use asusd_user::keys::KeyPresses;
+use asusd_user::metrics::Metrics;
 use config_traits::{StdConfig, StdConfigLoad};
 use rog_aura::aura_detection::LedSupportData;
-use rog_aura::keyboard::KeyLayout;
+use rog_aura::keyboard::{locale_from_xkb, KeyLayout};
+use rog_dbus::zbus_asusd::{AsusdProxyBlocking, DeviceInfo, DeviceKind};
 use rog_dbus::zbus_aura::AuraProxyBlocking;
 use smol::Executor;
 
@@ -13,6 +17,86 @@ const DATA_DIR: &str = "/usr/share/rog-gui/";
 #[cfg(feature = "local_data")]
 const DATA_DIR: &str = env!("CARGO_MANIFEST_DIR");
 const BOARD_NAME: &str = "/sys/class/dmi/id/board_name";
+/// Frames between samples of the values shown by `Heatmap` effects, about one
+/// second
+const METRICS_FRAMES: u32 = 30;
+
+/// The keyboard locale from the X11 keymap systemd-localed has, as set with
+/// `localectl set-x11-keymap`
+fn system_locale(conn: &zbus::blocking::Connection) -> Option<String> {
+    zbus::blocking::Proxy::new(
+        conn,
+        "org.freedesktop.locale1",
+        "/org/freedesktop/locale1",
+        "org.freedesktop.locale1",
+    )
+    .and_then(|proxy| proxy.get_property::<String>("X11Layout"))
+    .map_err(|e| println!("Could not get the keymap from localed: {e}"))
+    .ok()
+    .filter(|layout| !layout.is_empty())
+    .map(|layout| locale_from_xkb(&layout))
+}
+
+type SharedAura = Arc<Mutex<Option<AuraProxyBlocking<'static>>>>;
+
+fn aura_proxy(
+    conn: &zbus::blocking::Connection,
+    device: &DeviceInfo,
+) -> Option<AuraProxyBlocking<'static>> {
+    AuraProxyBlocking::builder(conn)
+        .path(device.dbus_path.clone())
+        .and_then(|b| b.build())
+        .map_err(|e| println!("Could not use Aura device at {}: {e}", device.dbus_path))
+        .ok()
+}
+
+/// Track the keyboard as it is added or removed, using the first Aura device
+/// asusd has
+fn watch_aura_devices(
+    conn: &zbus::blocking::Connection,
+    aura: SharedAura,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let asusd = AsusdProxyBlocking::new(conn)?;
+    if let Some(device) = asusd
+        .devices()?
+        .iter()
+        .find(|dev| dev.kind == DeviceKind::Aura)
+    {
+        *aura.lock().unwrap() = aura_proxy(conn, device);
+    }
+
+    let added = asusd.receive_device_added()?;
+    let conn_added = conn.clone();
+    let aura_added = aura.clone();
+    std::thread::spawn(move || {
+        for signal in added {
+            if let Ok(args) = signal.args() {
+                let mut aura = aura_added.lock().unwrap();
+                if args.device.kind == DeviceKind::Aura && aura.is_none() {
+                    println!("Aura device added at {}", args.device.dbus_path);
+                    *aura = aura_proxy(&conn_added, &args.device);
+                }
+            }
+        }
+    });
+
+    let removed = asusd.receive_device_removed()?;
+    std::thread::spawn(move || {
+        for signal in removed {
+            if let Ok(args) = signal.args() {
+                let mut aura = aura.lock().unwrap();
+                if aura
+                    .as_ref()
+                    .is_some_and(|proxy| *proxy.inner().path() == *args.device.dbus_path)
+                {
+                    println!("Aura device at {} removed", args.device.dbus_path);
+                    *aura = None;
+                }
+            }
+        }
+    });
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut logger = env_logger::Builder::new();
@@ -32,26 +116,65 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let executor = Executor::new();
 
     // if supported.keyboard_led.per_key_led_mode {
-    if let Some(cfg) = config.active_aura {
+    if let Some(cfg) = config.active_aura.clone() {
         let mut aura_config = ConfigAura::new().set_name(cfg).load();
+        aura_config
+            .aura
+            .load_image()
+            .unwrap_or_else(|e| println!("Could not load the keyboard image: {e}"));
         // let baord_name = std::fs::read_to_string(BOARD_NAME)?;
 
         let led_support = LedSupportData::get_data("");
 
-        let layout = KeyLayout::find_layout(led_support, PathBuf::from(DATA_DIR))
-            .map_err(|e| {
-                println!("{BOARD_NAME}, {e}");
-            })
-            .unwrap_or_else(|_| KeyLayout::default_layout());
+        let locale = config
+            .layout_locale
+            .clone()
+            .or_else(|| system_locale(&conn))
+            .unwrap_or_default();
+        let layout =
+            KeyLayout::find_layout_for_locale(led_support, PathBuf::from(DATA_DIR), &locale)
+                .map_err(|e| {
+                    println!("{BOARD_NAME}, {e}");
+                })
+                .unwrap_or_else(|_| KeyLayout::default_layout());
 
-        let aura_proxy_blocking = AuraProxyBlocking::new(&conn).unwrap();
+        let aura: SharedAura = Arc::new(Mutex::new(None));
+        watch_aura_devices(&conn, aura.clone())?;
+        // Only read the CPU load and sensors if a `Heatmap` shows them
+        let mut metrics = aura_config.aura.uses_metrics().then(Metrics::new);
+        // Only read the keyboard if an effect reacts to it
+        let keys = if aura_config.aura.reacts_to_keys() {
+            KeyPresses::new()
+                .map_err(|e| println!("Key presses are unavailable: {e}"))
+                .ok()
+        } else {
+            None
+        };
         executor
             .spawn(async move {
+                let mut frame = 0u32;
                 loop {
-                    aura_config.aura.next_state(&layout);
-                    let packets = aura_config.aura.create_packets();
-
-                    aura_proxy_blocking.direct_addressing_raw(packets).unwrap();
+                    for code in keys.iter().flat_map(KeyPresses::pressed) {
+                        aura_config
+                            .aura
+                            .key_pressed(&layout.leds_for_evdev_key(code), &layout);
+                    }
+                    if let Some(proxy) = aura.lock().unwrap().as_ref() {
+                        if let Some(metrics) =
+                            metrics.as_mut().filter(|_| frame % METRICS_FRAMES == 0)
+                        {
+                            metrics.sample();
+                            aura_config
+                                .aura
+                                .update_metrics(|source| metrics.value(source));
+                        }
+                        frame = frame.wrapping_add(1);
+                        aura_config.aura.next_state(&layout);
+                        let packets = aura_config.aura.create_packets();
+                        proxy
+                            .direct_addressing_raw(packets)
+                            .unwrap_or_else(|e| println!("Direct addressing failed: {e}"));
+                    }
                     std::thread::sleep(std::time::Duration::from_millis(33));
                 }
             })
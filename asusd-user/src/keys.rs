@@ -0,0 +1,97 @@
+//! Key presses on the laptop keyboard, for the reactive effects of an Aura
+//! config.
+//!
+//! Each evdev node of the laptop keyboard is read on its own thread. Reading
+//! `/dev/input` needs the user to be in the `input` group.
+
+use std::ffi::c_long;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::error::Error;
+
+/// The size of a `struct input_event`, a `timeval` then type, code and value
+const EVENT_SIZE: usize = 2 * std::mem::size_of::<c_long>() + 8;
+const EV_KEY: u16 = 0x01;
+/// ASUS USB vendor ID, used by the N-KEY keyboard
+const ASUS_VENDOR: &str = "0b05";
+
+pub struct KeyPresses {
+    presses: Receiver<u16>,
+}
+
+impl KeyPresses {
+    /// Start reading the laptop keyboard. External keyboards are ignored.
+    pub fn new() -> Result<Self, Error> {
+        let nodes = laptop_keyboards()?;
+        if nodes.is_empty() {
+            return Err(Error::NoKeyboard);
+        }
+        let (send, presses) = channel();
+        let mut opened = 0;
+        for node in nodes {
+            match File::open(&node) {
+                Ok(file) => {
+                    println!("Reading key presses from {node:?}");
+                    let send = send.clone();
+                    std::thread::spawn(move || read_presses(file, send));
+                    opened += 1;
+                }
+                Err(e) => println!("Could not open {node:?}: {e}"),
+            }
+        }
+        if opened == 0 {
+            return Err(Error::NoKeyboard);
+        }
+        Ok(Self { presses })
+    }
+
+    /// The evdev key codes pressed since the last call, in order
+    pub fn pressed(&self) -> impl Iterator<Item = u16> + '_ {
+        self.presses.try_iter()
+    }
+}
+
+/// The evdev nodes of the built in keyboard, either the ASUS USB keyboard or
+/// the PS/2 keyboard of older laptops
+fn laptop_keyboards() -> Result<Vec<PathBuf>, Error> {
+    let mut enumerator = udev::Enumerator::new()?;
+    enumerator.match_subsystem("input")?;
+    enumerator.match_property("ID_INPUT_KEYBOARD", "1")?;
+    Ok(enumerator
+        .scan_devices()?
+        .filter(|device| device.sysname().to_string_lossy().starts_with("event"))
+        .filter(|device| {
+            device
+                .property_value("ID_VENDOR_ID")
+                .is_some_and(|v| v == ASUS_VENDOR)
+                || device
+                    .property_value("ID_PATH")
+                    .is_some_and(|p| p.to_string_lossy().contains("i8042"))
+        })
+        .filter_map(|device| device.devnode().map(|node| node.to_path_buf()))
+        .collect())
+}
+
+fn read_presses(mut file: File, send: Sender<u16>) {
+    let mut event = [0; EVENT_SIZE];
+    while file.read_exact(&mut event).is_ok() {
+        let tail = &event[EVENT_SIZE - 8..];
+        let kind = u16::from_ne_bytes([
+            tail[0], tail[1],
+        ]);
+        let code = u16::from_ne_bytes([
+            tail[2], tail[3],
+        ]);
+        let value = i32::from_ne_bytes([
+            tail[4], tail[5], tail[6], tail[7],
+        ]);
+        // A value of 1 is a press, 0 a release, and 2 an autorepeat
+        if kind == EV_KEY && value == 1 && send.send(code).is_err() {
+            return;
+        }
+    }
+    println!("Stopped reading key presses");
+}
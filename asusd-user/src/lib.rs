@@ -1,4 +1,6 @@
 pub mod config;
+pub mod keys;
+pub mod metrics;
 
 pub mod error;
 
@@ -0,0 +1,103 @@
+//! Live values for the `Heatmap` effects of an Aura config
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use rog_aura::effects::MetricSource;
+use rog_platform::metrics::{CpuLoad, Hwmon};
+
+/// How long to wait before looking for a missing sensor again, as its driver
+/// may load after asusd-user starts
+const HWMON_RETRY: Duration = Duration::from_secs(30);
+
+enum Sensor {
+    Found(Hwmon),
+    /// Not found as of this time
+    Missing(Instant),
+}
+
+pub struct Metrics {
+    root: PathBuf,
+    cpu: Option<CpuLoad>,
+    /// All cores then each core, from 0.0 to 1.0
+    cpu_load: Vec<f32>,
+    /// Sensors by name
+    hwmon: BTreeMap<String, Sensor>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::with_root(Path::new(rog_platform::DEFAULT_ROOT))
+    }
+
+    /// As `new()`, but read `/proc` and `/sys` under `root`
+    pub fn with_root(root: &Path) -> Self {
+        let cpu = CpuLoad::with_root(root)
+            .map_err(|e| println!("CPU load is unavailable: {e}"))
+            .ok();
+        Self {
+            root: root.to_path_buf(),
+            cpu,
+            cpu_load: Vec::new(),
+            hwmon: BTreeMap::new(),
+        }
+    }
+
+    /// Take the CPU load since the last sample
+    pub fn sample(&mut self) {
+        if let Some(cpu) = self.cpu.as_mut() {
+            match cpu.sample() {
+                Ok(load) => self.cpu_load = load,
+                Err(e) => println!("Could not read the CPU load: {e}"),
+            }
+        }
+    }
+
+    /// The value of `source`, CPU load is in percent as of the last sample,
+    /// temperatures are read now
+    pub fn value(&mut self, source: &MetricSource) -> Option<f32> {
+        match source {
+            MetricSource::CpuTotal => self.cpu_load.first().map(|load| load * 100.0),
+            MetricSource::CpuCore(core) => self.cpu_load.get(core + 1).map(|load| load * 100.0),
+            MetricSource::Temperature(name) => self.temperature(name),
+        }
+    }
+
+    /// Read the sensor `name`. A sensor that is missing is looked for again
+    /// after `HWMON_RETRY`, and one that fails to read is looked for again
+    /// next time, as its hwmon number can change if the driver reloads.
+    fn temperature(&mut self, name: &str) -> Option<f32> {
+        let find = match self.hwmon.get(name) {
+            Some(Sensor::Found(_)) => false,
+            Some(Sensor::Missing(at)) => at.elapsed() >= HWMON_RETRY,
+            None => true,
+        };
+        if find {
+            let sensor = match Hwmon::find_with_root(&self.root, name) {
+                Ok(hwmon) => Sensor::Found(hwmon),
+                Err(e) => {
+                    if !self.hwmon.contains_key(name) {
+                        println!("Temperature is unavailable: {e}");
+                    }
+                    Sensor::Missing(Instant::now())
+                }
+            };
+            self.hwmon.insert(name.to_owned(), sensor);
+        }
+        let Some(Sensor::Found(hwmon)) = self.hwmon.get(name) else {
+            return None;
+        };
+        let temperature = hwmon.temperature().ok();
+        if temperature.is_none() {
+            self.hwmon.remove(name);
+        }
+        temperature
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
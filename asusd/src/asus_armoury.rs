@@ -0,0 +1,293 @@
+//! One `xyz.ljones.AsusArmoury` object is served for each attribute found in
+//! `/sys/class/firmware-attributes/asus-armoury/attributes/`, at
+//! `/xyz/ljones/asus_armoury/<name>`.
+
+use std::sync::Arc;
+
+use config_traits::StdConfig;
+use futures_util::lock::Mutex;
+use log::{debug, error, info, warn};
+use rog_platform::asus_armoury::{AttrValue, Attribute, FirmwareAttribute, FirmwareAttributes};
+use zbus::fdo::Error as FdoErr;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+use zbus::{interface, Connection};
+
+use crate::config::Config;
+use crate::error::RogError;
+use crate::{Reloadable, ASUS_ZBUS_PATH};
+
+const MOD_NAME: &str = "asus_armoury";
+
+fn dbus_path_for_attr(name: &str) -> OwnedObjectPath {
+    ObjectPath::from_str_unchecked(&format!("{ASUS_ZBUS_PATH}/{MOD_NAME}/{name}")).into()
+}
+
+/// `-1` is used on dbus for values the attribute doesn't have
+fn attr_value_to_i32(value: &AttrValue) -> i32 {
+    match value {
+        AttrValue::Integer(v) => *v,
+        _ => -1,
+    }
+}
+
+#[derive(Clone)]
+pub struct AsusArmouryAttribute {
+    attr: Attribute,
+    config: Arc<Mutex<Config>>,
+}
+
+impl AsusArmouryAttribute {
+    pub fn new(attr: Attribute, config: Arc<Mutex<Config>>) -> Self {
+        Self { attr, config }
+    }
+
+    pub fn attribute_name(&self) -> FirmwareAttribute {
+        FirmwareAttribute::from(self.attr.name())
+    }
+
+    /// Validate and write a new value, storing it in the config to restore
+    /// on boot
+    fn set_value(&self, config: &mut Config, value: i32) -> Result<(), RogError> {
+        self.attr.check_value(value)?;
+        self.attr.set_current_value(&AttrValue::Integer(value))?;
+        info!("Firmware attribute {} set to {value}", self.attr.name());
+        match self.attribute_name() {
+            FirmwareAttribute::None => {
+                warn!("{} is not a known attribute, not saved", self.attr.name())
+            }
+            name => {
+                config.armoury_settings.insert(name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Restore the firmware default, and stop restoring a saved value on boot
+    fn restore_default_value(&self, config: &mut Config) -> Result<(), RogError> {
+        self.attr.restore_default()?;
+        config.armoury_settings.remove(&self.attribute_name());
+        Ok(())
+    }
+
+    /// Add to the object server, and watch for the value being changed by
+    /// something other than asusd, e.g, the firmware itself
+    pub async fn start_tasks(mut self, connection: &Connection) -> Result<(), RogError> {
+        let path = dbus_path_for_attr(self.attr.name());
+        self.reload()
+            .await
+            .unwrap_or_else(|err| warn!("{}: {}", self.attr.name(), err));
+
+        match self.attr.get_watcher("current_value") {
+            Ok(watch) => {
+                let ctrl = self.clone();
+                let signal_ctxt = SignalEmitter::new(connection, path.clone())?.into_owned();
+                tokio::spawn(async move {
+                    use futures_util::StreamExt;
+
+                    let mut buffer = [0; 32];
+                    if let Ok(stream) = watch.into_event_stream(&mut buffer) {
+                        stream
+                            .for_each(|_| async {
+                                debug!("{} changed", ctrl.attr.name());
+                                ctrl.current_value_changed(&signal_ctxt).await.ok();
+                            })
+                            .await;
+                    }
+                });
+            }
+            Err(e) => info!("inotify watch failed for {}: {e}", self.attr.name()),
+        }
+
+        connection
+            .object_server()
+            .at(path.clone(), self)
+            .await
+            .map_err(|e| error!("Couldn't add server at path: {path}, {e:?}"))
+            .ok();
+        Ok(())
+    }
+}
+
+#[interface(name = "xyz.ljones.AsusArmoury")]
+impl AsusArmouryAttribute {
+    /// A list of the properties this attribute actually uses
+    #[zbus(property)]
+    async fn available_attrs(&self) -> Vec<String> {
+        let mut attrs = vec!["current_value".to_string()];
+        for (name, value) in [
+            ("default_value", self.attr.default_value()),
+            ("min_value", self.attr.min_value()),
+            ("max_value", self.attr.max_value()),
+            ("scalar_increment", self.attr.scalar_increment()),
+            ("possible_values", self.attr.possible_values()),
+        ] {
+            if *value != AttrValue::None {
+                attrs.push(name.to_string());
+            }
+        }
+        attrs
+    }
+
+    #[zbus(property)]
+    async fn name(&self) -> FirmwareAttribute {
+        self.attribute_name()
+    }
+
+    #[zbus(property)]
+    async fn current_value(&self) -> Result<i32, FdoErr> {
+        match self.attr.current_value().map_err(RogError::from)? {
+            AttrValue::Integer(v) => Ok(v),
+            _ => Err(FdoErr::NotSupported(format!(
+                "{} is not an integer attribute",
+                self.attr.name()
+            ))),
+        }
+    }
+
+    /// Set the value, this is checked against the attribute's bounds first
+    #[zbus(property)]
+    async fn set_current_value(&self, value: i32) -> Result<(), FdoErr> {
+        let mut config = self.config.lock().await;
+        self.set_value(&mut config, value)?;
+        config.write();
+        Ok(())
+    }
+
+    #[zbus(property)]
+    async fn default_value(&self) -> i32 {
+        attr_value_to_i32(self.attr.default_value())
+    }
+
+    #[zbus(property)]
+    async fn min_value(&self) -> i32 {
+        attr_value_to_i32(self.attr.min_value())
+    }
+
+    #[zbus(property)]
+    async fn max_value(&self) -> i32 {
+        attr_value_to_i32(self.attr.max_value())
+    }
+
+    #[zbus(property)]
+    async fn scalar_increment(&self) -> i32 {
+        attr_value_to_i32(self.attr.scalar_increment())
+    }
+
+    #[zbus(property)]
+    async fn possible_values(&self) -> Vec<i32> {
+        match self.attr.possible_values() {
+            AttrValue::EnumInt(values) => values.clone(),
+            AttrValue::Integer(v) => vec![*v],
+            _ => Vec::new(),
+        }
+    }
+
+    async fn restore_default(
+        &self,
+        #[zbus(signal_emitter)] ctxt: SignalEmitter<'_>,
+    ) -> Result<(), FdoErr> {
+        let mut config = self.config.lock().await;
+        self.restore_default_value(&mut config)?;
+        config.write();
+        drop(config);
+        self.current_value_changed(&ctxt).await?;
+        Ok(())
+    }
+}
+
+impl Reloadable for AsusArmouryAttribute {
+    /// Re-apply the saved value, if there is one
+    async fn reload(&mut self) -> Result<(), RogError> {
+        let config = self.config.lock().await;
+        if let Some(value) = config.armoury_settings.get(&self.attribute_name()) {
+            if self.attr.current_value()? != AttrValue::Integer(*value) {
+                self.attr.check_value(*value)?;
+                self.attr.set_current_value(&AttrValue::Integer(*value))?;
+                info!("Restored {} to {value}", self.attr.name());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serve every firmware attribute found
+pub async fn start_attributes_zbus(
+    connection: &Connection,
+    attributes: FirmwareAttributes,
+    config: Arc<Mutex<Config>>,
+) -> Result<(), RogError> {
+    for attr in attributes.attributes() {
+        AsusArmouryAttribute::new(attr.clone(), config.clone())
+            .start_tasks(connection)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_util::lock::Mutex;
+    use rog_platform::asus_armoury::{AttrValue, FirmwareAttribute, FirmwareAttributes};
+    use rog_platform::fixture::Fixture;
+
+    use super::AsusArmouryAttribute;
+    use crate::config::Config;
+    use crate::Reloadable;
+
+    #[tokio::test]
+    async fn set_validate_and_restore() {
+        let fixture = Fixture::new("asusd-armoury");
+        for (attr, file, value) in [
+            ("boot_sound", "current_value", "0"),
+            ("boot_sound", "default_value", "0"),
+            ("boot_sound", "possible_values", "0;1"),
+            ("ppt_pl1_spl", "current_value", "80"),
+            ("ppt_pl1_spl", "min_value", "15"),
+            ("ppt_pl1_spl", "max_value", "120"),
+        ] {
+            fixture.attr(
+                &format!("sys/class/firmware-attributes/asus-armoury/attributes/{attr}/{file}"),
+                value,
+            );
+        }
+        let attrs = FirmwareAttributes::with_root(fixture.root());
+        let config = Arc::new(Mutex::new(Config::default()));
+        let mut config_data = Config::default();
+
+        let boot_sound = AsusArmouryAttribute::new(attrs.boot_sound().unwrap().clone(), config);
+        assert!(boot_sound.set_value(&mut config_data, 2).is_err());
+        boot_sound.set_value(&mut config_data, 1).unwrap();
+        assert_eq!(
+            config_data
+                .armoury_settings
+                .get(&FirmwareAttribute::BootSound),
+            Some(&1)
+        );
+
+        // The saved value is re-applied on reload
+        let saved = Arc::new(Mutex::new(config_data));
+        let mut boot_sound = AsusArmouryAttribute::new(boot_sound.attr.clone(), saved.clone());
+        boot_sound.attr.restore_default().unwrap();
+        boot_sound.reload().await.unwrap();
+        assert_eq!(
+            boot_sound.attr.current_value().unwrap(),
+            AttrValue::Integer(1)
+        );
+
+        boot_sound
+            .restore_default_value(&mut *saved.lock().await)
+            .unwrap();
+        assert_eq!(
+            boot_sound.attr.current_value().unwrap(),
+            AttrValue::Integer(0)
+        );
+        assert!(saved.lock().await.armoury_settings.is_empty());
+
+        let ppt = AsusArmouryAttribute::new(attrs.ppt_pl1_spl().unwrap().clone(), saved.clone());
+        assert!(ppt.set_value(&mut *saved.lock().await, 121).is_err());
+        assert_eq!(ppt.attr.current_value().unwrap(), AttrValue::Integer(80));
+    }
+}
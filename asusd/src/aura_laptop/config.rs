@@ -3,14 +3,47 @@ use std::collections::BTreeMap;
 use config_traits::{StdConfig, StdConfigLoad};
 use log::{debug, info, warn};
 use rog_aura::aura_detection::LedSupportData;
-use rog_aura::keyboard::LaptopAuraPower;
+use rog_aura::keyboard::{AuraLaptopUsbPackets, LaptopAuraPower};
 use rog_aura::{
-    AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, Direction, LedBrightness, Speed, GRADIENT,
+    AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, Colour, Direction, LedBrightness, Speed,
+    GRADIENT,
 };
 use serde::{Deserialize, Serialize};
 
+use super::idle::IdleLevel;
 use crate::error::RogError;
 
+/// Settings used on AC or on battery in place of the normal ones. Anything
+/// unset uses the normal setting.
+#[derive(Deserialize, Serialize, Default, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct AuraPowerPolicy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<LedBrightness>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<AuraModeNum>,
+    /// Turn the keyboard, lightbar and logo off
+    pub off: bool,
+    /// Seconds without keyboard input before dimming to `Low`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dim_after: Option<u32>,
+    /// Seconds without keyboard input before turning the LEDs off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub off_after: Option<u32>,
+}
+
+/// When to show the battery level on the function row, or the lightbar of a
+/// zoned keyboard
+#[derive(Deserialize, Serialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryGauge {
+    #[default]
+    Off,
+    /// For a few seconds when AC is plugged or unplugged
+    OnPowerChange,
+    /// In place of the mode
+    Always,
+}
+
 #[derive(Deserialize, Serialize, Default, Debug, Clone)]
 // #[serde(default)]
 pub struct AuraConfig {
@@ -28,8 +61,36 @@ pub struct AuraConfig {
     pub multizone: Option<BTreeMap<AuraModeNum, Vec<AuraEffect>>>,
     pub multizone_on: bool,
     pub enabled: LaptopAuraPower,
+    #[serde(default)]
+    pub ac_policy: AuraPowerPolicy,
+    #[serde(default)]
+    pub battery_policy: AuraPowerPolicy,
+    /// Turn the keyboard, lightbar and logo off when the lid is closed while
+    /// running, such as when using an external monitor
+    #[serde(default)]
+    pub off_on_lid_closed: bool,
+    /// Milliseconds to crossfade to a new mode on per-key and zoned keyboards,
+    /// `0` to switch straight away
+    #[serde(default)]
+    pub transition_ms: u32,
+    /// Per-key and zoned keyboards only. A change to `Always` is picked up
+    /// when asusd restarts.
+    #[serde(default)]
+    pub battery_gauge: BatteryGauge,
     #[serde(skip)]
     pub per_key_mode_active: bool,
+    /// The last per-key frame written, restored after a flash
+    #[serde(skip)]
+    pub per_key_frame: Option<AuraLaptopUsbPackets>,
+    /// The colour of the last mode written, where a crossfade starts from
+    #[serde(skip)]
+    pub shown_colour: Option<Colour>,
+    #[serde(skip)]
+    pub on_battery: bool,
+    #[serde(skip)]
+    pub lid_closed: bool,
+    #[serde(skip)]
+    pub idle: IdleLevel,
 }
 
 impl StdConfig for AuraConfig {
@@ -55,13 +116,18 @@ impl StdConfigLoad for AuraConfig {}
 impl AuraConfig {
     /// Detect the keyboard type and load from default DB if data available
     pub fn new(prod_id: &str) -> Self {
+        Self::with_support_data(prod_id, LedSupportData::get_data(prod_id))
+    }
+
+    /// As `new()`, with the support data given instead of looked up for this
+    /// laptop
+    pub fn with_support_data(prod_id: &str, support_data: LedSupportData) -> Self {
         info!("Setting up AuraConfig for {prod_id:?}");
         // create a default config here
         let device_type = AuraDeviceType::from(prod_id);
         if device_type == AuraDeviceType::Unknown {
             warn!("idProduct:{prod_id:?} is unknown");
         }
-        let support_data = LedSupportData::get_data(prod_id);
         let enabled = LaptopAuraPower::new(device_type, &support_data);
         let mut config = AuraConfig {
             led_type: device_type,
@@ -74,7 +140,17 @@ impl AuraConfig {
             multizone: None,
             multizone_on: false,
             enabled,
+            ac_policy: AuraPowerPolicy::default(),
+            battery_policy: AuraPowerPolicy::default(),
+            off_on_lid_closed: false,
+            transition_ms: 0,
+            battery_gauge: BatteryGauge::Off,
             per_key_mode_active: false,
+            per_key_frame: None,
+            shown_colour: None,
+            on_battery: false,
+            lid_closed: false,
+            idle: IdleLevel::Active,
         };
 
         for n in &config.support_data.basic_modes {
@@ -112,7 +188,7 @@ impl AuraConfig {
     /// Multipurpose, will accept `AuraEffect` with zones and put in the correct
     /// store.
     pub fn set_builtin(&mut self, effect: AuraEffect) {
-        self.current_mode = effect.mode;
+        self.set_mode(effect.mode);
         if effect.zone() == AuraZone::None {
             self.builtins.insert(*effect.mode(), effect);
             self.multizone_on = false;
@@ -138,6 +214,68 @@ impl AuraConfig {
         }
     }
 
+    /// Set the current mode. If the policy for the current power source sets a
+    /// mode then that is changed too, so the new mode is what's shown.
+    pub fn set_mode(&mut self, mode: AuraModeNum) {
+        self.current_mode = mode;
+        if let Some(policy_mode) = self.power_policy_mut().mode.as_mut() {
+            *policy_mode = mode;
+        }
+    }
+
+    /// Set the brightness, or the policy brightness if the policy for the
+    /// current power source sets one.
+    pub fn set_brightness(&mut self, brightness: LedBrightness) {
+        if let Some(policy) = self.power_policy_mut().brightness.as_mut() {
+            *policy = brightness;
+        } else {
+            self.brightness = brightness;
+        }
+    }
+
+    /// The policy for the current power source
+    pub fn power_policy(&self) -> &AuraPowerPolicy {
+        if self.on_battery {
+            &self.battery_policy
+        } else {
+            &self.ac_policy
+        }
+    }
+
+    fn power_policy_mut(&mut self) -> &mut AuraPowerPolicy {
+        if self.on_battery {
+            &mut self.battery_policy
+        } else {
+            &mut self.ac_policy
+        }
+    }
+
+    /// If the LEDs should be off for the current power source, lid and idle
+    /// state
+    pub fn policy_off(&self) -> bool {
+        self.power_policy().off
+            || (self.lid_closed && self.off_on_lid_closed)
+            || self.idle == IdleLevel::Off
+    }
+
+    /// The brightness to use for the current power source, lid and idle state
+    pub fn policy_brightness(&self) -> LedBrightness {
+        if self.policy_off() {
+            return LedBrightness::Off;
+        }
+        self.idle
+            .brightness(self.power_policy().brightness.unwrap_or(self.brightness))
+    }
+
+    /// The mode to use for the current power source. A policy mode that isn't
+    /// supported is ignored.
+    pub fn policy_mode(&self) -> AuraModeNum {
+        self.power_policy()
+            .mode
+            .filter(|mode| self.builtins.contains_key(mode))
+            .unwrap_or(self.current_mode)
+    }
+
     pub fn get_multizone(&self, aura_type: AuraModeNum) -> Option<&[AuraEffect]> {
         if let Some(multi) = &self.multizone {
             return multi.get(&aura_type).map(|v| v.as_slice());
@@ -229,18 +367,27 @@ impl AuraConfig {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use rog_aura::aura_detection::LedSupportData;
     use rog_aura::keyboard::AuraPowerState;
     use rog_aura::{
         AuraEffect, AuraModeNum, AuraZone, Colour, Direction, LedBrightness, PowerZones, Speed,
     };
 
-    use super::AuraConfig;
+    use super::{AuraConfig, AuraPowerPolicy};
+    use crate::aura_laptop::idle::IdleLevel;
+
+    /// A 19b6 keyboard with the generic support data, whatever laptop the
+    /// tests run on
+    pub(crate) fn config_19b6() -> AuraConfig {
+        let mut data = LedSupportData::default();
+        data.power_zones.push(PowerZones::Keyboard);
+        AuraConfig::with_support_data("19b6", data)
+    }
 
     #[test]
     fn set_multizone_4key_config() {
-        std::env::set_var("BOARD_NAME", "");
-        let mut config = AuraConfig::new("19b6");
+        let mut config = config_19b6();
 
         let effect = AuraEffect {
             colour1: Colour {
@@ -318,8 +465,7 @@ mod tests {
 
     #[test]
     fn set_multizone_multimode_config() {
-        std::env::set_var("BOARD_NAME", "");
-        let mut config = AuraConfig::new("19b6");
+        let mut config = config_19b6();
 
         let effect = AuraEffect {
             zone: AuraZone::Key1,
@@ -364,6 +510,52 @@ mod tests {
         assert_eq!(sta.len(), 1);
     }
 
+    #[test]
+    fn power_and_lid_policy() {
+        let mut config = config_19b6();
+        config.builtins.insert(
+            AuraModeNum::Breathe,
+            AuraEffect::default_with_mode(AuraModeNum::Breathe),
+        );
+        config.battery_policy = AuraPowerPolicy {
+            brightness: Some(LedBrightness::Low),
+            mode: Some(AuraModeNum::Breathe),
+            ..Default::default()
+        };
+        assert_eq!(config.policy_brightness(), LedBrightness::Med);
+        assert_eq!(config.policy_mode(), AuraModeNum::Static);
+
+        config.on_battery = true;
+        assert_eq!(config.policy_brightness(), LedBrightness::Low);
+        assert_eq!(config.policy_mode(), AuraModeNum::Breathe);
+        // Changes while a policy is active change the policy
+        config.set_brightness(LedBrightness::High);
+        config.set_mode(AuraModeNum::Static);
+        assert_eq!(config.brightness, LedBrightness::Med);
+        assert_eq!(config.battery_policy.brightness, Some(LedBrightness::High));
+        assert_eq!(config.policy_mode(), AuraModeNum::Static);
+        // An unsupported mode is ignored
+        config.battery_policy.mode = Some(AuraModeNum::Pulse);
+        config.builtins.remove(&AuraModeNum::Pulse);
+        assert_eq!(config.policy_mode(), AuraModeNum::Static);
+
+        config.lid_closed = true;
+        assert!(!config.policy_off());
+        config.off_on_lid_closed = true;
+        assert!(config.policy_off());
+        assert_eq!(config.policy_brightness(), LedBrightness::Off);
+        config.lid_closed = false;
+        config.battery_policy.off = true;
+        assert!(config.policy_off());
+        config.on_battery = false;
+        assert!(!config.policy_off());
+
+        config.idle = IdleLevel::Dimmed;
+        assert_eq!(config.policy_brightness(), LedBrightness::Low);
+        config.idle = IdleLevel::Off;
+        assert!(config.policy_off());
+    }
+
     #[test]
     #[ignore] // Requires aura_support.ron file to be available
     fn verify_0x1866_g531i() {
@@ -0,0 +1,75 @@
+//! Software crossfades between builtin modes.
+//!
+//! The fade is drawn through direct addressing on per-key and zoned keyboards,
+//! after which the new builtin mode is written and the hardware takes over.
+
+use std::time::Duration;
+
+use rog_aura::keyboard::{AdvancedAuraType, AuraLaptopUsbPackets, LedCode, LedUsbPackets};
+use rog_aura::Colour;
+
+/// Time between frames, about 30 per second
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+/// The longest transition allowed. The Aura config stays locked while a
+/// transition runs.
+pub const MAX_TRANSITION_MS: u32 = 2000;
+
+/// The packets setting every LED to one colour, or `None` if the keyboard has
+/// no direct addressing
+pub fn frame(advanced: &AdvancedAuraType, colour: Colour) -> Option<AuraLaptopUsbPackets> {
+    let (mut packets, leds) = match advanced {
+        AdvancedAuraType::None => return None,
+        AdvancedAuraType::Zoned(zones) => (LedUsbPackets::new_zoned(true), zones.as_slice()),
+        AdvancedAuraType::PerKey => (LedUsbPackets::new_per_key(), LedCode::ALL.as_slice()),
+    };
+    for led in leds.iter().filter(|led| !led.is_placeholder()) {
+        packets.set(*led, colour.r, colour.g, colour.b);
+    }
+    Some(packets.into())
+}
+
+/// The frames of a fade lasting `duration_ms`, the last frame is `to`. Empty
+/// if the keyboard has no direct addressing.
+pub fn frames(
+    advanced: &AdvancedAuraType,
+    from: Colour,
+    to: Colour,
+    duration_ms: u32,
+) -> Vec<AuraLaptopUsbPackets> {
+    let steps = (duration_ms / FRAME_INTERVAL.as_millis() as u32).max(1);
+    (1..=steps)
+        .filter_map(|step| frame(advanced, from.blend(to, step as f32 / steps as f32)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rog_aura::keyboard::{AdvancedAuraType, LedCode};
+    use rog_aura::Colour;
+
+    use super::{frame, frames};
+
+    #[test]
+    fn fade_frames() {
+        let black = Colour { r: 0, g: 0, b: 0 };
+        let red = Colour { r: 200, g: 0, b: 0 };
+        assert!(frames(&AdvancedAuraType::None, black, red, 500).is_empty());
+
+        let per_key = frames(&AdvancedAuraType::PerKey, black, red, 100);
+        assert_eq!(per_key.len(), 3);
+        assert_eq!(
+            per_key.last(),
+            frame(&AdvancedAuraType::PerKey, red).as_ref()
+        );
+        assert_ne!(per_key[0], per_key[1]);
+
+        let zoned = AdvancedAuraType::Zoned(vec![
+            LedCode::ZonedKbLeft,
+            LedCode::ZonedKbRight,
+        ]);
+        let fade = frames(&zoned, black, red, 0);
+        assert_eq!(fade.len(), 1);
+        assert_eq!(fade[0].len(), 1);
+        assert_eq!(Some(&fade[0]), frame(&zoned, red).as_ref());
+    }
+}
@@ -0,0 +1,252 @@
+//! Dimming and turning off the keyboard LEDs after a time without input.
+//!
+//! Input is detected by reading the evdev nodes of each keyboard. The events
+//! themselves are discarded, only the time of the last one is kept. Keyboards
+//! plugged in later are found through udev.
+
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures_util::future::select_all;
+use log::{debug, warn};
+use rog_aura::LedBrightness;
+use rog_platform::error::PlatformError;
+use tokio::io::unix::AsyncFd;
+use udev::EventType;
+
+use crate::error::RogError;
+use crate::hotplug::UdevMonitor;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IdleLevel {
+    #[default]
+    Active,
+    Dimmed,
+    Off,
+}
+
+impl IdleLevel {
+    /// The level after `idle` time without input. A threshold of `None`
+    /// disables that level.
+    pub fn after(idle: Duration, dim_after: Option<u32>, off_after: Option<u32>) -> Self {
+        let passed =
+            |secs: Option<u32>| secs.is_some_and(|s| idle >= Duration::from_secs(s.into()));
+        if passed(off_after) {
+            Self::Off
+        } else if passed(dim_after) {
+            Self::Dimmed
+        } else {
+            Self::Active
+        }
+    }
+
+    /// How long after the last input the level will next change, if it will
+    pub fn next_change(
+        idle: Duration,
+        dim_after: Option<u32>,
+        off_after: Option<u32>,
+    ) -> Option<Duration> {
+        [
+            dim_after, off_after,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|s| Duration::from_secs(s.into()))
+        .filter(|d| *d > idle)
+        .min()
+    }
+
+    /// The brightness to use at this level. Dimming drops to `Low` unless the
+    /// brightness is already lower.
+    pub fn brightness(self, brightness: LedBrightness) -> LedBrightness {
+        match self {
+            Self::Active => brightness,
+            Self::Dimmed => u8::from(brightness).min(LedBrightness::Low.into()).into(),
+            Self::Off => LedBrightness::Off,
+        }
+    }
+}
+
+/// Waits for input on any keyboard
+pub struct KeyboardActivity {
+    devices: Vec<AsyncFd<File>>,
+    /// Finds keyboards that are plugged in, if udev could be monitored
+    monitor: Option<UdevMonitor>,
+}
+
+impl KeyboardActivity {
+    /// Open every keyboard input device
+    pub fn new() -> Result<Self, RogError> {
+        // Monitor before scanning so that no keyboards are missed in between
+        let monitor = UdevMonitor::with_subsystems(&["input"])
+            .map_err(|e| warn!("Keyboards plugged in later won't be watched: {e}"))
+            .ok();
+
+        let mut enumerator = udev::Enumerator::new()
+            .map_err(|e| PlatformError::Udev("enumerator failed".into(), e))?;
+        enumerator
+            .match_subsystem("input")
+            .map_err(|e| PlatformError::Udev("match_subsystem failed".into(), e))?;
+        enumerator
+            .match_property("ID_INPUT_KEYBOARD", "1")
+            .map_err(|e| PlatformError::Udev("match_property failed".into(), e))?;
+
+        let devices: Vec<AsyncFd<File>> = enumerator
+            .scan_devices()
+            .map_err(|e| PlatformError::IoPath("enumerator".to_owned(), e))?
+            .filter_map(|device| keyboard_node(&device))
+            .filter_map(|node| open_keyboard(&node))
+            .collect();
+        if devices.is_empty() && monitor.is_none() {
+            return Err(RogError::MissingFunction(
+                "No keyboard input devices".to_owned(),
+            ));
+        }
+        Ok(Self { devices, monitor })
+    }
+
+    /// Wait for input. A device that fails, such as one that was unplugged, is
+    /// no longer watched. Keyboards plugged in while waiting are added.
+    pub async fn next(&mut self) {
+        loop {
+            tokio::select! {
+                input = read_any(&mut self.devices) => {
+                    if input {
+                        return;
+                    }
+                }
+                nodes = plugged_in(&mut self.monitor) => {
+                    self.devices.extend(nodes.iter().filter_map(|node| open_keyboard(node)));
+                }
+            }
+        }
+    }
+}
+
+/// The evdev node of a keyboard
+fn keyboard_node(device: &udev::Device) -> Option<PathBuf> {
+    let keyboard = device
+        .property_value("ID_INPUT_KEYBOARD")
+        .is_some_and(|v| v == "1");
+    if !keyboard || !device.sysname().to_string_lossy().starts_with("event") {
+        return None;
+    }
+    device.devnode().map(|node| node.to_owned())
+}
+
+fn open_keyboard(node: &Path) -> Option<AsyncFd<File>> {
+    match OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(node)
+        .and_then(AsyncFd::new)
+    {
+        Ok(file) => {
+            debug!("Watching {node:?} for keyboard input");
+            Some(file)
+        }
+        Err(e) => {
+            warn!("Could not open {node:?}: {e}");
+            None
+        }
+    }
+}
+
+/// Wait for a device to be readable and drain it, returning `true` if it had
+/// input. Never returns if there are no devices.
+async fn read_any(devices: &mut Vec<AsyncFd<File>>) -> bool {
+    if devices.is_empty() {
+        return std::future::pending().await;
+    }
+    let mut buffer = [0; 24 * 16];
+    let (ready, index, _) = select_all(devices.iter().map(|d| Box::pin(d.readable()))).await;
+    let mut guard = match ready {
+        Ok(guard) => guard,
+        Err(e) => {
+            warn!("Keyboard input device failed: {e}");
+            devices.remove(index);
+            return false;
+        }
+    };
+    let mut input = false;
+    // Drain the events so the device is only ready again on new input
+    loop {
+        match guard.try_io(|fd| fd.get_ref().read(&mut buffer)) {
+            Ok(Ok(0)) => break,
+            Ok(Ok(_)) => input = true,
+            Ok(Err(e)) if e.kind() == ErrorKind::Interrupted => {}
+            Ok(Err(e)) => {
+                warn!("Keyboard input device failed: {e}");
+                drop(guard);
+                devices.remove(index);
+                break;
+            }
+            Err(_would_block) => break,
+        }
+    }
+    input
+}
+
+/// Wait for keyboards to be plugged in. Never returns if udev can't be
+/// monitored.
+async fn plugged_in(monitor: &mut Option<UdevMonitor>) -> Vec<PathBuf> {
+    let Some(udev) = monitor else {
+        return std::future::pending().await;
+    };
+    match udev
+        .next_events(|event| {
+            (event.event_type() == EventType::Add)
+                .then(|| keyboard_node(event))
+                .flatten()
+        })
+        .await
+    {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            warn!("udev monitor failed, keyboards plugged in won't be watched: {e}");
+            *monitor = None;
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rog_aura::LedBrightness;
+
+    use super::IdleLevel;
+
+    #[test]
+    fn idle_levels() {
+        let secs = Duration::from_secs;
+        let (dim, off) = (Some(30), Some(120));
+        assert_eq!(IdleLevel::after(secs(10), dim, off), IdleLevel::Active);
+        assert_eq!(IdleLevel::next_change(secs(10), dim, off), Some(secs(30)));
+        assert_eq!(IdleLevel::after(secs(30), dim, off), IdleLevel::Dimmed);
+        assert_eq!(IdleLevel::next_change(secs(30), dim, off), Some(secs(120)));
+        assert_eq!(IdleLevel::after(secs(500), dim, off), IdleLevel::Off);
+        assert_eq!(IdleLevel::next_change(secs(500), dim, off), None);
+        // Off only
+        assert_eq!(IdleLevel::after(secs(60), None, off), IdleLevel::Active);
+        assert_eq!(IdleLevel::after(secs(120), None, off), IdleLevel::Off);
+        assert_eq!(IdleLevel::next_change(secs(0), None, None), None);
+
+        assert_eq!(
+            IdleLevel::Dimmed.brightness(LedBrightness::High),
+            LedBrightness::Low
+        );
+        assert_eq!(
+            IdleLevel::Dimmed.brightness(LedBrightness::Off),
+            LedBrightness::Off
+        );
+        assert_eq!(
+            IdleLevel::Active.brightness(LedBrightness::Med),
+            LedBrightness::Med
+        );
+    }
+}
@@ -1,55 +1,174 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use config::AuraConfig;
 use config_traits::StdConfig;
-use futures_util::lock::{Mutex, MutexGuard};
-use log::info;
-use rog_aura::keyboard::{AuraLaptopUsbPackets, LedUsbPackets};
+use futures_util::lock::Mutex;
+use idle::IdleLevel;
+use log::{debug, info, warn};
+use rog_aura::effects::AdvancedEffects;
+use rog_aura::keyboard::{
+    AdvancedAuraType, AuraLaptopUsbPackets, LaptopAuraPower, LedCode, LedUsbPackets,
+};
 use rog_aura::usb::{AURA_LAPTOP_LED_APPLY, AURA_LAPTOP_LED_SET};
-use rog_aura::{AuraDeviceType, AuraEffect, LedBrightness, PowerZones, AURA_LAPTOP_LED_MSG_LEN};
-use rog_platform::hid_raw::HidRaw;
+use rog_aura::{
+    AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, Colour, LedBrightness, PowerZones,
+    AURA_LAPTOP_LED_MSG_LEN,
+};
 use rog_platform::keyboard_led::KeyboardBacklight;
+use tokio::sync::{mpsc, watch};
+use tokio::time::sleep;
 
 use crate::error::RogError;
+use crate::state::{ConfigGuard, SharedConfig};
+use crate::transport::SharedTransport;
 
 pub mod config;
+pub mod crossfade;
+pub mod idle;
 pub mod trait_impls;
 
+/// How many flashes can wait behind the one showing
+const FLASH_QUEUE: usize = 8;
+
+/// The parts of `AuraConfig` read by dbus properties and tasks
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuraState {
+    pub led_type: AuraDeviceType,
+    pub current_mode: AuraModeNum,
+    pub builtins: BTreeMap<AuraModeNum, AuraEffect>,
+    pub enabled: LaptopAuraPower,
+    pub basic_zones: Vec<AuraZone>,
+    pub power_zones: Vec<PowerZones>,
+    pub transition_ms: u32,
+    /// The `dim_after` and `off_after` of the current power policy
+    pub idle_after: (Option<u32>, Option<u32>),
+}
+
+impl From<&AuraConfig> for AuraState {
+    fn from(config: &AuraConfig) -> Self {
+        Self {
+            led_type: config.led_type,
+            current_mode: config.current_mode,
+            builtins: config.builtins.clone(),
+            enabled: config.enabled.clone(),
+            basic_zones: config.support_data.basic_zones.clone(),
+            power_zones: config.support_data.power_zones.clone(),
+            transition_ms: config.transition_ms,
+            idle_after: (
+                config.power_policy().dim_after,
+                config.power_policy().off_after,
+            ),
+        }
+    }
+}
+
+/// A blink of `zone` in `colour` `count` times, each on and off cycle taking
+/// `period`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flash {
+    pub colour: Colour,
+    pub count: u32,
+    pub period: Duration,
+    pub zone: AuraZone,
+}
+
 #[derive(Debug, Clone)]
 pub struct Aura {
-    pub hid: Option<Arc<Mutex<HidRaw>>>,
+    pub hid: Option<SharedTransport>,
     pub backlight: Option<Arc<Mutex<KeyboardBacklight>>>,
-    pub config: Arc<Mutex<AuraConfig>>,
+    config: Arc<SharedConfig<AuraConfig, AuraState>>,
+    flashes: mpsc::Sender<Flash>,
+    /// Taken by the task that shows flashes
+    flash_queue: Arc<Mutex<mpsc::Receiver<Flash>>>,
 }
 
 impl Aura {
+    pub fn new(
+        hid: Option<SharedTransport>,
+        backlight: Option<Arc<Mutex<KeyboardBacklight>>>,
+        config: AuraConfig,
+    ) -> Self {
+        let (flashes, flash_queue) = mpsc::channel(FLASH_QUEUE);
+        Self {
+            hid,
+            backlight,
+            config: Arc::new(SharedConfig::new(config)),
+            flashes,
+            flash_queue: Arc::new(Mutex::new(flash_queue)),
+        }
+    }
+
     /// Initialise the device if required.
     pub async fn do_initialization(&self) -> Result<(), RogError> {
         Ok(())
     }
 
-    pub async fn lock_config(&self) -> MutexGuard<'_, AuraConfig> {
+    /// Lock the config to change it. This waits for any other writer, such
+    /// as a per-key frame, to finish.
+    pub async fn lock_config(&self) -> ConfigGuard<'_, AuraConfig, AuraState> {
         self.config.lock().await
     }
 
+    /// The state as of the last config change, this never waits on a writer.
+    /// Don't hold this across an await.
+    pub fn state(&self) -> watch::Ref<'_, AuraState> {
+        self.config.state()
+    }
+
+    /// To be notified of each change to the state
+    pub fn subscribe(&self) -> watch::Receiver<AuraState> {
+        self.config.subscribe()
+    }
+
     /// Will lock the internal config and update. If anything else has locked
     /// this in scope then a deadlock can occur.
     pub async fn update_config(&self) -> Result<(), RogError> {
         let mut config = self.config.lock().await;
         let bright = if let Some(bl) = self.backlight.as_ref() {
-            bl.lock().await.get_brightness().unwrap_or_default()
+            bl.lock().await.get_brightness().unwrap_or_default().into()
         } else {
-            config.brightness.into()
+            config.policy_brightness()
         };
+        let (on_battery, lid_closed, idle, shown_colour) = (
+            config.on_battery, config.lid_closed, config.idle, config.shown_colour,
+        );
         config.read();
-        config.brightness = bright.into();
+        config.on_battery = on_battery;
+        config.lid_closed = lid_closed;
+        config.idle = idle;
+        config.shown_colour = shown_colour;
+        // Don't store a brightness that was set by a policy
+        if bright != config.policy_brightness() {
+            config.set_brightness(bright);
+        }
         config.write();
         Ok(())
     }
 
+    /// Store a brightness changed outside of asusd, such as by the Fn keys.
+    /// Returns `true` if the config was changed.
+    pub async fn sync_brightness(&self) -> Result<bool, RogError> {
+        let Some(backlight) = &self.backlight else {
+            return Ok(false);
+        };
+        let bright = LedBrightness::from(backlight.lock().await.get_brightness()?);
+        let mut config = self.config.lock().await;
+        if config.policy_brightness() == bright {
+            return Ok(false);
+        }
+        debug!("Keyboard brightness was changed to {bright:?} externally");
+        config.set_brightness(bright);
+        config.write();
+        Ok(true)
+    }
+
+    /// Write the mode for the current power source, see
+    /// `AuraConfig::policy_mode()`
     pub async fn write_current_config_mode(&self, config: &mut AuraConfig) -> Result<(), RogError> {
+        let mode = config.policy_mode();
         if config.multizone_on {
-            let mode = config.current_mode;
             let mut create = false;
             // There is no multizone config for this mode so create one here
             // using the colours of rainbow if it exists, or first available
@@ -73,9 +192,11 @@ impl Aura {
                     }
                 }
             }
+            // Each zone has its own colour so there is nothing to fade from
+            config.shown_colour = None;
         } else {
-            let mode = config.current_mode;
             if let Some(effect) = config.builtins.get(&mode).cloned() {
+                self.crossfade(config, &effect).await?;
                 self.write_effect_and_apply(config.led_type, &effect)
                     .await?;
             }
@@ -84,6 +205,54 @@ impl Aura {
         Ok(())
     }
 
+    /// Fade from the colour last shown to the colour of `effect` through
+    /// direct addressing, ready for `effect` to be written. Only done if
+    /// `transition_ms` is set, `effect` covers the whole keyboard, and the
+    /// keyboard is per-key or zoned.
+    pub async fn crossfade(
+        &self,
+        config: &mut AuraConfig,
+        effect: &AuraEffect,
+    ) -> Result<(), RogError> {
+        let to = effect.colour1;
+        let from = config.shown_colour.replace(to);
+        if effect.zone != AuraZone::None {
+            config.shown_colour = None;
+            return Ok(());
+        }
+        let (Some(from), Some(hid_raw)) = (from, &self.hid) else {
+            return Ok(());
+        };
+        if config.transition_ms == 0 || from == to {
+            return Ok(());
+        }
+        let frames = crossfade::frames(
+            &config.support_data.advanced_type,
+            from,
+            to,
+            config.transition_ms.min(crossfade::MAX_TRANSITION_MS),
+        );
+        if frames.is_empty() {
+            return Ok(());
+        }
+
+        debug!("Crossfading from {from:?} to {to:?}");
+        hid_raw
+            .lock()
+            .await
+            .write_bytes(&LedUsbPackets::get_init_msg())?;
+        for frame in frames {
+            {
+                let mut hid_raw = hid_raw.lock().await;
+                for row in frame.iter() {
+                    hid_raw.write_bytes(row)?;
+                }
+            }
+            sleep(crossfade::FRAME_INTERVAL).await;
+        }
+        Ok(())
+    }
+
     /// Write the AuraEffect to the device. Will lock `backlight` or `hid`.
     ///
     /// If per-key or software-mode is active it must be marked as disabled in
@@ -103,7 +272,7 @@ impl Aura {
             }
         } else if let Some(hid_raw) = &self.hid {
             let bytes: [u8; AURA_LAPTOP_LED_MSG_LEN] = mode.into();
-            let hid_raw = hid_raw.lock().await;
+            let mut hid_raw = hid_raw.lock().await;
             hid_raw.write_bytes(&bytes)?;
             hid_raw.write_bytes(&AURA_LAPTOP_LED_SET)?;
             // Changes won't persist unless apply is set
@@ -128,15 +297,24 @@ impl Aura {
     /// Set combination state for boot animation/sleep animation/all leds/keys
     /// leds/side leds LED active
     pub async fn set_power_states(&self, config: &AuraConfig) -> Result<(), RogError> {
-        if matches!(config.led_type, rog_aura::AuraDeviceType::LaptopKeyboardTuf) {
+        self.write_power_states(config.led_type, &config.enabled)
+            .await
+    }
+
+    async fn write_power_states(
+        &self,
+        led_type: AuraDeviceType,
+        enabled: &LaptopAuraPower,
+    ) -> Result<(), RogError> {
+        if matches!(led_type, rog_aura::AuraDeviceType::LaptopKeyboardTuf) {
             if let Some(backlight) = &self.backlight {
                 // TODO: tuf bool array
-                let buf = config.enabled.to_bytes(config.led_type);
+                let buf = enabled.to_bytes(led_type);
                 backlight.lock().await.set_kbd_rgb_state(&buf)?;
             }
         } else if let Some(hid_raw) = &self.hid {
-            let hid_raw = hid_raw.lock().await;
-            if let Some(p) = config.enabled.states.first() {
+            let mut hid_raw = hid_raw.lock().await;
+            if let Some(p) = enabled.states.first() {
                 if p.zone == PowerZones::Ally {
                     let msg = [
                         0x5d,
@@ -152,7 +330,7 @@ impl Aura {
                 }
             }
 
-            let bytes = config.enabled.to_bytes(config.led_type);
+            let bytes = enabled.to_bytes(led_type);
             let msg = [
                 0x5d, 0xbd, 0x01, bytes[0], bytes[1], bytes[2], bytes[3],
             ];
@@ -161,6 +339,46 @@ impl Aura {
         Ok(())
     }
 
+    /// Apply the brightness, mode, and power states for the current power
+    /// source and lid state. The LEDs are turned off by clearing the awake
+    /// state of each power zone.
+    pub async fn apply_power_policy(&self, config: &mut AuraConfig) -> Result<(), RogError> {
+        let off = config.policy_off();
+        let mut enabled = config.enabled.clone();
+        if off {
+            for state in enabled.states.iter_mut() {
+                state.awake = false;
+            }
+            // Coming back on fades in from black
+            config.shown_colour = Some(Colour { r: 0, g: 0, b: 0 });
+        }
+        self.write_power_states(config.led_type, &enabled)
+            .await
+            .map_err(|err| warn!("{err}"))
+            .ok();
+        if self.backlight.is_some() {
+            self.set_brightness(config.policy_brightness().into())
+                .await?;
+        }
+        if !off {
+            self.write_current_config_mode(config).await?;
+        }
+        Ok(())
+    }
+
+    /// Change the idle level, writing only what the change requires
+    pub async fn set_idle(&self, config: &mut AuraConfig, idle: IdleLevel) -> Result<(), RogError> {
+        let was_off = config.policy_off();
+        config.idle = idle;
+        if was_off != config.policy_off() {
+            self.apply_power_policy(config).await
+        } else if self.backlight.is_some() {
+            self.set_brightness(config.policy_brightness().into()).await
+        } else {
+            Ok(())
+        }
+    }
+
     /// Write an effect block. This is for per-key, but can be repurposed to
     /// write the raw factory mode packets - when doing this it is expected that
     /// only the first `Vec` (`effect[0]`) is valid.
@@ -178,7 +396,7 @@ impl Aura {
         const PER_KEY_TYPE: u8 = 0xbc;
 
         if let Some(hid_raw) = &self.hid {
-            let hid_raw = hid_raw.lock().await;
+            let mut hid_raw = hid_raw.lock().await;
             if pkt_type != PER_KEY_TYPE {
                 config.per_key_mode_active = false;
                 hid_raw.write_bytes(&effect[0])?;
@@ -190,6 +408,8 @@ impl Aura {
                     hid_raw.write_bytes(&init)?;
                     config.per_key_mode_active = true;
                 }
+                config.shown_colour = None;
+                config.per_key_frame = Some(effect.clone());
                 for row in effect.iter() {
                     hid_raw.write_bytes(row)?;
                 }
@@ -209,8 +429,161 @@ impl Aura {
         Ok(())
     }
 
+    /// Queue `flash` to be shown by `run_flashes()` after any already queued
+    pub fn queue_flash(&self, flash: Flash) -> Result<(), RogError> {
+        self.flashes
+            .try_send(flash)
+            .map_err(|_| RogError::DoTask("Too many flashes are queued".to_owned()))
+    }
+
+    /// Show each queued flash in turn, until the device is removed
+    pub async fn run_flashes(&self) {
+        let mut queue = self.flash_queue.lock().await;
+        while let Some(flash) = queue.recv().await {
+            self.flash(&flash)
+                .await
+                .unwrap_or_else(|e| warn!("Could not show the flash: {e}"));
+        }
+    }
+
+    /// Show `flash`, then restore the mode or per-key frame showing at the
+    /// end. The LEDs are lit for the flash even if a policy or idle has them
+    /// off.
+    ///
+    /// The config is only locked for each write, so other changes aren't held
+    /// up by a long flash.
+    pub async fn flash(&self, flash: &Flash) -> Result<(), RogError> {
+        let on = AuraEffect {
+            mode: AuraModeNum::Static,
+            zone: flash.zone,
+            colour1: flash.colour,
+            ..Default::default()
+        };
+        let off = AuraEffect {
+            colour1: Colour { r: 0, g: 0, b: 0 },
+            ..on.clone()
+        };
+
+        let led_type = {
+            let config = self.config.lock().await;
+            if config.policy_off() {
+                self.write_power_states(config.led_type, &config.enabled)
+                    .await?;
+            }
+            if self.backlight.is_some() && config.policy_brightness() == LedBrightness::Off {
+                self.set_brightness(LedBrightness::Med.into()).await?;
+            }
+            config.led_type
+        };
+        let mut result = Ok(());
+        'flash: for _ in 0..flash.count {
+            for effect in [
+                &on, &off,
+            ] {
+                result = {
+                    let _config = self.config.lock().await;
+                    self.write_effect_and_apply(led_type, effect).await
+                };
+                if result.is_err() {
+                    break 'flash;
+                }
+                sleep(flash.period / 2).await;
+            }
+        }
+        let mut config = self.config.lock().await;
+        let frame = Self::showing_frame(&config);
+        self.restore_lighting(&mut config, frame).await?;
+        result
+    }
+
+    /// Draw the battery gauge on the function row of a per-key keyboard, or
+    /// the lightbar of a zoned keyboard (the keyboard zones if it has no
+    /// lightbar). Returns `false` if the keyboard has no direct addressing.
+    pub async fn write_battery_gauge(
+        &self,
+        config: &mut AuraConfig,
+        percent: u8,
+        charging: bool,
+    ) -> Result<bool, RogError> {
+        let (zoned, leds) = match &config.support_data.advanced_type {
+            AdvancedAuraType::None => return Ok(false),
+            AdvancedAuraType::Zoned(zones) => {
+                let lightbar: Vec<LedCode> = zones
+                    .iter()
+                    .copied()
+                    .filter(LedCode::is_lightbar_zone)
+                    .collect();
+                if lightbar.is_empty() {
+                    (true, zones.clone())
+                } else {
+                    (true, lightbar)
+                }
+            }
+            AdvancedAuraType::PerKey => (false, LedCode::FUNCTION_ROW.to_vec()),
+        };
+        let gauge = AdvancedEffects::gauge(zoned, &leds, percent, charging);
+        self.write_effect_block(config, &gauge.create_packets())
+            .await?;
+        Ok(true)
+    }
+
+    /// Show the battery gauge for `duration`, then restore the lighting. Not
+    /// shown while a policy has the LEDs off. The config isn't locked while
+    /// the gauge is showing.
+    pub async fn show_battery_gauge(
+        &self,
+        percent: u8,
+        charging: bool,
+        duration: Duration,
+    ) -> Result<(), RogError> {
+        let (frame, gauge) = {
+            let mut config = self.config.lock().await;
+            let frame = Self::showing_frame(&config);
+            if config.policy_off()
+                || !self
+                    .write_battery_gauge(&mut config, percent, charging)
+                    .await?
+            {
+                return Ok(());
+            }
+            (frame, Self::showing_frame(&config))
+        };
+        sleep(duration).await;
+        let mut config = self.config.lock().await;
+        // Anything shown while the gauge was up is kept
+        let showing = Self::showing_frame(&config);
+        let frame = if showing != gauge { showing } else { frame };
+        self.restore_lighting(&mut config, frame).await
+    }
+
+    /// The per-key frame showing, if any, to restore after something
+    /// temporary such as a flash or the battery gauge
+    fn showing_frame(config: &AuraConfig) -> Option<AuraLaptopUsbPackets> {
+        config
+            .per_key_mode_active
+            .then(|| config.per_key_frame.clone())
+            .flatten()
+    }
+
+    /// Restore the lighting for the current policy, then `frame` from
+    /// `showing_frame()` if there was one
+    async fn restore_lighting(
+        &self,
+        config: &mut AuraConfig,
+        frame: Option<AuraLaptopUsbPackets>,
+    ) -> Result<(), RogError> {
+        // The per-key mode has to be initialised again after a builtin mode
+        config.per_key_mode_active = false;
+        config.shown_colour = None;
+        self.apply_power_policy(config).await?;
+        if let Some(frame) = frame.filter(|_| !config.policy_off()) {
+            self.write_effect_block(config, &frame).await?;
+        }
+        Ok(())
+    }
+
     pub async fn fix_ally_power(&mut self) -> Result<(), RogError> {
-        if self.config.lock().await.led_type == AuraDeviceType::Ally {
+        if self.state().led_type == AuraDeviceType::Ally {
             if let Some(hid_raw) = &self.hid {
                 let mut config = self.config.lock().await;
                 if config.ally_fix.is_none() {
@@ -227,3 +600,215 @@ impl Aura {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rog_aura::keyboard::{AdvancedAuraType, AuraLaptopUsbPackets, LedCode, LedUsbPackets};
+    use rog_aura::{AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, Colour, Direction, Speed};
+
+    use super::config::tests::config_19b6;
+    use super::{Aura, Flash};
+    use crate::transport::RecordingTransport;
+
+    fn aura_19b6(rec: &RecordingTransport) -> Aura {
+        Aura::new(Some(rec.shared()), None, config_19b6())
+    }
+
+    #[tokio::test]
+    async fn write_effect_packets_19b6() {
+        let rec = RecordingTransport::new();
+        let aura = aura_19b6(&rec);
+        let effect = AuraEffect {
+            mode: AuraModeNum::Static,
+            zone: AuraZone::None,
+            colour1: Colour {
+                r: 0xff,
+                g: 0,
+                b: 0,
+            },
+            colour2: Colour { r: 0, g: 0, b: 0 },
+            speed: Speed::Med,
+            direction: Direction::Right,
+        };
+        aura.write_effect_and_apply(AuraDeviceType::LaptopKeyboard2021, &effect)
+            .await
+            .unwrap();
+
+        assert_eq!(rec.packets(), vec![
+            vec![0x5d, 0xb3, 0, 0, 0xff, 0, 0, 0xeb, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            vec![0x5d, 0xb5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            vec![0x5d, 0xb4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        ]);
+    }
+
+    #[tokio::test]
+    async fn write_power_packets_19b6() {
+        let rec = RecordingTransport::new();
+        let aura = aura_19b6(&rec);
+        let config = aura.config.lock().await.clone();
+        aura.set_power_states(&config).await.unwrap();
+
+        let packets = rec.packets();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(&packets[0][..3], &[0x5d, 0xbd, 0x01]);
+        assert_eq!(packets[0].len(), 7);
+    }
+
+    #[tokio::test]
+    async fn apply_power_policy_19b6() {
+        let rec = RecordingTransport::new();
+        let aura = aura_19b6(&rec);
+        let mut config = aura.config.lock().await;
+        config.builtins.insert(
+            AuraModeNum::Static,
+            AuraEffect::default_with_mode(AuraModeNum::Static),
+        );
+        config.battery_policy.off = true;
+
+        aura.apply_power_policy(&mut config).await.unwrap();
+        // Power states then the mode
+        assert_eq!(rec.take_packets().len(), 4);
+
+        config.on_battery = true;
+        aura.apply_power_policy(&mut config).await.unwrap();
+        let packets = rec.take_packets();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(&packets[0][..3], &[0x5d, 0xbd, 0x01]);
+        // No awake bits for the keyboard or logo
+        assert_eq!(packets[0][3] & 0b1100, 0);
+    }
+
+    #[tokio::test]
+    async fn crossfade_per_key_19b6() {
+        let rec = RecordingTransport::new();
+        let aura = aura_19b6(&rec);
+        let mut config = aura.config.lock().await;
+        config.support_data.advanced_type = AdvancedAuraType::PerKey;
+        config.transition_ms = 66;
+        let mut effect = AuraEffect::default_with_mode(AuraModeNum::Static);
+        effect.colour1 = Colour {
+            r: 0xff,
+            g: 0,
+            b: 0,
+        };
+        config.builtins.insert(AuraModeNum::Static, effect);
+
+        // Nothing to fade from until a mode has been written
+        aura.write_current_config_mode(&mut config).await.unwrap();
+        assert_eq!(rec.take_packets().len(), 3);
+
+        config.shown_colour = Some(Colour { r: 0, g: 0, b: 0 });
+        aura.write_current_config_mode(&mut config).await.unwrap();
+        let packets = rec.take_packets();
+        // Init, two frames of 11 rows, then the mode
+        assert_eq!(packets.len(), 1 + 2 * 11 + 3);
+        assert_eq!(&packets[0][..3], &[0x5d, 0xbc, 0]);
+        assert_eq!(&packets[23][..2], &[0x5d, 0xb3]);
+        assert_eq!(
+            config.shown_colour,
+            Some(Colour {
+                r: 0xff,
+                g: 0,
+                b: 0
+            })
+        );
+
+        // Same colour, no fade
+        aura.write_current_config_mode(&mut config).await.unwrap();
+        assert_eq!(rec.take_packets().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn flash_restores_per_key_19b6() {
+        let rec = RecordingTransport::new();
+        let aura = aura_19b6(&rec);
+        aura.config.lock().await.builtins.insert(
+            AuraModeNum::Static,
+            AuraEffect::default_with_mode(AuraModeNum::Static),
+        );
+        let mut flash = Flash {
+            colour: Colour {
+                r: 0xff,
+                g: 0,
+                b: 0,
+            },
+            count: 2,
+            period: Duration::from_millis(10),
+            zone: AuraZone::None,
+        };
+
+        aura.flash(&flash).await.unwrap();
+        let packets = rec.take_packets();
+        // Two on and off cycles, then the power states and mode are restored
+        assert_eq!(packets.len(), 2 * 2 * 3 + 4);
+        assert_eq!(&packets[0][..5], &[0x5d, 0xb3, 0, 0, 0xff]);
+        assert_eq!(&packets[3][..5], &[0x5d, 0xb3, 0, 0, 0]);
+
+        let mut frame = LedUsbPackets::new_per_key();
+        frame.set(LedCode::Esc, 0, 0xff, 0);
+        let frame = AuraLaptopUsbPackets::from(frame);
+        aura.write_effect_block(&mut *aura.config.lock().await, &frame)
+            .await
+            .unwrap();
+        rec.clear();
+
+        // Queued flashes are shown in turn by the flash task
+        flash.count = 1;
+        aura.queue_flash(flash.clone()).unwrap();
+        aura.queue_flash(flash).unwrap();
+        let task = tokio::spawn({
+            let aura = aura.clone();
+            async move { aura.run_flashes().await }
+        });
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while rec.packets().len() < 2 * (2 * 3 + 4 + 1 + frame.len()) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        task.abort();
+        let packets = rec.take_packets();
+        assert_eq!(packets.len(), 2 * (2 * 3 + 4 + 1 + frame.len()));
+        assert!(packets.ends_with(&frame));
+        assert!(aura.config.lock().await.per_key_mode_active);
+    }
+
+    #[tokio::test]
+    async fn battery_gauge_restores_mode_19b6() {
+        let rec = RecordingTransport::new();
+        let aura = aura_19b6(&rec);
+        let mut config = aura.config.lock().await;
+        config.builtins.insert(
+            AuraModeNum::Static,
+            AuraEffect::default_with_mode(AuraModeNum::Static),
+        );
+        assert!(!aura
+            .write_battery_gauge(&mut config, 50, false)
+            .await
+            .unwrap());
+
+        config.support_data.advanced_type = AdvancedAuraType::PerKey;
+        drop(config);
+        aura.show_battery_gauge(50, false, Duration::from_millis(10))
+            .await
+            .unwrap();
+        let packets = rec.take_packets();
+        // Init and the gauge, then the power states and mode
+        assert_eq!(packets.len(), 1 + 11 + 4);
+        assert_eq!(&packets[0][..2], &[0x5d, 0xbc]);
+        assert_eq!(&packets[13][..2], &[0x5d, 0xb3]);
+        let mut config = aura.config.lock().await;
+        assert!(!config.per_key_mode_active);
+
+        config.battery_policy.off = true;
+        config.on_battery = true;
+        drop(config);
+        aura.show_battery_gauge(50, false, Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert!(rec.packets().is_empty());
+    }
+}
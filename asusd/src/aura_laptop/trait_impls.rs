@@ -1,37 +1,95 @@
 use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::time::Duration;
 
 use config_traits::StdConfig;
 use log::{debug, error, info, warn};
-use rog_aura::keyboard::{AuraLaptopUsbPackets, LaptopAuraPower};
-use rog_aura::{AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, LedBrightness, PowerZones};
+use rog_aura::keyboard::{AdvancedAuraType, AuraLaptopUsbPackets, LaptopAuraPower};
+use rog_aura::{
+    AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, Colour, LedBrightness, PowerZones,
+};
+use rog_platform::power::AsusPower;
+use tokio::time::{sleep, Instant};
 use zbus::fdo::Error as ZbErr;
 use zbus::object_server::SignalEmitter;
 use zbus::zvariant::OwnedObjectPath;
 use zbus::{interface, Connection};
 
-use super::Aura;
+use super::config::BatteryGauge;
+use super::crossfade::MAX_TRANSITION_MS;
+use super::idle::{IdleLevel, KeyboardActivity};
+use super::{Aura, Flash};
 use crate::error::RogError;
-use crate::{CtrlTask, Reloadable};
+use crate::{CtrlTask, Reloadable, TaskHandles};
 
 pub const AURA_ZBUS_NAME: &str = "Aura";
 pub const AURA_ZBUS_PATH: &str = "/xyz/ljones";
+/// How often the battery gauge is redrawn when it's always shown
+const GAUGE_POLL: Duration = Duration::from_secs(2);
+/// How long the battery gauge is shown when AC is plugged or unplugged
+const GAUGE_SHOW: Duration = Duration::from_secs(5);
+const FLASH_MAX_COUNT: u32 = 20;
+const FLASH_PERIOD_MS: RangeInclusive<u32> = 100..=5000;
+/// The longest a flash can take, so that queued flashes don't hold the LEDs
+/// for long
+const FLASH_MAX_MS: u32 = 20_000;
 
 #[derive(Clone)]
 pub struct AuraZbus(Aura);
 
+/// The battery to show in the gauge, if there is one
+fn gauge_power() -> Option<AsusPower> {
+    match AsusPower::new() {
+        Ok(power) if power.has_capacity() => Some(power),
+        Ok(_) => None,
+        Err(e) => {
+            info!("The battery gauge is unavailable: {e}");
+            None
+        }
+    }
+}
+
+/// Show the battery gauge for a few seconds if `battery_gauge` is
+/// `OnPowerChange`
+async fn show_gauge_on_power_change(aura: &Aura) {
+    {
+        let config = aura.lock_config().await;
+        if config.battery_gauge != BatteryGauge::OnPowerChange
+            || config.support_data.advanced_type == AdvancedAuraType::None
+        {
+            return;
+        }
+    }
+    let Some(power) = gauge_power() else {
+        return;
+    };
+    let (Ok(percent), Ok(charging)) = (power.get_capacity(), power.is_charging()) else {
+        return;
+    };
+    aura.show_battery_gauge(percent, charging, GAUGE_SHOW)
+        .await
+        .unwrap_or_else(|e| warn!("Could not show the battery gauge: {e}"));
+}
+
 impl AuraZbus {
     pub fn new(aura: Aura) -> Self {
         Self(aura)
     }
 
+    /// Serve the device at `path` and start the sleep and shutdown tasks. The
+    /// returned handles must be aborted when the device is removed.
     pub async fn start_tasks(
         mut self,
         connection: &Connection,
-        // _signal_ctx: SignalEmitter<'static>,
         path: OwnedObjectPath,
-    ) -> Result<(), RogError> {
-        // let task = zbus.clone();
-        // let signal_ctx = signal_ctx.clone();
+    ) -> Result<TaskHandles, RogError> {
+        let task = self.clone();
+        let signal_ctx = SignalEmitter::new(connection, path.clone())?.into_owned();
+        // Later changes come from logind, but the initial state is needed to
+        // start with the right policy
+        if let Ok(online) = AsusPower::new().and_then(|power| power.get_online()) {
+            self.0.lock_config().await.on_battery = online == 0;
+        }
         self.reload()
             .await
             .unwrap_or_else(|err| warn!("Controller error: {}", err));
@@ -41,9 +99,148 @@ impl AuraZbus {
             .await
             .map_err(|e| error!("Couldn't add server at path: {path}, {e:?}"))
             .ok();
-        // TODO: skip this until we keep handles to tasks so they can be killed
-        // task.create_tasks(signal_ctx).await
-        Ok(())
+        task.create_tasks(signal_ctx).await
+    }
+
+    /// Dim or turn off the LEDs after the idle times in the current power
+    /// policy, restoring them on the next keypress. While neither is set the
+    /// task only waits for the policy to change.
+    fn watch_idle(&self) -> TaskHandles {
+        let mut tasks = TaskHandles::new();
+        let mut keyboards = match KeyboardActivity::new() {
+            Ok(keyboards) => keyboards,
+            Err(e) => {
+                info!("Idle dimming is unavailable: {e}");
+                return tasks;
+            }
+        };
+        let aura = self.0.clone();
+        let mut changes = aura.subscribe();
+        tasks.push(tokio::spawn(async move {
+            let mut last_input = Instant::now();
+            let mut level = IdleLevel::Active;
+            loop {
+                let (dim_after, off_after) = changes.borrow_and_update().idle_after;
+                let idle = last_input.elapsed();
+                let next = IdleLevel::after(idle, dim_after, off_after);
+                if next != level {
+                    debug!("Keyboard idle level changed to {next:?}");
+                    level = next;
+                    let mut config = aura.config.lock().await;
+                    aura.set_idle(&mut config, level)
+                        .await
+                        .unwrap_or_else(|e| warn!("Could not set idle level: {e}"));
+                }
+                if dim_after.is_none() && off_after.is_none() {
+                    if changes.changed().await.is_err() {
+                        return;
+                    }
+                    // Idle time counts from when it was enabled
+                    last_input = Instant::now();
+                    continue;
+                }
+                let wait = IdleLevel::next_change(idle, dim_after, off_after).map(|at| at - idle);
+                tokio::select! {
+                    _ = keyboards.next() => last_input = Instant::now(),
+                    _ = sleep(wait.unwrap_or_default()), if wait.is_some() => {}
+                    // The thresholds change with the power source or a setter
+                    changed = changes.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        }));
+        tasks
+    }
+
+    /// Keep the battery gauge drawn while `battery_gauge` is `Always`. No task
+    /// is started for the other settings, `OnPowerChange` is shown by the
+    /// power change task.
+    async fn watch_battery_gauge(&self) -> TaskHandles {
+        let mut tasks = TaskHandles::new();
+        {
+            let config = self.0.lock_config().await;
+            if config.battery_gauge != BatteryGauge::Always
+                || config.support_data.advanced_type == AdvancedAuraType::None
+            {
+                return tasks;
+            }
+        }
+        let Some(power) = gauge_power() else {
+            return tasks;
+        };
+        let aura = self.0.clone();
+        tasks.push(tokio::spawn(async move {
+            loop {
+                sleep(GAUGE_POLL).await;
+                let mut config = aura.config.lock().await;
+                match config.battery_gauge {
+                    BatteryGauge::Always if config.policy_off() => continue,
+                    BatteryGauge::Always => {}
+                    _ => return,
+                }
+                let (Ok(percent), Ok(charging)) = (power.get_capacity(), power.is_charging())
+                else {
+                    continue;
+                };
+                aura.write_battery_gauge(&mut config, percent, charging)
+                    .await
+                    .map(|_| ())
+                    .unwrap_or_else(|e| warn!("Could not show the battery gauge: {e}"));
+            }
+        }));
+        tasks
+    }
+
+    /// The Fn keys change the brightness in the kernel without going through
+    /// asusd. Follow both `brightness` and `brightness_hw_changed` so the
+    /// config and clients stay in step.
+    async fn watch_brightness(&self, signal_ctxt: SignalEmitter<'static>) -> TaskHandles {
+        use futures_util::StreamExt;
+
+        let mut tasks = TaskHandles::new();
+        let Some(backlight) = &self.0.backlight else {
+            return tasks;
+        };
+        let watches = {
+            let backlight = backlight.lock().await;
+            [
+                backlight.monitor_brightness(),
+                backlight.monitor_brightness_hw_changed(),
+            ]
+        };
+        for watch in watches {
+            match watch {
+                Ok(watch) => {
+                    let ctrl = self.clone();
+                    let signal_ctxt = signal_ctxt.clone();
+                    tasks.push(tokio::spawn(async move {
+                        let mut buffer = [0; 32];
+                        watch
+                            .into_event_stream(&mut buffer)
+                            .unwrap()
+                            .for_each(|_| async {
+                                match ctrl.0.sync_brightness().await {
+                                    Ok(true) => {
+                                        ctrl.brightness_changed(&signal_ctxt).await.ok();
+                                    }
+                                    Ok(false) => {}
+                                    Err(e) => warn!("Could not read keyboard brightness: {e}"),
+                                }
+                            })
+                            .await;
+                    }));
+                }
+                Err(e) => info!(
+                    "inotify watch failed: {}. You can ignore this if your device does not \
+                     support the feature",
+                    e
+                ),
+            }
+        }
+        tasks
     }
 }
 
@@ -55,7 +252,7 @@ impl AuraZbus {
     /// Return the device type for this Aura keyboard
     #[zbus(property)]
     async fn device_type(&self) -> AuraDeviceType {
-        self.0.config.lock().await.led_type
+        self.0.state().led_type
     }
 
     /// Return the current LED brightness
@@ -71,7 +268,13 @@ impl AuraZbus {
     #[zbus(property)]
     async fn set_brightness(&mut self, brightness: LedBrightness) -> Result<(), ZbErr> {
         if let Some(bl) = self.0.backlight.as_ref() {
-            return Ok(bl.lock().await.set_brightness(brightness.into())?);
+            // Locked before the write so that the brightness watch doesn't
+            // see this as an external change
+            let mut config = self.0.lock_config().await;
+            bl.lock().await.set_brightness(brightness.into())?;
+            config.set_brightness(brightness);
+            config.write();
+            return Ok(());
         }
         Err(ZbErr::Failed("No sysfs brightness control".to_string()))
     }
@@ -90,33 +293,23 @@ impl AuraZbus {
     /// The total available modes
     #[zbus(property)]
     async fn supported_basic_modes(&self) -> Result<Vec<AuraModeNum>, ZbErr> {
-        let config = self.0.config.lock().await;
-        Ok(config.builtins.keys().cloned().collect())
+        Ok(self.0.state().builtins.keys().cloned().collect())
     }
 
     #[zbus(property)]
     async fn supported_basic_zones(&self) -> Result<Vec<AuraZone>, ZbErr> {
-        let config = self.0.config.lock().await;
-        Ok(config.support_data.basic_zones.clone())
+        Ok(self.0.state().basic_zones.clone())
     }
 
     #[zbus(property)]
     async fn supported_power_zones(&self) -> Result<Vec<PowerZones>, ZbErr> {
-        let config = self.0.config.lock().await;
-        Ok(config.support_data.power_zones.clone())
+        Ok(self.0.state().power_zones.clone())
     }
 
     /// The current mode data
     #[zbus(property)]
     async fn led_mode(&self) -> Result<AuraModeNum, ZbErr> {
-        // entirely possible to deadlock here, so use try instead of lock()
-        // let ctrl = self.0.lock().await;
-        // Ok(config.current_mode)
-        if let Some(config) = self.0.config.try_lock() {
-            Ok(config.current_mode)
-        } else {
-            Err(ZbErr::Failed("Aura control couldn't lock self".to_string()))
-        }
+        Ok(self.0.state().current_mode)
     }
 
     /// Set an Aura effect if the effect mode or zone is supported.
@@ -126,12 +319,14 @@ impl AuraZbus {
     #[zbus(property)]
     async fn set_led_mode(&mut self, num: AuraModeNum) -> Result<(), ZbErr> {
         let mut config = self.0.config.lock().await;
-        config.current_mode = num;
+        config.set_mode(num);
         self.0.write_current_config_mode(&mut config).await?;
-        if config.brightness == LedBrightness::Off {
-            config.brightness = LedBrightness::Med;
+        if config.policy_brightness() == LedBrightness::Off {
+            config.set_brightness(LedBrightness::Med);
         }
-        self.0.set_brightness(config.brightness.into()).await?;
+        self.0
+            .set_brightness(config.policy_brightness().into())
+            .await?;
         config.write();
         Ok(())
     }
@@ -139,15 +334,10 @@ impl AuraZbus {
     /// The current mode data
     #[zbus(property)]
     async fn led_mode_data(&self) -> Result<AuraEffect, ZbErr> {
-        // entirely possible to deadlock here, so use try instead of lock()
-        if let Some(config) = self.0.config.try_lock() {
-            let mode = config.current_mode;
-            match config.builtins.get(&mode) {
-                Some(effect) => Ok(effect.clone()),
-                None => Err(ZbErr::Failed("Could not get the current effect".into())),
-            }
-        } else {
-            Err(ZbErr::Failed("Aura control couldn't lock self".to_string()))
+        let state = self.0.state();
+        match state.builtins.get(&state.current_mode) {
+            Some(effect) => Ok(effect.clone()),
+            None => Err(ZbErr::Failed("Could not get the current effect".into())),
         }
     }
 
@@ -167,30 +357,93 @@ impl AuraZbus {
             )));
         }
 
+        self.0.crossfade(&mut config, &effect).await?;
         self.0
             .write_effect_and_apply(config.led_type, &effect)
             .await?;
-        if config.brightness == LedBrightness::Off {
-            config.brightness = LedBrightness::Med;
+        if config.policy_brightness() == LedBrightness::Off {
+            config.set_brightness(LedBrightness::Med);
         }
-        self.0.set_brightness(config.brightness.into()).await?;
+        self.0
+            .set_brightness(config.policy_brightness().into())
+            .await?;
         config.set_builtin(effect);
         config.write();
 
         Ok(())
     }
 
+    /// Milliseconds to crossfade to a new mode on per-key and zoned keyboards,
+    /// `0` to switch straight away
+    #[zbus(property)]
+    async fn transition_time(&self) -> u32 {
+        self.0.state().transition_ms
+    }
+
+    #[zbus(property)]
+    async fn set_transition_time(&mut self, millis: u32) -> Result<(), ZbErr> {
+        if millis > MAX_TRANSITION_MS {
+            return Err(ZbErr::InvalidArgs(format!(
+                "The transition time must be at most {MAX_TRANSITION_MS}ms"
+            )));
+        }
+        let mut config = self.0.config.lock().await;
+        config.transition_ms = millis;
+        config.write();
+        Ok(())
+    }
+
+    /// Blink the keyboard, logo or lightbar `count` times in one colour, each
+    /// on and off cycle taking `period_ms`, then restore the mode or per-key
+    /// frame that was showing. Returns once the flash is queued, flashes that
+    /// overlap are shown in turn.
+    async fn flash(
+        &self,
+        colour: Colour,
+        count: u32,
+        period_ms: u32,
+        zone: AuraZone,
+    ) -> Result<(), ZbErr> {
+        if !(1..=FLASH_MAX_COUNT).contains(&count) {
+            return Err(ZbErr::InvalidArgs(format!(
+                "The flash count must be 1 to {FLASH_MAX_COUNT}"
+            )));
+        }
+        if !FLASH_PERIOD_MS.contains(&period_ms) {
+            return Err(ZbErr::InvalidArgs(format!(
+                "The flash period must be {} to {}ms",
+                FLASH_PERIOD_MS.start(),
+                FLASH_PERIOD_MS.end()
+            )));
+        }
+        if count * period_ms > FLASH_MAX_MS {
+            return Err(ZbErr::InvalidArgs(format!(
+                "The flash must take at most {FLASH_MAX_MS}ms"
+            )));
+        }
+        if zone != AuraZone::None && !self.0.state().basic_zones.contains(&zone) {
+            return Err(ZbErr::NotSupported(format!(
+                "The Aura zone is not supported: {zone:?}"
+            )));
+        }
+        self.0.queue_flash(Flash {
+            colour,
+            count,
+            period: Duration::from_millis(period_ms.into()),
+            zone,
+        })?;
+        Ok(())
+    }
+
     /// Get the data set for every mode available
     async fn all_mode_data(&self) -> BTreeMap<AuraModeNum, AuraEffect> {
-        let config = self.0.config.lock().await;
-        config.builtins.clone()
+        self.0.state().builtins.clone()
     }
 
     // As property doesn't work for AuraPowerDev (complexity of serialization?)
     #[zbus(property)]
     async fn led_power(&self) -> LaptopAuraPower {
-        let config = self.0.config.lock().await;
-        config.enabled.clone()
+        self.0.state().enabled.clone()
     }
 
     /// Set a variety of states, input is array of enum.
@@ -230,114 +483,100 @@ impl CtrlTask for AuraZbus {
         "/xyz/ljones"
     }
 
-    async fn create_tasks(&self, _: SignalEmitter<'static>) -> Result<(), RogError> {
+    async fn create_tasks(
+        &self,
+        signal_ctx: SignalEmitter<'static>,
+    ) -> Result<TaskHandles, RogError> {
         let inner1 = self.0.clone();
         let inner3 = self.0.clone();
-        self.create_sys_event_tasks(
-            move |sleeping| {
-                let inner1 = inner1.clone();
-                // unwrap as we want to bomb out of the task
-                async move {
-                    if !sleeping {
+        let inner4 = self.0.clone();
+        let inner5 = self.0.clone();
+        let mut tasks = self
+            .create_sys_event_tasks(
+                signal_ctx.connection(),
+                move |sleeping| {
+                    let inner1 = inner1.clone();
+                    async move {
+                        if !sleeping {
+                            info!("CtrlKbdLedTask reloading brightness and modes");
+                            let mut config = inner1.config.lock().await;
+                            inner1
+                                .apply_power_policy(&mut config)
+                                .await
+                                .unwrap_or_else(|e| warn!("CtrlKbdLedTask: {e}"));
+                        } else {
+                            inner1
+                                .update_config()
+                                .await
+                                .unwrap_or_else(|e| warn!("CtrlKbdLedTask: {e}"));
+                        }
+                    }
+                },
+                move |_shutting_down| {
+                    let inner3 = inner3.clone();
+                    async move {
                         info!("CtrlKbdLedTask reloading brightness and modes");
-                        if let Some(backlight) = &inner1.backlight {
+                        if let Some(backlight) = &inner3.backlight {
+                            // The config is always locked before the backlight
+                            let config = inner3.config.lock().await;
                             backlight
                                 .lock()
                                 .await
-                                .set_brightness(inner1.config.lock().await.brightness.into())
-                                .map_err(|e| {
-                                    error!("CtrlKbdLedTask: {e}");
-                                    e
-                                })
-                                .unwrap();
+                                .set_brightness(config.brightness.into())
+                                .unwrap_or_else(|e| warn!("CtrlKbdLedTask: {e}"));
                         }
-                        let mut config = inner1.config.lock().await;
-                        inner1
-                            .write_current_config_mode(&mut config)
-                            .await
-                            .map_err(|e| {
-                                error!("CtrlKbdLedTask: {e}");
-                                e
-                            })
-                            .unwrap();
-                    } else if sleeping {
-                        inner1
-                            .update_config()
-                            .await
-                            .map_err(|e| {
-                                error!("CtrlKbdLedTask: {e}");
-                                e
-                            })
-                            .unwrap();
                     }
-                }
-            },
-            move |_shutting_down| {
-                let inner3 = inner3.clone();
-                async move {
-                    info!("CtrlKbdLedTask reloading brightness and modes");
-                    if let Some(backlight) = &inner3.backlight {
-                        // unwrap as we want to bomb out of the task
-                        backlight
-                            .lock()
-                            .await
-                            .set_brightness(inner3.config.lock().await.brightness.into())
-                            .map_err(|e| {
-                                error!("CtrlKbdLedTask: {e}");
-                                e
-                            })
-                            .unwrap();
+                },
+                move |lid_closed| {
+                    let inner = inner4.clone();
+                    async move {
+                        let mut config = inner.config.lock().await;
+                        config.lid_closed = lid_closed;
+                        if config.off_on_lid_closed {
+                            info!("CtrlKbdLedTask applying lid closed ({lid_closed}) policy");
+                            inner
+                                .apply_power_policy(&mut config)
+                                .await
+                                .unwrap_or_else(|e| warn!("CtrlKbdLedTask: {e}"));
+                        }
                     }
-                }
-            },
-            move |_lid_closed| {
-                // on lid change
-                async move {}
-            },
-            move |_power_plugged| {
-                // power change
-                async move {}
-            },
-        )
-        .await;
-
-        // let ctrl2 = self.0.clone();
-        // let ctrl = self.0.lock().await;
-        // if ctrl.led_node.has_brightness_control() {
-        //     let watch = ctrl.led_node.monitor_brightness()?;
-        //     tokio::spawn(async move {
-        //         let mut buffer = [0; 32];
-        //         watch
-        //             .into_event_stream(&mut buffer)
-        //             .unwrap()
-        //             .for_each(|_| async {
-        //                 if let Some(lock) = ctrl2.try_lock() {
-        //                     load_save(true, lock).unwrap(); // unwrap as we want
-        //                                                     // to
-        //                                                     // bomb out of the
-        //                                                     // task
-        //                 }
-        //             })
-        //             .await;
-        //     });
-        // }
+                },
+                move |power_plugged| {
+                    let inner = inner5.clone();
+                    async move {
+                        {
+                            let mut config = inner.config.lock().await;
+                            config.on_battery = !power_plugged;
+                            if config.ac_policy != config.battery_policy {
+                                info!("CtrlKbdLedTask applying AC ({power_plugged}) policy");
+                                inner
+                                    .apply_power_policy(&mut config)
+                                    .await
+                                    .unwrap_or_else(|e| warn!("CtrlKbdLedTask: {e}"));
+                            }
+                        }
+                        show_gauge_on_power_change(&inner).await;
+                    }
+                },
+            )
+            .await?;
 
-        Ok(())
+        tasks.extend(self.watch_brightness(signal_ctx).await);
+        let aura = self.0.clone();
+        tasks.push(tokio::spawn(async move { aura.run_flashes().await }));
+        tasks.extend(self.watch_idle());
+        tasks.extend(self.watch_battery_gauge().await);
+
+        Ok(tasks)
     }
 }
 
 impl Reloadable for AuraZbus {
     async fn reload(&mut self) -> Result<(), RogError> {
         self.0.fix_ally_power().await?;
-        debug!("reloading keyboard mode");
+        debug!("reloading keyboard mode, brightness, and power states");
         let mut config = self.0.lock_config().await;
-        self.0.write_current_config_mode(&mut config).await?;
-        debug!("reloading power states");
-        self.0
-            .set_power_states(&config)
-            .await
-            .map_err(|err| warn!("{err}"))
-            .ok();
+        self.0.apply_power_policy(&mut config).await?;
         Ok(())
     }
 }
@@ -4,16 +4,16 @@
 // - Add it to Zbus server
 // - If udev sees device removed then remove the zbus path
 
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use dmi_id::DMIID;
-use futures_lite::future::block_on;
 use futures_util::lock::Mutex;
 use log::{debug, error, info, warn};
-use mio::{Events, Interest, Poll, Token};
 use rog_platform::error::PlatformError;
 use rog_platform::hid_raw::HidRaw;
-use udev::{Device, MonitorBuilder};
+use tokio::time::{sleep_until, Instant};
+use udev::{Device, Event};
 use zbus::zvariant::{ObjectPath, OwnedObjectPath};
 use zbus::Connection;
 
@@ -21,8 +21,13 @@ use crate::aura_laptop::trait_impls::AuraZbus;
 use crate::aura_scsi::trait_impls::ScsiZbus;
 use crate::aura_slash::trait_impls::SlashZbus;
 use crate::aura_types::DeviceHandle;
+use crate::device_registry::{DeviceInfo, DeviceKind, DeviceRegistry};
 use crate::error::RogError;
-use crate::ASUS_ZBUS_PATH;
+use crate::hotplug::{
+    Debouncer, HotplugAction, HotplugEvent, PendingDevice, Subsystem, UdevMonitor,
+};
+use crate::transport::SharedTransport;
+use crate::{TaskHandles, ASUS_ZBUS_PATH};
 
 const MOD_NAME: &str = "aura";
 
@@ -80,188 +85,293 @@ fn dev_prop_matches(dev: &Device, prop: &str, value: &str) -> bool {
     false
 }
 
+/// The dbus path a udev event belongs to, shared by all the endpoints of one
+/// USB device
+fn event_dbus_path(subsystem: Subsystem, event: &Event) -> Option<OwnedObjectPath> {
+    match subsystem {
+        Subsystem::Hidraw => event
+            .parent_with_subsystem_devtype("usb", "usb_device")
+            .ok()?
+            .and_then(|parent| dbus_path_for_dev(&parent)),
+        Subsystem::Block => {
+            if !dev_prop_matches(event, "ID_VENDOR_ID", "0b05") {
+                return None;
+            }
+            event
+                .property_value("ID_SERIAL_SHORT")
+                .map(|serial| dbus_path_for_scsi(&serial.to_string_lossy()))
+        }
+    }
+}
+
+fn scan_subsystem(subsystem: &str) -> Result<Vec<Device>, RogError> {
+    let mut enumerator = udev::Enumerator::new().map_err(|err| {
+        warn!("{}", err);
+        PlatformError::Udev("enumerator failed".into(), err)
+    })?;
+
+    enumerator.match_subsystem(subsystem).map_err(|err| {
+        warn!("{}", err);
+        PlatformError::Udev("match_subsystem failed".into(), err)
+    })?;
+
+    Ok(enumerator
+        .scan_devices()
+        .map_err(|e| PlatformError::IoPath("enumerator".to_owned(), e))?
+        .collect())
+}
+
+/// The parts of a hidraw endpoint needed to create a device. These are taken
+/// before any await as a `udev::Device` can't be sent between threads.
+struct HidEndpoint {
+    transport: SharedTransport,
+    syspath: PathBuf,
+    prod_id: String,
+    serial: String,
+    dbus_path: OwnedObjectPath,
+}
+
+impl HidEndpoint {
+    fn from_device(device: Device) -> Option<Self> {
+        let usb_device = device
+            .parent_with_subsystem_devtype("usb", "usb_device")
+            .ok()??;
+        if usb_device.attribute_value("idVendor")? != "0b05" {
+            debug!("Not ASUS vendor ID");
+            return None;
+        }
+        // Almost all devices are identified by the productId
+        let prod_id = usb_device
+            .attribute_value("idProduct")?
+            .to_string_lossy()
+            .to_string();
+        let serial = usb_device
+            .attribute_value("serial")
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let dbus_path = dbus_path_for_dev(&usb_device)?;
+        let syspath = device.syspath().to_owned();
+        // Use the top-level endpoint, not the parent
+        let hidraw = HidRaw::from_device(device)
+            .map_err(|e| debug!("Couldn't open {syspath:?}: {e}"))
+            .ok()?;
+        Some(Self {
+            transport: Arc::new(Mutex::new(hidraw)),
+            syspath,
+            prod_id,
+            serial,
+            dbus_path,
+        })
+    }
+}
+
+/// As `HidEndpoint`, for SCSI block devices
+struct ScsiEndpoint {
+    dev_node: String,
+    syspath: PathBuf,
+    prod_id: String,
+    serial: String,
+    dbus_path: OwnedObjectPath,
+}
+
+impl ScsiEndpoint {
+    fn from_device(device: &Device) -> Option<Self> {
+        // "ID_MODEL_ID" "1932"
+        // "ID_VENDOR_ID" "0b05"
+        if !dev_prop_matches(device, "ID_VENDOR_ID", "0b05") {
+            return None;
+        }
+        let Some(serial) = device.property_value("ID_SERIAL_SHORT") else {
+            debug!("No serial for SCSI device: {:?}", device.devpath());
+            return None;
+        };
+        let serial = serial.to_string_lossy().to_string();
+        Some(Self {
+            dev_node: device.devnode()?.to_str()?.to_string(),
+            syspath: device.syspath().to_owned(),
+            prod_id: device
+                .property_value("ID_MODEL_ID")
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            dbus_path: dbus_path_for_scsi(&serial),
+            serial,
+        })
+    }
+}
+
 /// A device.
 ///
 /// Each controller within should track its dbus path so it can be removed if
 /// required.
 pub struct AsusDevice {
     device: DeviceHandle,
-    dbus_path: OwnedObjectPath,
+    info: DeviceInfo,
+    /// The hidraw endpoint or block device, used to match removal events
+    syspath: Option<PathBuf>,
+    /// Aborted when the device is removed
+    tasks: TaskHandles,
+}
+
+impl AsusDevice {
+    fn new(
+        device: DeviceHandle,
+        dbus_path: OwnedObjectPath,
+        prod_id: &str,
+        serial: &str,
+        tasks: TaskHandles,
+    ) -> Self {
+        let kind = DeviceKind::from_handle(&device).unwrap_or(DeviceKind::Aura);
+        Self {
+            device,
+            info: DeviceInfo::new(kind, prod_id, serial, dbus_path),
+            syspath: None,
+            tasks,
+        }
+    }
+
+    fn with_syspath(mut self, syspath: PathBuf) -> Self {
+        self.syspath = Some(syspath);
+        self
+    }
+
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
 }
 
+/// Owns every device. Once started with `run()` all hotplug events are
+/// handled, one at a time, on the daemon's runtime.
 pub struct DeviceManager {
-    _dbus_connection: Connection,
+    connection: Connection,
+    registry: DeviceRegistry,
+    monitor: UdevMonitor,
+    devices: Vec<AsusDevice>,
 }
 
 impl DeviceManager {
-    async fn init_hid_devices(
-        connection: &Connection,
-        device: Device,
-    ) -> Result<Vec<AsusDevice>, RogError> {
-        let mut devices = Vec::new();
-        if let Some(usb_device) = device.parent_with_subsystem_devtype("usb", "usb_device")? {
-            if let Some(usb_id) = usb_device.attribute_value("idProduct") {
-                if let Some(vendor_id) = usb_device.attribute_value("idVendor") {
-                    if vendor_id != "0b05" {
-                        debug!("Not ASUS vendor ID");
-                        return Ok(devices);
-                    }
-                    // Almost all devices are identified by the productId.
-                    // So let's see what we have and:
-                    // 1. Generate an interface path
-                    // 2. Create the device
-                    // Use the top-level endpoint, not the parent
-                    if let Ok(hidraw) = HidRaw::from_device(device) {
-                        debug!("Testing device {usb_id:?}");
-                        let dev = Arc::new(Mutex::new(hidraw));
-                        // SLASH DEVICE
-                        if let Ok(dev_type) = DeviceHandle::new_slash_hid(
-                            dev.clone(),
-                            usb_id.to_str().unwrap_or_default(),
-                        )
-                        .await
-                        {
-                            if let DeviceHandle::Slash(slash) = dev_type.clone() {
-                                let path =
-                                    dbus_path_for_dev(&usb_device).unwrap_or(dbus_path_for_slash());
-                                let ctrl = SlashZbus::new(slash);
-                                ctrl.start_tasks(connection, path.clone()).await.unwrap();
-                                devices.push(AsusDevice {
-                                    device: dev_type,
-                                    dbus_path: path,
-                                });
-                            }
-                        }
-                        // AURA LAPTOP DEVICE
-                        if let Ok(dev_type) = DeviceHandle::maybe_laptop_aura(
-                            Some(dev),
-                            usb_id.to_str().unwrap_or_default(),
-                        )
-                        .await
-                        {
-                            if let DeviceHandle::Aura(aura) = dev_type.clone() {
-                                let path =
-                                    dbus_path_for_dev(&usb_device).unwrap_or(dbus_path_for_tuf());
-                                let ctrl = AuraZbus::new(aura);
-                                ctrl.start_tasks(connection, path.clone()).await.unwrap();
-                                devices.push(AsusDevice {
-                                    device: dev_type,
-                                    dbus_path: path,
-                                });
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Ok(devices)
+    fn has_device(&self, path: &OwnedObjectPath, kind: DeviceKind) -> bool {
+        self.devices
+            .iter()
+            .any(|dev| dev.info.dbus_path == *path && dev.info.kind == kind)
     }
 
-    /// To be called on daemon startup
-    async fn init_all_hid(connection: &Connection) -> Result<Vec<AsusDevice>, RogError> {
-        // track and ensure we use only one hidraw per prod_id
-        // let mut interfaces = HashSet::new();
-        let mut devices: Vec<AsusDevice> = Vec::new();
-
-        let mut enumerator = udev::Enumerator::new().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("enumerator failed".into(), err)
-        })?;
-
-        enumerator.match_subsystem("hidraw").map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("match_subsystem failed".into(), err)
-        })?;
-
-        for device in enumerator
-            .scan_devices()
-            .map_err(|e| PlatformError::IoPath("enumerator".to_owned(), e))?
-        {
-            devices.append(&mut Self::init_hid_devices(connection, device).await?);
-        }
-
-        Ok(devices)
+    async fn push_device(&mut self, device: AsusDevice) {
+        info!(
+            "Added {:?} device {} at {}",
+            device.info.kind, device.info.prod_id, device.info.dbus_path
+        );
+        self.registry
+            .add(&self.connection, device.info.clone(), device.device.clone())
+            .await;
+        self.devices.push(device);
     }
 
-    async fn init_scsi(
-        connection: &Connection,
-        device: &Device,
-        path: OwnedObjectPath,
-    ) -> Option<AsusDevice> {
-        // "ID_MODEL_ID" "1932"
-        // "ID_VENDOR_ID" "0b05"
-        if dev_prop_matches(device, "ID_VENDOR_ID", "0b05") {
-            if let Some(dev_node) = device.devnode() {
-                let prod_id = device
-                    .property_value("ID_MODEL_ID")
-                    .unwrap_or_default()
-                    .to_string_lossy();
-                if let Ok(dev_type) =
-                    DeviceHandle::maybe_scsi(dev_node.as_os_str().to_str().unwrap(), &prod_id).await
+    /// Try each kind of device on the endpoint. A USB device has several
+    /// endpoints, those after the first that works are skipped.
+    async fn add_hid_endpoint(&mut self, endpoint: HidEndpoint) {
+        let path = endpoint.dbus_path.clone();
+        debug!(
+            "Testing device {} at {:?}",
+            endpoint.prod_id, endpoint.syspath
+        );
+        // SLASH DEVICE
+        if !self.has_device(&path, DeviceKind::Slash) {
+            if let Ok(DeviceHandle::Slash(slash)) =
+                DeviceHandle::new_slash_hid(endpoint.transport.clone(), &endpoint.prod_id).await
+            {
+                match SlashZbus::new(slash.clone())
+                    .start_tasks(&self.connection, path.clone())
+                    .await
                 {
-                    if let DeviceHandle::Scsi(scsi) = dev_type.clone() {
-                        let ctrl = ScsiZbus::new(scsi);
-                        ctrl.start_tasks(connection, path.clone()).await.unwrap();
-                        return Some(AsusDevice {
-                            device: dev_type,
-                            dbus_path: path,
-                        });
+                    Ok(tasks) => {
+                        let dev = AsusDevice::new(
+                            DeviceHandle::Slash(slash),
+                            path.clone(),
+                            &endpoint.prod_id,
+                            &endpoint.serial,
+                            tasks,
+                        );
+                        self.push_device(dev.with_syspath(endpoint.syspath.clone()))
+                            .await;
                     }
+                    Err(e) => error!("Couldn't start Slash at {path}: {e}"),
+                }
+            }
+        }
+        // AURA LAPTOP DEVICE
+        if !self.has_device(&path, DeviceKind::Aura) {
+            if let Ok(DeviceHandle::Aura(aura)) =
+                DeviceHandle::maybe_laptop_aura(Some(endpoint.transport), &endpoint.prod_id).await
+            {
+                match AuraZbus::new(aura.clone())
+                    .start_tasks(&self.connection, path.clone())
+                    .await
+                {
+                    Ok(tasks) => {
+                        let dev = AsusDevice::new(
+                            DeviceHandle::Aura(aura),
+                            path.clone(),
+                            &endpoint.prod_id,
+                            &endpoint.serial,
+                            tasks,
+                        );
+                        self.push_device(dev.with_syspath(endpoint.syspath)).await;
+                    }
+                    Err(e) => error!("Couldn't start Aura at {path}: {e}"),
                 }
             }
         }
-        None
     }
 
-    async fn init_all_scsi(connection: &Connection) -> Result<Vec<AsusDevice>, RogError> {
-        // track and ensure we use only one hidraw per prod_id
-        // let mut interfaces = HashSet::new();
-        let mut devices: Vec<AsusDevice> = Vec::new();
-
-        let mut enumerator = udev::Enumerator::new().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("enumerator failed".into(), err)
-        })?;
-
-        enumerator.match_subsystem("block").map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("match_subsystem failed".into(), err)
-        })?;
-
-        let mut found = Vec::new();
-        for device in enumerator
-            .scan_devices()
-            .map_err(|e| PlatformError::IoPath("enumerator".to_owned(), e))?
+    async fn add_scsi_endpoint(&mut self, endpoint: ScsiEndpoint) {
+        let path = endpoint.dbus_path.clone();
+        if self.has_device(&path, DeviceKind::Scsi) {
+            return;
+        }
+        if let Ok(DeviceHandle::Scsi(scsi)) =
+            DeviceHandle::maybe_scsi(&endpoint.dev_node, &endpoint.prod_id).await
         {
-            if let Some(serial) = device.property_value("ID_SERIAL_SHORT") {
-                let serial = serial.to_string_lossy().to_string();
-                let path = dbus_path_for_scsi(&serial);
-                if found.contains(&path) {
-                    continue;
+            match ScsiZbus::new(scsi.clone())
+                .start_tasks(&self.connection, path.clone())
+                .await
+            {
+                Ok(tasks) => {
+                    let dev = AsusDevice::new(
+                        DeviceHandle::Scsi(scsi),
+                        path,
+                        &endpoint.prod_id,
+                        &endpoint.serial,
+                        tasks,
+                    );
+                    self.push_device(dev.with_syspath(endpoint.syspath)).await;
                 }
-
-                if let Some(dev) = Self::init_scsi(connection, &device, path.clone()).await {
-                    devices.push(dev);
-                    found.push(path);
-                }
-            } else {
-                debug!("No serial for SCSI device: {:?}", device.devpath());
+                Err(e) => error!("Couldn't start SCSI aura at {path}: {e}"),
             }
         }
-
-        Ok(devices)
     }
 
-    pub async fn find_all_devices(connection: &Connection) -> Vec<AsusDevice> {
-        let mut devices: Vec<AsusDevice> = Vec::new();
+    /// To be called on daemon startup
+    async fn find_all_devices(&mut self) {
         // HID first, always
-        if let Ok(devs) = &mut Self::init_all_hid(connection).await {
-            devices.append(devs);
+        match scan_subsystem("hidraw") {
+            Ok(devices) => {
+                let endpoints: Vec<HidEndpoint> = devices
+                    .into_iter()
+                    .filter_map(HidEndpoint::from_device)
+                    .collect();
+                for endpoint in endpoints {
+                    self.add_hid_endpoint(endpoint).await;
+                }
+            }
+            Err(e) => warn!("Couldn't scan hidraw devices: {e}"),
         }
+
         // USB after, need to check if HID picked something up and if so, skip it
         let mut do_slash = true;
         let mut do_kb_backlight = true;
-        for dev in devices.iter() {
+        for dev in self.devices.iter() {
             if matches!(dev.device, DeviceHandle::Slash(_)) {
                 do_slash = false;
             }
@@ -271,15 +381,24 @@ impl DeviceManager {
         }
 
         if do_slash {
-            if let Ok(dev_type) = DeviceHandle::new_slash_usb().await {
-                if let DeviceHandle::Slash(slash) = dev_type.clone() {
-                    let path = dbus_path_for_slash();
-                    let ctrl = SlashZbus::new(slash);
-                    ctrl.start_tasks(connection, path.clone()).await.unwrap();
-                    devices.push(AsusDevice {
-                        device: dev_type,
-                        dbus_path: path,
-                    });
+            if let Ok(DeviceHandle::Slash(slash)) = DeviceHandle::new_slash_usb().await {
+                let path = dbus_path_for_slash();
+                let prod_id = slash
+                    .lock_config()
+                    .await
+                    .slash_type
+                    .prod_id_str()
+                    .to_string();
+                match SlashZbus::new(slash.clone())
+                    .start_tasks(&self.connection, path.clone())
+                    .await
+                {
+                    Ok(tasks) => {
+                        let dev =
+                            AsusDevice::new(DeviceHandle::Slash(slash), path, &prod_id, "", tasks);
+                        self.push_device(dev).await;
+                    }
+                    Err(e) => error!("Couldn't start Slash at {path}: {e}"),
                 }
             } else {
                 info!("Tested device was not Slash");
@@ -297,189 +416,179 @@ impl DeviceManager {
             );
             if product_name.contains("TUF") || product_family.contains("TUF") {
                 info!("TUF laptop, try using sysfs backlight control");
-                if let Ok(dev_type) = DeviceHandle::maybe_laptop_aura(None, "tuf").await {
-                    if let DeviceHandle::Aura(aura) = dev_type.clone() {
-                        let path = dbus_path_for_tuf();
-                        let ctrl = AuraZbus::new(aura);
-                        ctrl.start_tasks(connection, path.clone()).await.unwrap();
-                        devices.push(AsusDevice {
-                            device: dev_type,
-                            dbus_path: path,
-                        });
+                if let Ok(DeviceHandle::Aura(aura)) =
+                    DeviceHandle::maybe_laptop_aura(None, "tuf").await
+                {
+                    let path = dbus_path_for_tuf();
+                    match AuraZbus::new(aura.clone())
+                        .start_tasks(&self.connection, path.clone())
+                        .await
+                    {
+                        Ok(tasks) => {
+                            let dev =
+                                AsusDevice::new(DeviceHandle::Aura(aura), path, "tuf", "", tasks);
+                            self.push_device(dev).await;
+                        }
+                        Err(e) => error!("Couldn't start Aura at {path}: {e}"),
                     }
                 }
             }
         }
 
-        if let Ok(devs) = &mut Self::init_all_scsi(connection).await {
-            devices.append(devs);
+        match scan_subsystem("block") {
+            Ok(devices) => {
+                let endpoints: Vec<ScsiEndpoint> = devices
+                    .iter()
+                    .filter_map(ScsiEndpoint::from_device)
+                    .collect();
+                for endpoint in endpoints {
+                    self.add_scsi_endpoint(endpoint).await;
+                }
+            }
+            Err(e) => warn!("Couldn't scan block devices: {e}"),
         }
+    }
 
-        devices
+    /// Stop and remove every device at `path`
+    async fn remove_devices(&mut self, path: &OwnedObjectPath) {
+        let (removed, kept) = std::mem::take(&mut self.devices)
+            .into_iter()
+            .partition(|dev| dev.info.dbus_path == *path);
+        self.devices = kept;
+        let removed: Vec<AsusDevice> = removed;
+        if removed.is_empty() {
+            return;
+        }
+
+        let object_server = self.connection.object_server();
+        for dev in removed {
+            dev.tasks.abort();
+            let res = match dev.device {
+                DeviceHandle::Aura(_) => object_server.remove::<AuraZbus, _>(path).await,
+                DeviceHandle::Slash(_) => object_server.remove::<SlashZbus, _>(path).await,
+                DeviceHandle::Scsi(_) => object_server.remove::<ScsiZbus, _>(path).await,
+                _ => {
+                    warn!("No interface to remove for device at {path}");
+                    Ok(false)
+                }
+            };
+            match res {
+                Ok(_) => info!("DeviceManager removed: {path}"),
+                Err(e) => error!("Couldn't remove interface at {path}: {e}"),
+            }
+        }
+        self.registry.remove(&self.connection, path).await;
     }
 
+    /// Re-open each endpoint from its syspath and add it
+    async fn add_endpoints(&mut self, pending: &PendingDevice) {
+        for syspath in &pending.syspaths {
+            match pending.subsystem {
+                Subsystem::Hidraw => {
+                    let endpoint = Device::from_syspath(syspath)
+                        .ok()
+                        .and_then(HidEndpoint::from_device);
+                    if let Some(endpoint) = endpoint {
+                        self.add_hid_endpoint(endpoint).await;
+                    }
+                }
+                Subsystem::Block => {
+                    let endpoint = Device::from_syspath(syspath)
+                        .ok()
+                        .and_then(|dev| ScsiEndpoint::from_device(&dev));
+                    if let Some(endpoint) = endpoint {
+                        self.add_scsi_endpoint(endpoint).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_pending(&mut self, pending: PendingDevice) {
+        let path = &pending.dbus_path;
+        let known = self.devices.iter().any(|dev| dev.info.dbus_path == *path);
+        debug!("Hotplug {:?} for {path}", pending.action);
+        match pending.action {
+            HotplugAction::Change if known => {
+                debug!("Ignoring change event for existing device {path}");
+            }
+            HotplugAction::Add | HotplugAction::Change => self.add_endpoints(&pending).await,
+            HotplugAction::Remove => self.remove_devices(path).await,
+            HotplugAction::Replug => {
+                self.remove_devices(path).await;
+                self.add_endpoints(&pending).await;
+            }
+        }
+    }
+
+    /// Removal events can't always be traced to the USB device by udev, as
+    /// its attributes are already gone. Fall back to the endpoint the device
+    /// was created from.
+    fn resolve_event(&self, mut event: HotplugEvent) -> HotplugEvent {
+        if event.dbus_path.is_none() {
+            event.dbus_path = self
+                .devices
+                .iter()
+                .find(|dev| dev.syspath.as_ref() == Some(&event.syspath))
+                .map(|dev| dev.info.dbus_path.clone());
+        }
+        event
+    }
+
+    /// Finds all devices and registers them. `run()` must then be spawned to
+    /// handle hotplug.
     pub async fn new(connection: Connection) -> Result<Self, RogError> {
-        let conn_copy = connection.clone();
-        let devices = Self::find_all_devices(&conn_copy).await;
-        info!("Found {} valid devices on startup", devices.len());
-        let devices = Arc::new(Mutex::new(devices));
-        let manager = Self {
-            _dbus_connection: connection,
+        let registry = DeviceRegistry::new();
+        registry.start_tasks(&connection).await?;
+        // Monitor before scanning so that no events are missed in between
+        let monitor = UdevMonitor::new()?;
+
+        let mut manager = Self {
+            connection,
+            registry,
+            monitor,
+            devices: Vec::new(),
         };
-
+        manager.find_all_devices().await;
+        info!("Found {} valid devices on startup", manager.devices.len());
         // TODO: The /sysfs/ LEDs don't cause events, so they need to be manually
         // checked for and added
+        Ok(manager)
+    }
 
-        std::thread::spawn(move || {
-            let mut monitor = MonitorBuilder::new()?.listen()?;
-            let mut poll = Poll::new()?;
-            let mut events = Events::with_capacity(1024);
-            poll.registry()
-                .register(&mut monitor, Token(0), Interest::READABLE)?;
-
-            let rt = tokio::runtime::Runtime::new().expect("Unable to create Runtime");
-            let _enter = rt.enter();
-            loop {
-                if poll.poll(&mut events, None).is_err() {
-                    continue;
-                }
-                for event in monitor.iter() {
-                    let action = event
-                        .action()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .to_string();
-
-                    let subsys = if let Some(subsys) = event.subsystem() {
-                        subsys.to_string_lossy().to_string()
-                    } else {
-                        continue;
-                    };
-
-                    let devices = devices.clone();
-                    let conn_copy = conn_copy.clone();
-                    block_on(async move {
-                        // SCSCI devs
-                        if subsys == "block" {
-                            if action == "remove" {
-                                if let Some(serial) =
-                                    event.device().property_value("ID_SERIAL_SHORT")
-                                {
-                                    let serial = serial.to_string_lossy().to_string();
-                                    let path = dbus_path_for_scsi(&serial);
-
-                                    let index = if let Some(index) = devices
-                                        .lock()
-                                        .await
-                                        .iter()
-                                        .position(|dev| dev.dbus_path == path)
-                                    {
-                                        index
-                                    } else {
-                                        if dev_prop_matches(&event.device(), "ID_VENDOR_ID", "0b05")
-                                        {
-                                            warn!("No device for dbus path: {path:?}");
-                                        }
-                                        return Ok(());
-                                    };
-                                    info!("removing: {path:?}");
-                                    let dev = devices.lock().await.remove(index);
-                                    let path = path.clone();
-                                    if let DeviceHandle::Scsi(_) = dev.device {
-                                        conn_copy
-                                            .object_server()
-                                            .remove::<ScsiZbus, _>(&path)
-                                            .await?;
-                                    }
-                                }
-                            } else if action == "add" {
-                                let evdev = event.device();
-                                if let Some(serial) = evdev.property_value("ID_SERIAL_SHORT") {
-                                    let serial = serial.to_string_lossy().to_string();
-                                    let path = dbus_path_for_scsi(&serial);
-                                    if let Some(new_devs) =
-                                        Self::init_scsi(&conn_copy, &evdev, path).await
-                                    {
-                                        devices.lock().await.append(&mut vec![new_devs]);
-                                    }
-                                }
-                            };
-                        }
+    /// The registry served at `xyz.ljones.Asusd`
+    pub fn registry(&self) -> &DeviceRegistry {
+        &self.registry
+    }
 
-                        if subsys == "hidraw" {
-                            if let Some(parent) =
-                                event.parent_with_subsystem_devtype("usb", "usb_device")?
-                            {
-                                if action == "remove" {
-                                    if let Some(path) = dbus_path_for_dev(&parent) {
-                                        // Find the indexs of devices matching the path
-                                        let removals: Vec<usize> = devices
-                                            .lock()
-                                            .await
-                                            .iter()
-                                            .enumerate()
-                                            .filter_map(|(i, dev)| {
-                                                if dev.dbus_path == path {
-                                                    Some(i)
-                                                } else {
-                                                    None
-                                                }
-                                            })
-                                            .collect();
-                                        if removals.is_empty() {
-                                            return Ok(());
-                                        }
-                                        info!("removing: {path:?}");
-                                        // Iter in reverse so as to not screw up indexing
-                                        for index in removals.iter().rev() {
-                                            let dev = devices.lock().await.remove(*index);
-                                            let path = path.clone();
-                                            let res = match dev.device {
-                                                DeviceHandle::Aura(_) => {
-                                                    conn_copy
-                                                        .object_server()
-                                                        .remove::<AuraZbus, _>(&path)
-                                                        .await?
-                                                }
-                                                DeviceHandle::Slash(_) => {
-                                                    conn_copy
-                                                        .object_server()
-                                                        .remove::<SlashZbus, _>(&path)
-                                                        .await?
-                                                }
-                                                DeviceHandle::Scsi(_) => {
-                                                    conn_copy
-                                                        .object_server()
-                                                        .remove::<ScsiZbus, _>(&path)
-                                                        .await?
-                                                }
-                                                _ => todo!(),
-                                            };
-                                            info!("AuraManager removed: {path:?}, {res}");
-                                        }
-                                    }
-                                } else if action == "add" {
-                                    let evdev = event.device();
-                                    if let Ok(mut new_devs) =
-                                        Self::init_hid_devices(&conn_copy, evdev)
-                                            .await
-                                            .map_err(|e| error!("Couldn't add new device: {e:?}"))
-                                    {
-                                        devices.lock().await.append(&mut new_devs);
-                                    }
-                                };
+    /// Handle hotplug events until the udev monitor fails
+    pub async fn run(mut self) {
+        let mut debounce = Debouncer::new();
+        loop {
+            let deadline = debounce.next_deadline();
+            tokio::select! {
+                events = self
+                    .monitor
+                    .next_events(|event| HotplugEvent::from_udev(event, event_dbus_path)) => {
+                    match events {
+                        Ok(events) => {
+                            for event in events {
+                                let event = self.resolve_event(event);
+                                debounce.push(event, Instant::now());
                             }
                         }
-                        Ok::<(), RogError>(())
-                    })
-                    .map_err(|e| error!("{e:?}"))
-                    .ok();
+                        Err(e) => {
+                            error!("udev monitor failed, hotplug is disabled: {e}");
+                            return;
+                        }
+                    }
+                }
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    for pending in debounce.take_ready(Instant::now()) {
+                        self.handle_pending(pending).await;
+                    }
                 }
             }
-            // Required for return type on spawn
-            #[allow(unreachable_code)]
-            Ok::<(), RogError>(())
-        });
-        Ok(manager)
+        }
     }
 }
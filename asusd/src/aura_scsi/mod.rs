@@ -1,33 +1,66 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use config::ScsiConfig;
-use futures_util::lock::{Mutex, MutexGuard};
-use rog_scsi::{AuraEffect, Device, Task};
+use rog_aura::AuraDeviceType;
+use rog_scsi::{AuraEffect, AuraMode};
+use tokio::sync::watch;
 
 use crate::error::RogError;
+use crate::state::{ConfigGuard, SharedConfig};
+use crate::transport::SharedTransport;
 
 pub mod config;
 pub mod trait_impls;
 
+/// The parts of `ScsiConfig` read by dbus properties
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScsiState {
+    pub dev_type: AuraDeviceType,
+    pub enabled: bool,
+    pub current_mode: AuraMode,
+    pub modes: BTreeMap<AuraMode, AuraEffect>,
+}
+
+impl From<&ScsiConfig> for ScsiState {
+    fn from(config: &ScsiConfig) -> Self {
+        Self {
+            dev_type: config.dev_type,
+            enabled: config.enabled,
+            current_mode: config.current_mode,
+            modes: config.modes.clone(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ScsiAura {
-    device: Arc<Mutex<Device>>,
-    config: Arc<Mutex<ScsiConfig>>,
+    device: SharedTransport,
+    config: Arc<SharedConfig<ScsiConfig, ScsiState>>,
 }
 
 impl ScsiAura {
-    pub fn new(device: Arc<Mutex<Device>>, config: Arc<Mutex<ScsiConfig>>) -> Self {
-        Self { device, config }
+    pub fn new(device: SharedTransport, config: ScsiConfig) -> Self {
+        Self {
+            device,
+            config: Arc::new(SharedConfig::new(config)),
+        }
     }
 
-    pub async fn lock_config(&self) -> MutexGuard<'_, ScsiConfig> {
+    pub async fn lock_config(&self) -> ConfigGuard<'_, ScsiConfig, ScsiState> {
         self.config.lock().await
     }
 
+    /// The state as of the last config change, this never waits on a writer.
+    /// Don't hold this across an await.
+    pub fn state(&self) -> watch::Ref<'_, ScsiState> {
+        self.config.state()
+    }
+
     pub async fn write_effect(&self, effect: &AuraEffect) -> Result<(), RogError> {
-        let tasks: Vec<Task> = effect.into();
-        for task in &tasks {
-            self.device.lock().await.perform(task).ok();
+        let mut device = self.device.lock().await;
+        for pkt in &effect.to_packets() {
+            device.write_bytes(pkt).ok();
         }
         Ok(())
     }
@@ -43,3 +76,34 @@ impl ScsiAura {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rog_scsi::{AuraEffect, AuraMode, SCSI_CDB_LEN};
+
+    use super::config::ScsiConfig;
+    use super::ScsiAura;
+    use crate::transport::RecordingTransport;
+
+    #[tokio::test]
+    async fn static_effect_packets() {
+        let rec = RecordingTransport::new();
+        let scsi = ScsiAura::new(rec.shared(), ScsiConfig::default());
+        scsi.write_effect(&AuraEffect::default_with_mode(AuraMode::Static))
+            .await
+            .unwrap();
+
+        let packets = rec.packets();
+        // mode, 4 colours, apply, save
+        assert_eq!(packets.len(), 7);
+        // mode register 0x8021, one data byte
+        assert_eq!(&packets[0][..5], &[0xec, 0x41, 0x53, 0x80, 0x21]);
+        assert_eq!(packets[0][13], 1);
+        assert_eq!(packets[0].len(), SCSI_CDB_LEN + 1);
+        // colour registers take 3 bytes
+        assert_eq!(packets[1].len(), SCSI_CDB_LEN + 3);
+        // apply then save
+        assert_eq!(packets[5][SCSI_CDB_LEN], 0x01);
+        assert_eq!(packets[6][SCSI_CDB_LEN], 0xaa);
+    }
+}
@@ -1,15 +1,17 @@
 use std::collections::BTreeMap;
 
 use config_traits::StdConfig;
-use log::error;
+use log::{debug, error, warn};
 use rog_aura::AuraDeviceType;
 use rog_scsi::{AuraEffect, AuraMode};
 use zbus::fdo::Error as ZbErr;
+use zbus::object_server::SignalEmitter;
 use zbus::zvariant::OwnedObjectPath;
 use zbus::{interface, Connection};
 
 use super::ScsiAura;
 use crate::error::RogError;
+use crate::{CtrlTask, TaskHandles};
 
 #[derive(Clone)]
 pub struct ScsiZbus(ScsiAura);
@@ -19,18 +21,22 @@ impl ScsiZbus {
         Self(scsi)
     }
 
+    /// Serve the device at `path` and start the resume task. The returned
+    /// handles must be aborted when the device is removed.
     pub async fn start_tasks(
         self,
         connection: &Connection,
         path: OwnedObjectPath,
-    ) -> Result<(), RogError> {
+    ) -> Result<TaskHandles, RogError> {
+        let task = self.clone();
+        let signal_ctx = SignalEmitter::new(connection, path.clone())?.into_owned();
         connection
             .object_server()
             .at(path.clone(), self)
             .await
             .map_err(|e| error!("Couldn't add server at path: {path}, {e:?}"))
             .ok();
-        Ok(())
+        task.create_tasks(signal_ctx).await
     }
 }
 
@@ -39,14 +45,13 @@ impl ScsiZbus {
     /// Return the device type for this Aura keyboard
     #[zbus(property)]
     async fn device_type(&self) -> AuraDeviceType {
-        self.0.config.lock().await.dev_type
+        self.0.state().dev_type
     }
 
     /// Get enabled or not
     #[zbus(property)]
     async fn enabled(&self) -> bool {
-        let lock = self.0.lock_config().await;
-        lock.enabled
+        self.0.state().enabled
     }
 
     /// Set enabled true or false
@@ -59,8 +64,7 @@ impl ScsiZbus {
 
     #[zbus(property)]
     async fn led_mode(&self) -> u8 {
-        let config = self.0.lock_config().await;
-        config.current_mode as u8
+        self.0.state().current_mode as u8
     }
 
     #[zbus(property)]
@@ -82,15 +86,10 @@ impl ScsiZbus {
     /// The current mode data
     #[zbus(property)]
     async fn led_mode_data(&self) -> Result<AuraEffect, ZbErr> {
-        // entirely possible to deadlock here, so use try instead of lock()
-        if let Some(config) = self.0.config.try_lock() {
-            let mode = config.current_mode;
-            match config.modes.get(&mode) {
-                Some(effect) => Ok(effect.clone()),
-                None => Err(ZbErr::Failed("Could not get the current effect".into())),
-            }
-        } else {
-            Err(ZbErr::Failed("Aura control couldn't lock self".to_string()))
+        let state = self.0.state();
+        match state.modes.get(&state.current_mode) {
+            Some(effect) => Ok(effect.clone()),
+            None => Err(ZbErr::Failed("Could not get the current effect".into())),
         }
     }
 
@@ -110,7 +109,40 @@ impl ScsiZbus {
 
     /// Get the data set for every mode available
     async fn all_mode_data(&self) -> BTreeMap<AuraMode, AuraEffect> {
-        let config = self.0.config.lock().await;
-        config.modes.clone()
+        self.0.state().modes.clone()
+    }
+}
+
+impl CtrlTask for ScsiZbus {
+    fn zbus_path() -> &'static str {
+        "/xyz/ljones"
+    }
+
+    async fn create_tasks(
+        &self,
+        signal_ctx: SignalEmitter<'static>,
+    ) -> Result<TaskHandles, RogError> {
+        let scsi = self.0.clone();
+        let tasks = self
+            .create_sys_event_tasks(
+                signal_ctx.connection(),
+                move |sleeping| {
+                    let scsi = scsi.clone();
+                    async move {
+                        // The enclosure may have been power cycled over suspend
+                        if !sleeping {
+                            debug!("SCSI aura restoring mode after resume");
+                            scsi.do_initialization()
+                                .await
+                                .unwrap_or_else(|e| warn!("SCSI aura init failed: {e}"));
+                        }
+                    }
+                },
+                move |_| async {},
+                move |_| async {},
+                move |_| async {},
+            )
+            .await?;
+        Ok(tasks)
     }
 }
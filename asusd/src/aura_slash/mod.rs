@@ -2,29 +2,26 @@ use std::sync::Arc;
 
 use config::SlashConfig;
 use futures_util::lock::{Mutex, MutexGuard};
-use rog_platform::hid_raw::HidRaw;
-use rog_platform::usb_raw::USBRaw;
-use rog_slash::usb::{slash_pkt_enable, slash_pkt_init, slash_pkt_options, slash_pkt_set_mode};
+use rog_slash::usb::{
+    slash_pkt_enable, slash_pkt_init, slash_pkt_options, slash_pkt_save, slash_pkt_set_mode,
+};
 
 use crate::error::RogError;
+use crate::transport::SharedTransport;
 
 pub mod config;
 pub mod trait_impls;
 
 #[derive(Debug, Clone)]
 pub struct Slash {
-    hid: Option<Arc<Mutex<HidRaw>>>,
-    usb: Option<Arc<Mutex<USBRaw>>>,
+    /// Either the hidraw node or the raw USB device
+    transport: SharedTransport,
     config: Arc<Mutex<SlashConfig>>,
 }
 
 impl Slash {
-    pub fn new(
-        hid: Option<Arc<Mutex<HidRaw>>>,
-        usb: Option<Arc<Mutex<USBRaw>>>,
-        config: Arc<Mutex<SlashConfig>>,
-    ) -> Self {
-        Self { hid, usb, config }
+    pub fn new(transport: SharedTransport, config: Arc<Mutex<SlashConfig>>) -> Self {
+        Self { transport, config }
     }
 
     pub async fn lock_config(&self) -> MutexGuard<'_, SlashConfig> {
@@ -32,12 +29,7 @@ impl Slash {
     }
 
     pub async fn write_bytes(&self, message: &[u8]) -> Result<(), RogError> {
-        if let Some(hid) = &self.hid {
-            hid.lock().await.write_bytes(message)?;
-        } else if let Some(usb) = &self.usb {
-            usb.lock().await.write_bytes(message)?;
-        }
-        Ok(())
+        self.transport.lock().await.write_bytes(message)
     }
 
     /// Initialise the device if required. Locks the internal config so be wary
@@ -66,4 +58,49 @@ impl Slash {
 
         Ok(())
     }
+
+    /// Write the enabled state, brightness, interval and mode in `config`,
+    /// then save them to the device
+    pub async fn write_display(&self, config: &SlashConfig) -> Result<(), RogError> {
+        self.write_bytes(&slash_pkt_options(
+            config.slash_type,
+            config.enabled,
+            config.brightness,
+            config.display_interval,
+        ))
+        .await?;
+        let mode_packets = slash_pkt_set_mode(config.slash_type, config.display_mode);
+        self.write_bytes(&mode_packets[1]).await?;
+        self.write_bytes(&slash_pkt_save(config.slash_type)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_util::lock::Mutex;
+    use rog_slash::SlashType;
+
+    use super::config::SlashConfig;
+    use super::Slash;
+    use crate::transport::RecordingTransport;
+
+    #[tokio::test]
+    async fn initialization_packets() {
+        let rec = RecordingTransport::new();
+        let config = SlashConfig {
+            slash_type: SlashType::GA605,
+            ..Default::default()
+        };
+        let slash = Slash::new(rec.shared(), Arc::new(Mutex::new(config)));
+        slash.do_initialization().await.unwrap();
+
+        let packets = rec.packets();
+        // init x2, enable, options, mode
+        assert_eq!(packets.len(), 5);
+        assert_eq!(&packets[0][..2], &[0x5d, 0xd7]);
+        assert_eq!(&packets[1][..2], &[0x5d, 0xd2]);
+        assert!(packets.iter().all(|p| p[0] == 0x5d));
+    }
 }
@@ -6,12 +6,13 @@ use rog_slash::usb::{
     slash_pkt_shutdown, slash_pkt_sleep,
 };
 use rog_slash::{DeviceState, SlashMode};
+use zbus::object_server::SignalEmitter;
 use zbus::zvariant::OwnedObjectPath;
 use zbus::{interface, Connection};
 
 use super::Slash;
 use crate::error::RogError;
-use crate::Reloadable;
+use crate::{CtrlTask, Reloadable, TaskHandles};
 
 #[derive(Clone)]
 pub struct SlashZbus(Slash);
@@ -21,12 +22,15 @@ impl SlashZbus {
         Self(slash)
     }
 
+    /// Serve the device at `path` and start the resume task. The returned
+    /// handles must be aborted when the device is removed.
     pub async fn start_tasks(
         mut self,
         connection: &Connection,
         path: OwnedObjectPath,
-    ) -> Result<(), RogError> {
-        // let task = zbus.clone();
+    ) -> Result<TaskHandles, RogError> {
+        let task = self.clone();
+        let signal_ctx = SignalEmitter::new(connection, path.clone())?.into_owned();
         self.reload()
             .await
             .unwrap_or_else(|err| warn!("Controller error: {}", err));
@@ -36,7 +40,7 @@ impl SlashZbus {
             .await
             .map_err(|e| error!("Couldn't add server at path: {path}, {e:?}"))
             .ok();
-        Ok(())
+        task.create_tasks(signal_ctx).await
     }
 }
 
@@ -315,3 +319,44 @@ impl Reloadable for SlashZbus {
         Ok(())
     }
 }
+
+impl CtrlTask for SlashZbus {
+    fn zbus_path() -> &'static str {
+        "/xyz/ljones"
+    }
+
+    async fn create_tasks(
+        &self,
+        signal_ctx: SignalEmitter<'static>,
+    ) -> Result<TaskHandles, RogError> {
+        let slash = self.clone();
+        let tasks = self
+            .create_sys_event_tasks(
+                signal_ctx.connection(),
+                move |sleeping| {
+                    let mut slash = slash.clone();
+                    async move {
+                        // The device loses its settings over suspend
+                        if !sleeping {
+                            debug!("Slash reinitialising after resume");
+                            slash
+                                .0
+                                .do_initialization()
+                                .await
+                                .unwrap_or_else(|e| warn!("Slash init failed: {e}"));
+                            slash
+                                .reload()
+                                .await
+                                .unwrap_or_else(|e| warn!("Slash reload failed: {e}"));
+                        }
+                    }
+                },
+                // The shutdown display is handled by the device itself
+                move |_| async {},
+                move |_| async {},
+                move |_| async {},
+            )
+            .await?;
+        Ok(tasks)
+    }
+}
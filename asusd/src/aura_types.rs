@@ -18,6 +18,7 @@ use crate::aura_scsi::ScsiAura;
 use crate::aura_slash::config::SlashConfig;
 use crate::aura_slash::Slash;
 use crate::error::RogError;
+use crate::transport::SharedTransport;
 
 pub enum _DeviceHandle {
     /// The AniMe devices require USBRaw as they are not HID devices
@@ -45,10 +46,7 @@ pub enum DeviceHandle {
 
 impl DeviceHandle {
     /// Try Slash HID. If one exists it is initialsed and returned.
-    pub async fn new_slash_hid(
-        device: Arc<Mutex<HidRaw>>,
-        prod_id: &str,
-    ) -> Result<Self, RogError> {
+    pub async fn new_slash_hid(device: SharedTransport, prod_id: &str) -> Result<Self, RogError> {
         debug!("Testing for HIDRAW Slash");
         let slash_type = SlashType::from_dmi();
         if matches!(slash_type, SlashType::Unsupported)
@@ -65,7 +63,7 @@ impl DeviceHandle {
 
         let mut config = SlashConfig::new().load();
         config.slash_type = slash_type;
-        let slash = Slash::new(Some(device), None, Arc::new(Mutex::new(config)));
+        let slash = Slash::new(device, Arc::new(Mutex::new(config)));
         slash.do_initialization().await?;
         Ok(Self::Slash(slash))
     }
@@ -83,11 +81,7 @@ impl DeviceHandle {
 
             let mut config = SlashConfig::new().load();
             config.slash_type = slash_type;
-            let slash = Slash::new(
-                None,
-                Some(Arc::new(Mutex::new(usb))),
-                Arc::new(Mutex::new(config)),
-            );
+            let slash = Slash::new(Arc::new(Mutex::new(usb)), Arc::new(Mutex::new(config)));
             slash.do_initialization().await?;
             Ok(Self::Slash(slash))
         } else {
@@ -106,14 +100,14 @@ impl DeviceHandle {
 
         let mut config = ScsiConfig::new().load();
         config.dev_type = AuraDeviceType::ScsiExtDisk;
-        let dev = Arc::new(Mutex::new(open_device(dev_node)?));
-        let scsi = ScsiAura::new(dev, Arc::new(Mutex::new(config)));
+        let dev: SharedTransport = Arc::new(Mutex::new(open_device(dev_node)?));
+        let scsi = ScsiAura::new(dev, config);
         scsi.do_initialization().await?;
         Ok(Self::Scsi(scsi))
     }
 
     pub async fn maybe_laptop_aura(
-        device: Option<Arc<Mutex<HidRaw>>>,
+        device: Option<SharedTransport>,
         prod_id: &str,
     ) -> Result<Self, RogError> {
         debug!("Testing for laptop aura");
@@ -138,11 +132,7 @@ impl DeviceHandle {
 
         let mut config = AuraConfig::load_and_update_config(prod_id);
         config.led_type = aura_type;
-        let aura = Aura {
-            hid: device,
-            backlight,
-            config: Arc::new(Mutex::new(config)),
-        };
+        let aura = Aura::new(device, backlight, config);
         aura.do_initialization().await?;
         Ok(Self::Aura(aura))
     }
@@ -17,11 +17,13 @@ type Tunings = HashMap<PlatformProfile, Tuning>;
 
 #[derive(Deserialize, Serialize, PartialEq)]
 pub struct Config {
-    // The current charge limit applied
+    /// The charge limit set by the user, restored on boot. A one-shot full
+    /// charge doesn't change this.
     pub charge_control_end_threshold: u8,
-    /// Save charge limit for restoring
+    /// A one-shot charge to 100% is applied in place of the limit. This is
+    /// never saved.
     #[serde(skip)]
-    pub base_charge_control_end_threshold: u8,
+    pub one_shot_charge: bool,
     pub disable_nvidia_powerd_on_battery: bool,
     /// An optional command/script to run when power is changed to AC
     pub ac_command: String,
@@ -73,7 +75,7 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             charge_control_end_threshold: 100,
-            base_charge_control_end_threshold: 100,
+            one_shot_charge: false,
             disable_nvidia_powerd_on_battery: true,
             ac_command: Default::default(),
             bat_command: Default::default(),
@@ -149,7 +151,7 @@ impl From<Config611> for Config {
         Self {
             // Restore the base charge limit
             charge_control_end_threshold: c.charge_control_end_threshold,
-            base_charge_control_end_threshold: c.charge_control_end_threshold,
+            one_shot_charge: false,
             disable_nvidia_powerd_on_battery: c.disable_nvidia_powerd_on_battery,
             ac_command: c.ac_command,
             bat_command: c.bat_command,
@@ -216,7 +218,7 @@ impl From<Config601> for Config {
         Self {
             // Restore the base charge limit
             charge_control_end_threshold: c.charge_control_end_threshold,
-            base_charge_control_end_threshold: c.charge_control_end_threshold,
+            one_shot_charge: false,
             disable_nvidia_powerd_on_battery: c.disable_nvidia_powerd_on_battery,
             ac_command: c.ac_command,
             bat_command: c.bat_command,
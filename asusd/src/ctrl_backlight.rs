@@ -0,0 +1,377 @@
+use std::sync::Arc;
+
+use config_traits::StdConfig;
+use futures_util::lock::Mutex;
+use log::{debug, info, warn};
+use rog_platform::backlight::{Backlight, BacklightType};
+use rog_platform::error::PlatformError;
+use zbus::fdo::Error as FdoErr;
+use zbus::object_server::SignalEmitter;
+use zbus::{interface, Connection};
+
+use crate::config::Config;
+use crate::error::RogError;
+use crate::{CtrlTask, Reloadable, TaskHandles, ZbusRun, ASUS_ZBUS_PATH};
+
+/// Written to `bl_power` to turn the screenpad on
+const BL_POWER_ON: i32 = 0;
+/// Written to `bl_power` to turn the screenpad off (`FB_BLANK_POWERDOWN`)
+const BL_POWER_OFF: i32 = 4;
+const GAMMA_MIN: f32 = 0.1;
+const GAMMA_MAX: f32 = 4.0;
+
+/// Map a brightness percentage to a raw value for a device with
+/// `max_brightness` of `max`. A gamma above 1.0 gives finer control at the low
+/// end, which suits the screenpad as it is very bright.
+fn percent_to_raw(percent: i32, max: i32, gamma: f32) -> i32 {
+    let normalised = percent.clamp(0, 100) as f32 / 100.0;
+    (normalised.powf(gamma) * max as f32).round() as i32
+}
+
+/// The inverse of `percent_to_raw()`
+fn raw_to_percent(raw: i32, max: i32, gamma: f32) -> i32 {
+    if max <= 0 {
+        return 0;
+    }
+    let normalised = raw.clamp(0, max) as f32 / max as f32;
+    (normalised.powf(1.0 / gamma) * 100.0).round() as i32
+}
+
+#[derive(Clone)]
+pub struct CtrlBacklight {
+    primary: Option<Backlight>,
+    screenpad: Option<Backlight>,
+    config: Arc<Mutex<Config>>,
+}
+
+impl CtrlBacklight {
+    pub fn new(config: Arc<Mutex<Config>>) -> Result<Self, RogError> {
+        Self::with_backlights(
+            Backlight::new(BacklightType::Primary).ok(),
+            Backlight::new(BacklightType::Screenpad).ok(),
+            config,
+        )
+    }
+
+    pub fn with_backlights(
+        primary: Option<Backlight>,
+        screenpad: Option<Backlight>,
+        config: Arc<Mutex<Config>>,
+    ) -> Result<Self, RogError> {
+        if primary.is_none() && screenpad.is_none() {
+            return Err(PlatformError::MissingFunction("No backlights found".to_owned()).into());
+        }
+        Ok(Self {
+            primary,
+            screenpad,
+            config,
+        })
+    }
+
+    fn backlight(&self, device_type: BacklightType) -> Result<&Backlight, RogError> {
+        match device_type {
+            BacklightType::Primary => self.primary.as_ref(),
+            BacklightType::Screenpad => self.screenpad.as_ref(),
+        }
+        .ok_or(RogError::NotSupported)
+    }
+
+    async fn gamma(&self, device_type: &BacklightType) -> f32 {
+        match device_type {
+            BacklightType::Primary => 1.0,
+            BacklightType::Screenpad => self.config.lock().await.screenpad_gamma.unwrap_or(1.0),
+        }
+    }
+
+    async fn brightness_percent(&self, device_type: BacklightType) -> Result<i32, RogError> {
+        let gamma = self.gamma(&device_type).await;
+        let backlight = self.backlight(device_type)?;
+        Ok(raw_to_percent(
+            backlight.get_brightness()?,
+            backlight.get_max_brightness()?,
+            gamma,
+        ))
+    }
+
+    async fn set_brightness_percent(
+        &self,
+        device_type: BacklightType,
+        percent: i32,
+    ) -> Result<(), RogError> {
+        if !(0..=100).contains(&percent) {
+            return Err(PlatformError::OutOfRange(format!(
+                "brightness must be 0-100, got {percent}"
+            ))
+            .into());
+        }
+        let gamma = self.gamma(&device_type).await;
+        let backlight = self.backlight(device_type)?;
+        let raw = percent_to_raw(percent, backlight.get_max_brightness()?, gamma);
+        debug!(
+            "Setting {:?} brightness to {percent}% ({raw})",
+            backlight.device_type()
+        );
+        backlight.set_brightness(raw)?;
+        Ok(())
+    }
+
+    /// Copy the primary brightness to the screenpad, if sync is enabled
+    async fn sync_screenpad(&self) -> Result<bool, RogError> {
+        let sync = self.config.lock().await.screenpad_sync_primary;
+        if sync != Some(true) || self.screenpad.is_none() {
+            return Ok(false);
+        }
+        let percent = self.brightness_percent(BacklightType::Primary).await?;
+        self.set_brightness_percent(BacklightType::Screenpad, percent)
+            .await?;
+        Ok(true)
+    }
+
+    /// The primary brightness is changed by the desktop environment, this
+    /// follows it to update the screenpad and notify clients.
+    async fn watch_primary_brightness(
+        &self,
+        signal_ctxt: SignalEmitter<'static>,
+    ) -> Result<(), RogError> {
+        use futures_util::StreamExt;
+
+        let Some(primary) = &self.primary else {
+            return Ok(());
+        };
+        let ctrl = self.clone();
+        match primary.monitor_brightness() {
+            Ok(watch) => {
+                tokio::spawn(async move {
+                    let mut buffer = [0; 32];
+                    watch
+                        .into_event_stream(&mut buffer)
+                        .unwrap()
+                        .for_each(|_| async {
+                            ctrl.primary_brightness_changed(&signal_ctxt).await.ok();
+                            match ctrl.sync_screenpad().await {
+                                Ok(true) => {
+                                    ctrl.screenpad_brightness_changed(&signal_ctxt).await.ok();
+                                }
+                                Ok(false) => {}
+                                Err(e) => warn!("Could not sync screenpad brightness: {e}"),
+                            }
+                        })
+                        .await;
+                });
+            }
+            Err(e) => info!(
+                "inotify watch failed: {}. You can ignore this if your device does not support \
+                 the feature",
+                e
+            ),
+        }
+        Ok(())
+    }
+}
+
+#[interface(name = "xyz.ljones.Backlight")]
+impl CtrlBacklight {
+    /// Brightness of the primary display, 0-100
+    #[zbus(property)]
+    async fn primary_brightness(&self) -> Result<i32, FdoErr> {
+        Ok(self.brightness_percent(BacklightType::Primary).await?)
+    }
+
+    #[zbus(property)]
+    async fn set_primary_brightness(&self, value: i32) -> Result<(), FdoErr> {
+        self.set_brightness_percent(BacklightType::Primary, value)
+            .await?;
+        Ok(())
+    }
+
+    /// Brightness of the screenpad, 0-100. The screenpad gamma is applied
+    #[zbus(property)]
+    async fn screenpad_brightness(&self) -> Result<i32, FdoErr> {
+        Ok(self.brightness_percent(BacklightType::Screenpad).await?)
+    }
+
+    #[zbus(property)]
+    async fn set_screenpad_brightness(&self, value: i32) -> Result<(), FdoErr> {
+        self.set_brightness_percent(BacklightType::Screenpad, value)
+            .await?;
+        Ok(())
+    }
+
+    /// The gamma curve used to map screenpad brightness, as a float string
+    #[zbus(property)]
+    async fn screenpad_gamma(&self) -> String {
+        let gamma = self.config.lock().await.screenpad_gamma.unwrap_or(1.0);
+        gamma.to_string()
+    }
+
+    #[zbus(property)]
+    async fn set_screenpad_gamma(
+        &self,
+        value: &str,
+        #[zbus(signal_emitter)] ctxt: SignalEmitter<'_>,
+    ) -> Result<(), FdoErr> {
+        let gamma: f32 = value
+            .trim()
+            .parse()
+            .map_err(|_| FdoErr::InvalidArgs(format!("{value} is not a number")))?;
+        if !(GAMMA_MIN..=GAMMA_MAX).contains(&gamma) {
+            return Err(FdoErr::InvalidArgs(format!(
+                "gamma must be {GAMMA_MIN}-{GAMMA_MAX}, got {gamma}"
+            )));
+        }
+        // Keep the same perceived brightness under the new curve
+        let percent = self.brightness_percent(BacklightType::Screenpad).await;
+        {
+            let mut config = self.config.lock().await;
+            config.screenpad_gamma = Some(gamma);
+            config.write();
+        }
+        if let Ok(percent) = percent {
+            self.set_brightness_percent(BacklightType::Screenpad, percent)
+                .await?;
+        }
+        self.screenpad_gamma_changed(&ctxt).await?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    async fn screenpad_power(&self) -> Result<bool, FdoErr> {
+        let screenpad = self.backlight(BacklightType::Screenpad)?;
+        Ok(screenpad.get_bl_power().map_err(RogError::from)? == BL_POWER_ON)
+    }
+
+    #[zbus(property)]
+    async fn set_screenpad_power(&self, value: bool) -> Result<(), FdoErr> {
+        let screenpad = self.backlight(BacklightType::Screenpad)?;
+        screenpad
+            .set_bl_power(if value { BL_POWER_ON } else { BL_POWER_OFF })
+            .map_err(RogError::from)?;
+        Ok(())
+    }
+
+    /// If set the screenpad brightness follows the primary display
+    #[zbus(property)]
+    async fn screenpad_sync_with_primary(&self) -> bool {
+        self.config
+            .lock()
+            .await
+            .screenpad_sync_primary
+            .unwrap_or_default()
+    }
+
+    #[zbus(property)]
+    async fn set_screenpad_sync_with_primary(
+        &self,
+        value: bool,
+        #[zbus(signal_emitter)] ctxt: SignalEmitter<'_>,
+    ) -> Result<(), FdoErr> {
+        {
+            let mut config = self.config.lock().await;
+            config.screenpad_sync_primary = Some(value);
+            config.write();
+        }
+        if self.sync_screenpad().await? {
+            self.screenpad_brightness_changed(&ctxt).await?;
+        }
+        self.screenpad_sync_with_primary_changed(&ctxt).await?;
+        Ok(())
+    }
+}
+
+impl ZbusRun for CtrlBacklight {
+    async fn add_to_server(self, server: &mut Connection) {
+        Self::add_to_server_helper(self, ASUS_ZBUS_PATH, server).await;
+    }
+}
+
+impl Reloadable for CtrlBacklight {
+    async fn reload(&mut self) -> Result<(), RogError> {
+        self.sync_screenpad().await?;
+        Ok(())
+    }
+}
+
+impl CtrlTask for CtrlBacklight {
+    fn zbus_path() -> &'static str {
+        ASUS_ZBUS_PATH
+    }
+
+    async fn create_tasks(
+        &self,
+        signal_ctxt: SignalEmitter<'static>,
+    ) -> Result<TaskHandles, RogError> {
+        self.watch_primary_brightness(signal_ctxt).await?;
+        Ok(TaskHandles::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_util::lock::Mutex;
+    use rog_platform::backlight::{Backlight, BacklightType};
+    use rog_platform::fixture::Fixture;
+
+    use super::{percent_to_raw, raw_to_percent, CtrlBacklight};
+    use crate::config::Config;
+
+    #[test]
+    fn gamma_mapping() {
+        assert_eq!(percent_to_raw(0, 255, 2.2), 0);
+        assert_eq!(percent_to_raw(100, 255, 2.2), 255);
+        assert_eq!(percent_to_raw(50, 255, 1.0), 128);
+        assert!(percent_to_raw(50, 255, 2.2) < 128);
+        for percent in 0..=100 {
+            let raw = percent_to_raw(percent, 19393, 1.8);
+            assert_eq!(raw_to_percent(raw, 19393, 1.8), percent);
+        }
+        assert_eq!(raw_to_percent(10, 0, 1.0), 0);
+    }
+
+    #[tokio::test]
+    async fn screenpad_follows_primary() {
+        let fixture = Fixture::new("asusd-backlight");
+        fixture
+            .attr("sys/class/backlight/intel_backlight/brightness", "0")
+            .attr("sys/class/backlight/intel_backlight/max_brightness", "1000")
+            .attr("sys/class/backlight/asus_screenpad/brightness", "0")
+            .attr("sys/class/backlight/asus_screenpad/max_brightness", "255");
+        let read = |path: &str| fixture.read(&format!("sys/class/backlight/{path}"));
+
+        let config = Config {
+            screenpad_sync_primary: Some(true),
+            screenpad_gamma: Some(2.0),
+            ..Default::default()
+        };
+        let ctrl = CtrlBacklight::with_backlights(
+            Backlight::with_root(fixture.root(), BacklightType::Primary).ok(),
+            Backlight::with_root(fixture.root(), BacklightType::Screenpad).ok(),
+            Arc::new(Mutex::new(config)),
+        )
+        .unwrap();
+
+        assert!(ctrl
+            .set_brightness_percent(BacklightType::Primary, 101)
+            .await
+            .is_err());
+        ctrl.set_brightness_percent(BacklightType::Primary, 50)
+            .await
+            .unwrap();
+        assert_eq!(read("intel_backlight/brightness"), "500");
+        assert!(ctrl.sync_screenpad().await.unwrap());
+        // 0.5^2 * 255
+        assert_eq!(read("asus_screenpad/brightness"), "64");
+        assert_eq!(
+            ctrl.brightness_percent(BacklightType::Screenpad)
+                .await
+                .unwrap(),
+            50
+        );
+
+        ctrl.config.lock().await.screenpad_sync_primary = Some(false);
+        assert!(!ctrl.sync_screenpad().await.unwrap());
+
+        assert!(CtrlBacklight::with_backlights(None, None, ctrl.config.clone()).is_err());
+    }
+}
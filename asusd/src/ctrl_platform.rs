@@ -0,0 +1,305 @@
+use std::sync::Arc;
+
+use config_traits::StdConfig;
+use futures_util::lock::Mutex;
+use log::{debug, info, warn};
+use rog_platform::power::AsusPower;
+use zbus::fdo::Error as FdoErr;
+use zbus::object_server::SignalEmitter;
+use zbus::{interface, Connection};
+
+use crate::config::Config;
+use crate::error::RogError;
+use crate::{CtrlTask, Reloadable, TaskHandles, ZbusRun, ASUS_ZBUS_PATH};
+
+/// Battery charge control. The charge limit is owned by asusd so that it is
+/// persisted and restored on boot, and so a one-shot full charge can be
+/// undone again.
+#[derive(Clone)]
+pub struct CtrlPlatform {
+    power: AsusPower,
+    config: Arc<Mutex<Config>>,
+}
+
+impl CtrlPlatform {
+    pub fn new(config: Arc<Mutex<Config>>) -> Result<Self, RogError> {
+        Ok(Self::with_power(AsusPower::new()?, config))
+    }
+
+    pub fn with_power(power: AsusPower, config: Arc<Mutex<Config>>) -> Self {
+        Self { power, config }
+    }
+
+    /// Apply a new base charge limit. This also ends a one-shot full charge.
+    fn set_charge_limit(&self, config: &mut Config, limit: u8) -> Result<(), RogError> {
+        if !(20..=100).contains(&limit) {
+            return Err(RogError::ChargeLimit(limit));
+        }
+        self.power.set_charge_control_end_threshold(limit)?;
+        info!("Battery charge limit set to {limit}");
+        config.charge_control_end_threshold = limit;
+        config.one_shot_charge = false;
+        Ok(())
+    }
+
+    /// Returns true if the limit currently applied is a one-shot full charge
+    fn one_shot_active(config: &Config) -> bool {
+        config.one_shot_charge
+    }
+
+    /// The limit that should be in sysfs, 100 during a one-shot full charge
+    fn applied_charge_limit(config: &Config) -> u8 {
+        if config.one_shot_charge {
+            100
+        } else {
+            config.charge_control_end_threshold
+        }
+    }
+
+    /// Toggle charging to 100% once. The base limit is kept so that it can
+    /// be restored, either by toggling again or by unplugging AC.
+    fn toggle_one_shot(&self, config: &mut Config) -> Result<(), RogError> {
+        if Self::one_shot_active(config) {
+            return self.restore_charge_limit(config);
+        }
+        if config.charge_control_end_threshold < 100 {
+            self.power.set_charge_control_end_threshold(100)?;
+            info!("One-shot full charge enabled");
+            config.one_shot_charge = true;
+        }
+        Ok(())
+    }
+
+    /// End a one-shot full charge if one is active
+    fn restore_charge_limit(&self, config: &mut Config) -> Result<(), RogError> {
+        if Self::one_shot_active(config) {
+            let base = config.charge_control_end_threshold;
+            self.power.set_charge_control_end_threshold(base)?;
+            info!("One-shot full charge ended, charge limit restored to {base}");
+            config.one_shot_charge = false;
+        }
+        Ok(())
+    }
+
+    /// Track changes made to the limit outside of asusd, such as by another
+    /// tool writing to sysfs. These become the new base limit.
+    async fn watch_charge_control_end_threshold(
+        &self,
+        signal_ctxt: SignalEmitter<'static>,
+    ) -> Result<(), RogError> {
+        use futures_util::StreamExt;
+
+        let ctrl = self.clone();
+        match self.power.monitor_charge_control_end_threshold() {
+            Ok(watch) => {
+                tokio::spawn(async move {
+                    let mut buffer = [0; 32];
+                    watch
+                        .into_event_stream(&mut buffer)
+                        .unwrap()
+                        .for_each(|_| async {
+                            if let Ok(value) = ctrl.power.get_charge_control_end_threshold() {
+                                let mut config = ctrl.config.lock().await;
+                                if Self::applied_charge_limit(&config) != value {
+                                    debug!(
+                                        "charge_control_end_threshold was changed to {value} \
+                                         externally"
+                                    );
+                                    config.charge_control_end_threshold = value;
+                                    config.one_shot_charge = false;
+                                    config.write();
+                                    drop(config);
+                                    ctrl.charge_control_end_threshold_changed(&signal_ctxt)
+                                        .await
+                                        .ok();
+                                }
+                            }
+                        })
+                        .await;
+                });
+            }
+            Err(e) => info!(
+                "inotify watch failed: {}. You can ignore this if your device does not support \
+                 the feature",
+                e
+            ),
+        }
+        Ok(())
+    }
+}
+
+#[interface(name = "xyz.ljones.Platform")]
+impl CtrlPlatform {
+    #[zbus(property)]
+    async fn version(&self) -> String {
+        crate::VERSION.to_string()
+    }
+
+    /// The charge limit currently applied, this is 100 during a one-shot
+    /// full charge
+    #[zbus(property)]
+    fn charge_control_end_threshold(&self) -> Result<u8, FdoErr> {
+        let limit = self
+            .power
+            .get_charge_control_end_threshold()
+            .map_err(RogError::from)?;
+        Ok(limit)
+    }
+
+    /// Set the charge limit, 20-100
+    #[zbus(property)]
+    async fn set_charge_control_end_threshold(&self, limit: u8) -> Result<(), FdoErr> {
+        let mut config = self.config.lock().await;
+        self.set_charge_limit(&mut config, limit)?;
+        config.write();
+        Ok(())
+    }
+
+    /// Toggle a one-shot charge to 100%
+    async fn one_shot_full_charge(
+        &self,
+        #[zbus(signal_emitter)] ctxt: SignalEmitter<'_>,
+    ) -> Result<(), FdoErr> {
+        // The config isn't written, a one-shot charge must not persist past a
+        // reboot
+        self.toggle_one_shot(&mut *self.config.lock().await)?;
+        self.charge_control_end_threshold_changed(&ctxt).await?;
+        Ok(())
+    }
+}
+
+impl ZbusRun for CtrlPlatform {
+    async fn add_to_server(self, server: &mut Connection) {
+        Self::add_to_server_helper(self, ASUS_ZBUS_PATH, server).await;
+    }
+}
+
+impl Reloadable for CtrlPlatform {
+    /// Restore the saved charge limit, this is run on boot
+    async fn reload(&mut self) -> Result<(), RogError> {
+        let mut config = self.config.lock().await;
+        let limit = config.charge_control_end_threshold;
+        if self.power.has_charge_control_end_threshold() {
+            self.set_charge_limit(&mut config, limit)?;
+        } else {
+            warn!("Battery charge limit is not supported on this laptop");
+        }
+        Ok(())
+    }
+}
+
+impl CtrlTask for CtrlPlatform {
+    fn zbus_path() -> &'static str {
+        ASUS_ZBUS_PATH
+    }
+
+    async fn create_tasks(
+        &self,
+        signal_ctxt: SignalEmitter<'static>,
+    ) -> Result<TaskHandles, RogError> {
+        let platform_sleep = self.clone();
+        let platform_power = self.clone();
+        let signal_power = signal_ctxt.clone();
+        let tasks = self
+            .create_sys_event_tasks(
+                signal_ctxt.connection(),
+                move |sleeping| {
+                    let platform = platform_sleep.clone();
+                    async move {
+                        // Some firmware resets the charge limit on resume
+                        if !sleeping {
+                            let config = platform.config.lock().await;
+                            let limit = Self::applied_charge_limit(&config);
+                            debug!("Restoring charge limit {limit} after resume");
+                            platform
+                                .power
+                                .set_charge_control_end_threshold(limit)
+                                .map_err(|e| warn!("Could not restore charge limit: {e}"))
+                                .ok();
+                        }
+                    }
+                },
+                move |_| async {},
+                move |_| async {},
+                move |power_plugged| {
+                    let platform = platform_power.clone();
+                    let signal = signal_power.clone();
+                    async move {
+                        if !power_plugged {
+                            let mut config = platform.config.lock().await;
+                            if Self::one_shot_active(&config) {
+                                platform
+                                    .restore_charge_limit(&mut config)
+                                    .map_err(|e| warn!("Could not restore charge limit: {e}"))
+                                    .ok();
+                                drop(config);
+                                platform
+                                    .charge_control_end_threshold_changed(&signal)
+                                    .await
+                                    .ok();
+                            }
+                        }
+                    }
+                },
+            )
+            .await?;
+
+        self.watch_charge_control_end_threshold(signal_ctxt).await?;
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_util::lock::Mutex;
+    use rog_platform::fixture::Fixture;
+    use rog_platform::power::AsusPower;
+
+    use super::CtrlPlatform;
+    use crate::config::Config;
+    use crate::error::RogError;
+
+    const LIMIT: &str = "sys/class/power_supply/BAT0/charge_control_end_threshold";
+
+    #[test]
+    fn charge_limit_and_one_shot() {
+        let fixture = Fixture::new("asusd-charge");
+        fixture
+            .attr("sys/class/power_supply/BAT0/type", "Battery\n")
+            .attr(LIMIT, "100\n");
+        let power = AsusPower::with_root(fixture.root()).unwrap();
+        let ctrl = CtrlPlatform::with_power(power, Arc::new(Mutex::new(Config::default())));
+        let mut config = Config::default();
+
+        assert!(matches!(
+            ctrl.set_charge_limit(&mut config, 10),
+            Err(RogError::ChargeLimit(10))
+        ));
+        ctrl.set_charge_limit(&mut config, 80).unwrap();
+        assert_eq!(fixture.read(LIMIT), "80");
+
+        ctrl.toggle_one_shot(&mut config).unwrap();
+        assert_eq!(fixture.read(LIMIT), "100");
+        // Only the limit is saved, so a write during the one-shot keeps it
+        assert_eq!(config.charge_control_end_threshold, 80);
+        assert!(config.one_shot_charge);
+        assert_eq!(CtrlPlatform::applied_charge_limit(&config), 100);
+
+        ctrl.toggle_one_shot(&mut config).unwrap();
+        assert_eq!(fixture.read(LIMIT), "80");
+
+        ctrl.toggle_one_shot(&mut config).unwrap();
+        ctrl.restore_charge_limit(&mut config).unwrap();
+        assert_eq!(fixture.read(LIMIT), "80");
+        assert_eq!(config.charge_control_end_threshold, 80);
+        assert!(!config.one_shot_charge);
+
+        // Setting a limit during a one-shot ends it
+        ctrl.toggle_one_shot(&mut config).unwrap();
+        ctrl.set_charge_limit(&mut config, 60).unwrap();
+        assert_eq!(fixture.read(LIMIT), "60");
+        assert!(!config.one_shot_charge);
+    }
+}
@@ -1,10 +1,20 @@
 use std::env;
 use std::error::Error;
+use std::sync::Arc;
 
 use ::zbus::Connection;
+use asusd::asus_armoury::start_attributes_zbus;
 use asusd::aura_manager::DeviceManager;
-use asusd::{print_board_info, DBUS_NAME};
-use log::info;
+use asusd::config::Config;
+use asusd::ctrl_backlight::CtrlBacklight;
+use asusd::ctrl_platform::CtrlPlatform;
+use asusd::scenes::{Scenes, ScenesConfig};
+use asusd::schedules::{Schedules, SchedulesConfig};
+use asusd::{print_board_info, start_tasks, CtrlTask, DBUS_NAME};
+use config_traits::{StdConfig, StdConfigLoad, StdConfigLoad2};
+use futures_util::lock::Mutex;
+use log::{error, info};
+use rog_platform::asus_armoury::FirmwareAttributes;
 use zbus::fdo::ObjectManager;
 
 #[tokio::main]
@@ -48,10 +58,45 @@ async fn start_daemon() -> Result<(), Box<dyn Error>> {
     // println!("{:?}", supported.supported_functions());
 
     // Start zbus server
-    let server = Connection::system().await?;
+    let mut server = Connection::system().await?;
     server.object_server().at("/", ObjectManager).await.unwrap();
 
-    let _ = DeviceManager::new(server.clone()).await?;
+    let config = Config::new().load();
+    let config = Arc::new(Mutex::new(config));
+
+    match CtrlPlatform::new(config.clone()) {
+        Ok(ctrl) => {
+            let sig_ctx = CtrlPlatform::signal_context(&server)?;
+            start_tasks(ctrl, &mut server, sig_ctx).await?;
+        }
+        Err(err) => {
+            error!("CtrlPlatform: {}", err);
+        }
+    }
+
+    match CtrlBacklight::new(config.clone()) {
+        Ok(ctrl) => {
+            let sig_ctx = CtrlBacklight::signal_context(&server)?;
+            start_tasks(ctrl, &mut server, sig_ctx).await?;
+        }
+        Err(err) => {
+            error!("CtrlBacklight: {}", err);
+        }
+    }
+
+    start_attributes_zbus(&server, FirmwareAttributes::new(), config.clone()).await?;
+
+    let devices = DeviceManager::new(server.clone()).await?;
+    let scenes = Scenes::new(devices.registry().clone(), ScenesConfig::new().load());
+    scenes.start_tasks(&server).await?;
+    let schedules = Schedules::new(
+        devices.registry().clone(),
+        &scenes,
+        SchedulesConfig::new().load(),
+    );
+    let sig_ctx = Schedules::signal_context(&server)?;
+    start_tasks(schedules, &mut server, sig_ctx).await?;
+    tokio::spawn(devices.run());
 
     // Request dbus name after finishing initalizing all functions
     server.request_name(DBUS_NAME).await?;
@@ -0,0 +1,218 @@
+//! The `xyz.ljones.Asusd` interface at `/xyz/ljones/Daemon` lists every USB
+//! or SCSI device asusd is controlling, and signals when one is hotplugged so
+//! that clients don't need to poll the object manager.
+
+use std::sync::Arc;
+
+use futures_util::lock::Mutex;
+use log::{debug, warn};
+use rog_aura::AuraDeviceType;
+use serde::{Deserialize, Serialize};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{OwnedObjectPath, Type};
+use zbus::{interface, Connection};
+
+use crate::aura_types::DeviceHandle;
+use crate::DBUS_PATH;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[zvariant(signature = "s")]
+pub enum DeviceKind {
+    Aura,
+    Slash,
+    Scsi,
+}
+
+impl DeviceKind {
+    pub fn from_handle(handle: &DeviceHandle) -> Option<Self> {
+        match handle {
+            DeviceHandle::Aura(_)
+            | DeviceHandle::OldAura(_)
+            | DeviceHandle::TufLedClass(_)
+            | DeviceHandle::Ally(_)
+            | DeviceHandle::MulticolourLed => Some(Self::Aura),
+            DeviceHandle::Slash(_) => Some(Self::Slash),
+            DeviceHandle::Scsi(_) => Some(Self::Scsi),
+            DeviceHandle::None => None,
+        }
+    }
+}
+
+/// The details of a device as seen on dbus
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct DeviceInfo {
+    pub kind: DeviceKind,
+    /// The USB product ID, e.g `19b6`. TUF sysfs keyboards use `tuf`
+    pub prod_id: String,
+    /// Empty if the device has no serial
+    pub serial: String,
+    /// Where the device interface is served
+    pub dbus_path: OwnedObjectPath,
+    pub device_type: AuraDeviceType,
+}
+
+impl DeviceInfo {
+    pub fn new(kind: DeviceKind, prod_id: &str, serial: &str, dbus_path: OwnedObjectPath) -> Self {
+        let device_type = match kind {
+            DeviceKind::Aura => AuraDeviceType::from(prod_id),
+            DeviceKind::Slash => AuraDeviceType::AnimeOrSlash,
+            DeviceKind::Scsi => AuraDeviceType::ScsiExtDisk,
+        };
+        Self {
+            kind,
+            prod_id: prod_id.to_string(),
+            serial: serial.to_string(),
+            dbus_path,
+            device_type,
+        }
+    }
+}
+
+/// Tracks the devices the `DeviceManager` has added. A clone is kept by the
+/// manager so that hotplug events update the same list that is served.
+#[derive(Clone, Default)]
+pub struct DeviceRegistry {
+    devices: Arc<Mutex<Vec<(DeviceInfo, DeviceHandle)>>>,
+}
+
+impl DeviceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn start_tasks(&self, connection: &Connection) -> Result<(), zbus::Error> {
+        connection
+            .object_server()
+            .at(DBUS_PATH, self.clone())
+            .await?;
+        Ok(())
+    }
+
+    /// Add a device, replacing any existing entry of the same kind at the
+    /// same dbus path. Aura and Slash on one hidraw device share a path.
+    async fn insert(&self, info: DeviceInfo, handle: DeviceHandle) {
+        let mut devices = self.devices.lock().await;
+        devices.retain(|(dev, _)| dev.dbus_path != info.dbus_path || dev.kind != info.kind);
+        devices.push((info, handle));
+    }
+
+    /// Remove every device at `path`
+    async fn remove_path(&self, path: &OwnedObjectPath) -> Vec<DeviceInfo> {
+        let mut devices = self.devices.lock().await;
+        let (removed, kept) = std::mem::take(&mut *devices)
+            .into_iter()
+            .partition(|(dev, _)| dev.dbus_path == *path);
+        *devices = kept;
+        removed.into_iter().map(|(info, _)| info).collect()
+    }
+
+    /// Each device and the handle used to control it
+    pub async fn handles(&self) -> Vec<(DeviceInfo, DeviceHandle)> {
+        self.devices.lock().await.clone()
+    }
+
+    /// Register a device and emit `DeviceAdded`
+    pub async fn add(&self, connection: &Connection, info: DeviceInfo, handle: DeviceHandle) {
+        debug!("Registering device {:?} at {}", info.kind, info.dbus_path);
+        self.insert(info.clone(), handle).await;
+        match SignalEmitter::new(connection, DBUS_PATH) {
+            Ok(ctxt) => Self::device_added(&ctxt, info)
+                .await
+                .unwrap_or_else(|e| warn!("DeviceAdded signal failed: {e}")),
+            Err(e) => warn!("DeviceAdded signal failed: {e}"),
+        }
+    }
+
+    /// Unregister the devices at `path` and emit `DeviceRemoved` for each
+    pub async fn remove(&self, connection: &Connection, path: &OwnedObjectPath) {
+        for info in self.remove_path(path).await {
+            debug!("Unregistering device {:?} at {}", info.kind, info.dbus_path);
+            match SignalEmitter::new(connection, DBUS_PATH) {
+                Ok(ctxt) => Self::device_removed(&ctxt, info)
+                    .await
+                    .unwrap_or_else(|e| warn!("DeviceRemoved signal failed: {e}")),
+                Err(e) => warn!("DeviceRemoved signal failed: {e}"),
+            }
+        }
+    }
+}
+
+#[interface(name = "xyz.ljones.Asusd")]
+impl DeviceRegistry {
+    #[zbus(property)]
+    async fn version(&self) -> String {
+        crate::VERSION.to_string()
+    }
+
+    /// All devices currently being controlled
+    async fn devices(&self) -> Vec<DeviceInfo> {
+        self.devices
+            .lock()
+            .await
+            .iter()
+            .map(|(info, _)| info.clone())
+            .collect()
+    }
+
+    #[zbus(signal)]
+    async fn device_added(ctxt: &SignalEmitter<'_>, device: DeviceInfo) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn device_removed(ctxt: &SignalEmitter<'_>, device: DeviceInfo) -> zbus::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use rog_aura::AuraDeviceType;
+    use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+
+    use super::{DeviceInfo, DeviceKind, DeviceRegistry};
+    use crate::aura_types::DeviceHandle;
+
+    fn path(p: &str) -> OwnedObjectPath {
+        ObjectPath::from_str_unchecked(p).into()
+    }
+
+    #[tokio::test]
+    async fn insert_and_remove() {
+        let registry = DeviceRegistry::new();
+        let aura = DeviceInfo::new(DeviceKind::Aura, "19b6", "", path("/xyz/ljones/aura/19b6"));
+        assert_eq!(aura.device_type, AuraDeviceType::LaptopKeyboard2021);
+        let scsi = DeviceInfo::new(
+            DeviceKind::Scsi,
+            "1932",
+            "ABC123",
+            path("/xyz/ljones/aura/ABC123_scsi"),
+        );
+        assert_eq!(scsi.device_type, AuraDeviceType::ScsiExtDisk);
+
+        // Slash shares the hidraw device, and so the path, with Aura
+        let slash = DeviceInfo::new(DeviceKind::Slash, "19b6", "", aura.dbus_path.clone());
+
+        registry.insert(aura.clone(), DeviceHandle::None).await;
+        registry.insert(scsi.clone(), DeviceHandle::None).await;
+        registry.insert(slash.clone(), DeviceHandle::None).await;
+        // Re-adding the same kind at the same path replaces the entry
+        registry.insert(aura.clone(), DeviceHandle::None).await;
+        assert_eq!(registry.devices().await, vec![
+            scsi.clone(),
+            slash.clone(),
+            aura.clone()
+        ]);
+
+        assert_eq!(registry.remove_path(&scsi.dbus_path).await, vec![scsi]);
+        assert!(registry
+            .remove_path(&path("/xyz/ljones/aura/none"))
+            .await
+            .is_empty());
+        assert_eq!(registry.devices().await, vec![
+            slash.clone(),
+            aura.clone()
+        ]);
+
+        assert_eq!(registry.remove_path(&aura.dbus_path).await, vec![
+            slash, aura
+        ]);
+        assert!(registry.devices().await.is_empty());
+    }
+}
@@ -121,7 +121,14 @@ impl From<ron::Error> for RogError {
 impl From<RogError> for zbus::fdo::Error {
     #[inline]
     fn from(err: RogError) -> Self {
-        zbus::fdo::Error::Failed(format!("{}", err))
+        match err {
+            RogError::ChargeLimit(_)
+            | RogError::Platform(PlatformError::InvalidValue | PlatformError::OutOfRange(_)) => {
+                zbus::fdo::Error::InvalidArgs(format!("{}", err))
+            }
+            RogError::NotSupported => zbus::fdo::Error::NotSupported(format!("{}", err)),
+            _ => zbus::fdo::Error::Failed(format!("{}", err)),
+        }
     }
 }
 
@@ -0,0 +1,326 @@
+//! Async udev hotplug events for the `DeviceManager`, and mains power and lid
+//! changes for controller tasks.
+//!
+//! A USB device exposes several hidraw endpoints, each of which sends its own
+//! add or remove event, so events are collected per dbus path for a short
+//! window before being acted on.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rog_platform::power::AsusPower;
+use tokio::io::unix::AsyncFd;
+use tokio::time::Instant;
+use udev::{Event, EventType, MonitorBuilder, MonitorSocket};
+use zbus::proxy;
+use zbus::zvariant::OwnedObjectPath;
+
+use crate::error::RogError;
+
+/// How long to wait for more events from the same device before acting
+pub const DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Hidraw,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotplugAction {
+    Add,
+    /// A change event. Some devices are only usable after this
+    Change,
+    Remove,
+    /// Removed then added again within the debounce window
+    Replug,
+}
+
+/// A udev event with everything required taken out of it. `udev::Device` can
+/// not be sent between threads so is not kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotplugEvent {
+    pub action: HotplugAction,
+    pub subsystem: Subsystem,
+    pub syspath: PathBuf,
+    /// The path the device is, or would be, served at. Not all removal events
+    /// can be resolved to this from udev alone.
+    pub dbus_path: Option<OwnedObjectPath>,
+}
+
+impl HotplugEvent {
+    /// Returns `None` for events asusd is not interested in
+    pub fn from_udev(
+        event: &Event,
+        dbus_path: impl FnOnce(Subsystem, &Event) -> Option<OwnedObjectPath>,
+    ) -> Option<Self> {
+        let subsystem = match event.subsystem()?.to_str()? {
+            "hidraw" => Subsystem::Hidraw,
+            "block" => Subsystem::Block,
+            _ => return None,
+        };
+        let action = match event.event_type() {
+            EventType::Add => HotplugAction::Add,
+            EventType::Change => HotplugAction::Change,
+            EventType::Remove => HotplugAction::Remove,
+            _ => return None,
+        };
+        Some(Self {
+            action,
+            subsystem,
+            syspath: event.syspath().to_owned(),
+            dbus_path: dbus_path(subsystem, event),
+        })
+    }
+}
+
+/// The pending events for one dbus path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingDevice {
+    pub action: HotplugAction,
+    pub subsystem: Subsystem,
+    pub dbus_path: OwnedObjectPath,
+    /// Each endpoint seen, in the order the events arrived
+    pub syspaths: Vec<PathBuf>,
+    deadline: Instant,
+}
+
+/// Collects events per device until no more have arrived for `DEBOUNCE`
+#[derive(Debug, Default)]
+pub struct Debouncer {
+    pending: HashMap<OwnedObjectPath, PendingDevice>,
+}
+
+impl Debouncer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events without a dbus path are dropped
+    pub fn push(&mut self, event: HotplugEvent, now: Instant) {
+        let Some(dbus_path) = event.dbus_path else {
+            return;
+        };
+        let deadline = now + DEBOUNCE;
+        match self.pending.get_mut(&dbus_path) {
+            Some(pending) => {
+                pending.action = match (pending.action, event.action) {
+                    (HotplugAction::Remove | HotplugAction::Replug, HotplugAction::Add) => {
+                        pending.syspaths.clear();
+                        HotplugAction::Replug
+                    }
+                    // A change to a device being added is part of the add
+                    (HotplugAction::Add | HotplugAction::Replug, HotplugAction::Change) => {
+                        pending.action
+                    }
+                    (_, action) => action,
+                };
+                if !pending.syspaths.contains(&event.syspath) {
+                    pending.syspaths.push(event.syspath);
+                }
+                pending.deadline = deadline;
+            }
+            None => {
+                self.pending.insert(dbus_path.clone(), PendingDevice {
+                    action: event.action,
+                    subsystem: event.subsystem,
+                    dbus_path,
+                    syspaths: vec![event.syspath],
+                    deadline,
+                });
+            }
+        }
+    }
+
+    /// The earliest time a device will be ready
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|p| p.deadline).min()
+    }
+
+    /// Take every device that has had no events for `DEBOUNCE`
+    pub fn take_ready(&mut self, now: Instant) -> Vec<PendingDevice> {
+        let ready: Vec<OwnedObjectPath> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| p.deadline <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready
+            .iter()
+            .filter_map(|path| self.pending.remove(path))
+            .collect()
+    }
+}
+
+/// The udev monitor socket.
+struct UdevSocket(MonitorSocket);
+
+// SAFETY: the socket is owned by a single `UdevMonitor` and is never used
+// from more than one thread at a time, libudev only requires that objects
+// are not accessed concurrently.
+unsafe impl Send for UdevSocket {}
+
+impl AsRawFd for UdevSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// Receives udev events without blocking the runtime
+pub struct UdevMonitor {
+    socket: AsyncFd<UdevSocket>,
+}
+
+impl UdevMonitor {
+    /// Monitor hidraw and block devices
+    pub fn new() -> Result<Self, RogError> {
+        Self::with_subsystems(&[
+            "hidraw", "block",
+        ])
+    }
+
+    pub fn with_subsystems(subsystems: &[&str]) -> Result<Self, RogError> {
+        let mut builder = MonitorBuilder::new()?;
+        for subsystem in subsystems {
+            builder = builder.match_subsystem(subsystem)?;
+        }
+        Ok(Self {
+            socket: AsyncFd::new(UdevSocket(builder.listen()?))?,
+        })
+    }
+
+    /// Wait for the socket to have events, then convert them with `f`
+    pub async fn next_events<F, T>(&mut self, mut f: F) -> Result<Vec<T>, RogError>
+    where
+        F: FnMut(&Event) -> Option<T>,
+    {
+        // The socket is only `Send`, so it must be borrowed mutably
+        let mut guard = self.socket.readable_mut().await?;
+        let events = guard.get_inner().0.iter().filter_map(|e| f(&e)).collect();
+        guard.clear_ready();
+        Ok(events)
+    }
+}
+
+/// The lid state from UPower, as logind has no change signal for `LidClosed`
+#[proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+pub trait UPower {
+    #[zbus(property)]
+    fn lid_is_present(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn lid_is_closed(&self) -> zbus::Result<bool>;
+}
+
+/// Follows the mains power supply through its `power_supply` uevents
+pub struct MainsMonitor {
+    monitor: UdevMonitor,
+    power: AsusPower,
+    sysname: OsString,
+}
+
+impl MainsMonitor {
+    pub fn new() -> Result<Self, RogError> {
+        let power = AsusPower::new()?;
+        let sysname = power
+            .mains_path()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_owned())
+            .ok_or_else(|| RogError::MissingFunction("No mains power supply".to_owned()))?;
+        Ok(Self {
+            monitor: UdevMonitor::with_subsystems(&["power_supply"])?,
+            power,
+            sysname,
+        })
+    }
+
+    /// If mains power is connected
+    pub fn online(&self) -> Result<bool, RogError> {
+        Ok(self.power.get_online()? != 0)
+    }
+
+    /// Wait for the mains supply to change, then return if it is connected
+    pub async fn changed(&mut self) -> Result<bool, RogError> {
+        loop {
+            let sysname = &self.sysname;
+            let events = self
+                .monitor
+                .next_events(|event| (event.sysname() == sysname.as_os_str()).then_some(()))
+                .await?;
+            if !events.is_empty() {
+                return self.online();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tokio::time::Instant;
+    use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+
+    use super::{Debouncer, HotplugAction, HotplugEvent, Subsystem, DEBOUNCE};
+
+    fn event(action: HotplugAction, endpoint: &str, path: Option<&str>) -> HotplugEvent {
+        HotplugEvent {
+            action,
+            subsystem: Subsystem::Hidraw,
+            syspath: PathBuf::from(format!("/sys/class/hidraw/{endpoint}")),
+            dbus_path: path.map(|p| OwnedObjectPath::from(ObjectPath::from_str_unchecked(p))),
+        }
+    }
+
+    #[test]
+    fn debounce_endpoints() {
+        let kb = "/xyz/ljones/aura/19b6_3_7";
+        let now = Instant::now();
+        let mut debounce = Debouncer::new();
+        debounce.push(event(HotplugAction::Add, "hidraw0", Some(kb)), now);
+        debounce.push(event(HotplugAction::Add, "hidraw1", Some(kb)), now);
+        debounce.push(event(HotplugAction::Change, "hidraw1", Some(kb)), now);
+        debounce.push(event(HotplugAction::Add, "hidraw2", None), now);
+
+        assert!(debounce.take_ready(now).is_empty());
+        assert_eq!(debounce.next_deadline(), Some(now + DEBOUNCE));
+        let ready = debounce.take_ready(now + DEBOUNCE);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].action, HotplugAction::Add);
+        assert_eq!(ready[0].syspaths.len(), 2);
+        assert_eq!(debounce.next_deadline(), None);
+    }
+
+    #[test]
+    fn debounce_transitions() {
+        let kb = "/xyz/ljones/aura/19b6_3_7";
+        let disk = "/xyz/ljones/aura/ABC_scsi";
+        let now = Instant::now();
+        let mut debounce = Debouncer::new();
+        // Undock and redock
+        debounce.push(event(HotplugAction::Remove, "hidraw0", Some(kb)), now);
+        debounce.push(event(HotplugAction::Remove, "hidraw1", Some(kb)), now);
+        debounce.push(event(HotplugAction::Add, "hidraw3", Some(kb)), now);
+        // Added then gone again
+        debounce.push(event(HotplugAction::Add, "sda", Some(disk)), now);
+        debounce.push(event(HotplugAction::Remove, "sda", Some(disk)), now);
+
+        let ready = debounce.take_ready(now + DEBOUNCE);
+        assert_eq!(ready.len(), 2);
+        let disk = ready.iter().find(|p| p.dbus_path.as_str() == disk).unwrap();
+        assert_eq!(disk.action, HotplugAction::Remove);
+        let kb = ready.iter().find(|p| p.dbus_path.as_str() == kb).unwrap();
+        assert_eq!(kb.action, HotplugAction::Replug);
+        // Only the new endpoint is used to add the device again
+        assert_eq!(kb.syspaths, vec![
+            PathBuf::from("/sys/class/hidraw/hidraw3")
+        ]);
+    }
+}
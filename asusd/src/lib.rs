@@ -1,4 +1,5 @@
 #![deny(unused_must_use)]
+pub mod asus_armoury;
 pub mod aura_laptop;
 pub mod aura_manager;
 pub mod aura_scsi;
@@ -6,7 +7,15 @@ pub mod aura_slash;
 pub mod aura_types;
 /// Configuration loading, saving
 pub mod config;
+pub mod ctrl_backlight;
+pub mod ctrl_platform;
+pub mod device_registry;
 pub mod error;
+pub mod hotplug;
+pub mod scenes;
+pub mod schedules;
+pub mod state;
+pub mod transport;
 
 use std::future::Future;
 use std::time::Duration;
@@ -15,6 +24,7 @@ use dmi_id::DMIID;
 use futures_lite::stream::StreamExt;
 use log::{debug, info, warn};
 use logind_zbus::manager::ManagerProxy;
+use tokio::task::JoinHandle;
 use tokio::time::sleep;
 use zbus::object_server::{Interface, SignalEmitter};
 use zbus::proxy::CacheProperties;
@@ -22,6 +32,7 @@ use zbus::zvariant::ObjectPath;
 use zbus::Connection;
 
 use crate::error::RogError;
+use crate::hotplug::{MainsMonitor, UPowerProxy};
 
 const CONFIG_PATH_BASE: &str = "/etc/asusd/";
 pub const ASUS_ZBUS_PATH: &str = "/xyz/ljones";
@@ -30,6 +41,10 @@ pub static DBUS_NAME: &str = "xyz.ljones.Asusd";
 pub static DBUS_PATH: &str = "/xyz/ljones/Daemon";
 pub static DBUS_IFACE: &str = "xyz.ljones.Asusd";
 
+/// How often lid and power state are polled when change signals are not
+/// available
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 /// This macro adds a function which spawns an `inotify` task on the passed in
 /// `Executor`.
 ///
@@ -50,8 +65,7 @@ pub static DBUS_IFACE: &str = "xyz.ljones.Asusd";
 ///     task_watch_item!(panel_od platform);
 ///     task_watch_item!(gpu_mux_mode platform);
 /// }
-/// ```\
-/// // TODO: this is kind of useless if it can't trigger some action
+/// ```
 #[macro_export]
 macro_rules! task_watch_item {
     ($name:ident $name_str:literal $self_inner:ident) => {
@@ -146,6 +160,45 @@ pub trait ReloadAndNotify {
     ) -> impl Future<Output = Result<(), RogError>> + Send;
 }
 
+/// Handles to the tasks spawned for a controller. Dropping these leaves the
+/// tasks running, `abort()` must be called to stop them, e.g when a device is
+/// unplugged.
+#[derive(Debug, Default)]
+pub struct TaskHandles(Vec<JoinHandle<()>>);
+
+impl TaskHandles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, handle: JoinHandle<()>) {
+        self.0.push(handle);
+    }
+
+    pub fn extend(&mut self, other: TaskHandles) {
+        self.0.extend(other.0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Stop all the tasks
+    pub fn abort(&self) {
+        for handle in &self.0 {
+            handle.abort();
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.0.iter().all(|handle| handle.is_finished())
+    }
+}
+
 pub trait ZbusRun {
     fn add_to_server(self, server: &mut Connection) -> impl Future<Output = ()> + Send;
 
@@ -182,7 +235,7 @@ pub trait CtrlTask {
     fn create_tasks(
         &self,
         signal: SignalEmitter<'static>,
-    ) -> impl Future<Output = Result<(), RogError>> + Send;
+    ) -> impl Future<Output = Result<TaskHandles, RogError>> + Send;
 
     // /// Create a timed repeating task
     // async fn repeating_task(&self, millis: u64, mut task: impl FnMut() + Send +
@@ -200,13 +253,17 @@ pub trait CtrlTask {
     ///
     /// The closures can potentially block, so execution time should be the
     /// minimal possible such as save a variable.
+    ///
+    /// Returns the handles of the spawned tasks, or an error if logind can't
+    /// be reached on `connection`.
     fn create_sys_event_tasks<Fut1, Fut2, Fut3, Fut4, F1, F2, F3, F4>(
         &self,
+        connection: &Connection,
         mut on_prepare_for_sleep: F1,
         mut on_prepare_for_shutdown: F2,
         mut on_lid_change: F3,
         mut on_external_power_change: F4,
-    ) -> impl Future<Output = ()> + Send
+    ) -> impl Future<Output = Result<TaskHandles, RogError>> + Send
     where
         F1: FnMut(bool) -> Fut1 + Send + 'static,
         F2: FnMut(bool) -> Fut2 + Send + 'static,
@@ -217,19 +274,16 @@ pub trait CtrlTask {
         Fut3: Future<Output = ()> + Send,
         Fut4: Future<Output = ()> + Send,
     {
-        async {
-            let connection = Connection::system()
-                .await
-                .expect("Controller could not create dbus connection");
-
+        let connection = connection.clone();
+        async move {
             let manager = ManagerProxy::builder(&connection)
                 .cache_properties(CacheProperties::No)
                 .build()
-                .await
-                .expect("Controller could not create ManagerProxy");
+                .await?;
 
+            let mut tasks = TaskHandles::new();
             let manager1 = manager.clone();
-            tokio::spawn(async move {
+            tasks.push(tokio::spawn(async move {
                 if let Ok(mut notif) = manager1.receive_prepare_for_shutdown().await {
                     while let Some(event) = notif.next().await {
                         // blocks thread :|
@@ -239,10 +293,10 @@ pub trait CtrlTask {
                         }
                     }
                 }
-            });
+            }));
 
             let manager2 = manager.clone();
-            tokio::spawn(async move {
+            tasks.push(tokio::spawn(async move {
                 if let Ok(mut notif) = manager2.receive_prepare_for_sleep().await {
                     while let Some(event) = notif.next().await {
                         // blocks thread :|
@@ -252,12 +306,29 @@ pub trait CtrlTask {
                         }
                     }
                 }
-            });
+            }));
 
             let manager3 = manager.clone();
-            tokio::spawn(async move {
+            tasks.push(tokio::spawn(async move {
                 let mut last_power = manager3.on_external_power().await.unwrap_or_default();
 
+                match MainsMonitor::new() {
+                    Ok(mut mains) => loop {
+                        match mains.changed().await {
+                            Ok(next) => {
+                                if next != last_power {
+                                    last_power = next;
+                                    on_external_power_change(next).await;
+                                }
+                            }
+                            Err(e) => {
+                                warn!("Mains power monitor failed, polling instead: {e}");
+                                break;
+                            }
+                        }
+                    },
+                    Err(e) => warn!("Can't monitor mains power, polling instead: {e}"),
+                }
                 loop {
                     if let Ok(next) = manager3.on_external_power().await {
                         if next != last_power {
@@ -265,13 +336,36 @@ pub trait CtrlTask {
                             on_external_power_change(next).await;
                         }
                     }
-                    sleep(Duration::from_secs(2)).await;
+                    sleep(POLL_INTERVAL).await;
                 }
-            });
+            }));
 
-            tokio::spawn(async move {
+            tasks.push(tokio::spawn(async move {
                 let mut last_lid = manager.lid_closed().await.unwrap_or_default();
-                // need to loop on these as they don't emit signals
+
+                // logind never signals LidClosed changes, but UPower does for
+                // LidIsClosed when it can see the lid
+                match UPowerProxy::new(&connection).await {
+                    Ok(upower) => match upower.lid_is_present().await {
+                        Ok(true) => {
+                            let mut changes = upower.receive_lid_is_closed_changed().await;
+                            while let Some(change) = changes.next().await {
+                                if let Ok(next) = change.get().await {
+                                    if next != last_lid {
+                                        last_lid = next;
+                                        on_lid_change(next).await;
+                                    }
+                                }
+                            }
+                            warn!("UPower lid change signals stopped, polling instead");
+                        }
+                        Ok(false) => debug!("UPower has no lid, polling logind instead"),
+                        Err(e) => {
+                            warn!("Can't follow lid changes from UPower, polling instead: {e}")
+                        }
+                    },
+                    Err(e) => warn!("Can't follow lid changes from UPower, polling instead: {e}"),
+                }
                 loop {
                     if let Ok(next) = manager.lid_closed().await {
                         if next != last_lid {
@@ -279,9 +373,10 @@ pub trait CtrlTask {
                             on_lid_change(next).await;
                         }
                     }
-                    sleep(Duration::from_secs(2)).await;
+                    sleep(POLL_INTERVAL).await;
                 }
-            });
+            }));
+            Ok(tasks)
         }
     }
 }
@@ -307,6 +402,37 @@ where
         .unwrap_or_else(|err| warn!("Controller error: {}", err));
     zbus.add_to_server(connection).await;
 
-    zbus_clone.create_tasks(signal_ctx).await.ok();
+    // These controllers live as long as the daemon, so the tasks are never
+    // stopped
+    zbus_clone
+        .create_tasks(signal_ctx)
+        .await
+        .map_err(|e| warn!("Could not create tasks for {}: {e}", T::zbus_path()))
+        .ok();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::TaskHandles;
+
+    #[tokio::test]
+    async fn abort_task_handles() {
+        let mut tasks = TaskHandles::new();
+        for _ in 0..3 {
+            tasks.push(tokio::spawn(async {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }));
+        }
+        assert_eq!(tasks.len(), 3);
+        assert!(!tasks.is_finished());
+
+        tasks.abort();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(tasks.is_finished());
+    }
+}
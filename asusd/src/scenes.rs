@@ -0,0 +1,498 @@
+//! Named lighting scenes, served as `xyz.ljones.Scenes` at
+//! `/xyz/ljones/Daemon`.
+//!
+//! A scene keeps the lighting state of each Aura, Slash and SCSI device, keyed
+//! by the kind and product ID of the device, plus the serial of a SCSI disk.
+//! Unlike the dbus path these don't change when a device is replugged or the
+//! laptop reboots. Applying a scene locks every device config before writing
+//! anything, and puts back any device already written if one fails.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use config_traits::{StdConfig, StdConfigLoad};
+use futures_util::lock::{Mutex, MutexGuard};
+use log::{debug, warn};
+use rog_aura::keyboard::LaptopAuraPower;
+use rog_aura::{AuraEffect, AuraModeNum, LedBrightness};
+use rog_slash::SlashMode;
+use serde::{Deserialize, Serialize};
+use zbus::fdo::Error as FdoErr;
+use zbus::object_server::SignalEmitter;
+use zbus::{interface, Connection};
+
+use crate::aura_laptop::config::AuraConfig;
+use crate::aura_laptop::{Aura, AuraState};
+use crate::aura_scsi::config::ScsiConfig;
+use crate::aura_scsi::{ScsiAura, ScsiState};
+use crate::aura_slash::config::SlashConfig;
+use crate::aura_slash::Slash;
+use crate::aura_types::DeviceHandle;
+use crate::device_registry::{DeviceInfo, DeviceKind, DeviceRegistry};
+use crate::error::RogError;
+use crate::state::ConfigGuard;
+use crate::DBUS_PATH;
+
+const CONFIG_FILE: &str = "scenes.ron";
+
+/// The state of one device in a scene
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum DeviceScene {
+    Aura {
+        mode: AuraModeNum,
+        /// The builtin effect for the mode, or one per zone if multizone
+        effects: Vec<AuraEffect>,
+        brightness: LedBrightness,
+        enabled: LaptopAuraPower,
+    },
+    Slash {
+        mode: SlashMode,
+        brightness: u8,
+        interval: u8,
+    },
+    Scsi {
+        effect: rog_scsi::AuraEffect,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    /// Keyed by `scene_key()` of the device
+    pub devices: BTreeMap<String, DeviceScene>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct ScenesConfig {
+    pub scenes: BTreeMap<String, Scene>,
+}
+
+impl StdConfig for ScenesConfig {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn file_name(&self) -> String {
+        CONFIG_FILE.to_owned()
+    }
+
+    fn config_dir() -> std::path::PathBuf {
+        std::path::PathBuf::from(crate::CONFIG_PATH_BASE)
+    }
+}
+
+impl StdConfigLoad for ScenesConfig {}
+
+/// A device with its config locked
+enum Locked<'a> {
+    Aura(&'a Aura, ConfigGuard<'a, AuraConfig, AuraState>),
+    Slash(&'a Slash, MutexGuard<'a, SlashConfig>),
+    Scsi(&'a ScsiAura, ConfigGuard<'a, ScsiConfig, ScsiState>),
+}
+
+impl<'a> Locked<'a> {
+    async fn lock(handle: &'a DeviceHandle) -> Option<Self> {
+        match handle {
+            DeviceHandle::Aura(aura) => Some(Self::Aura(aura, aura.lock_config().await)),
+            DeviceHandle::Slash(slash) => Some(Self::Slash(slash, slash.lock_config().await)),
+            DeviceHandle::Scsi(scsi) => Some(Self::Scsi(scsi, scsi.lock_config().await)),
+            _ => None,
+        }
+    }
+
+    fn capture(&self) -> DeviceScene {
+        match self {
+            Self::Aura(_, config) => {
+                let mode = config.policy_mode();
+                let effects = if config.multizone_on {
+                    config.get_multizone(mode).unwrap_or_default().to_vec()
+                } else {
+                    config.builtins.get(&mode).cloned().into_iter().collect()
+                };
+                DeviceScene::Aura {
+                    mode,
+                    effects,
+                    brightness: config
+                        .power_policy()
+                        .brightness
+                        .unwrap_or(config.brightness),
+                    enabled: config.enabled.clone(),
+                }
+            }
+            Self::Slash(_, config) => DeviceScene::Slash {
+                mode: config.display_mode,
+                brightness: config.brightness,
+                interval: config.display_interval,
+            },
+            Self::Scsi(_, config) => DeviceScene::Scsi {
+                effect: config
+                    .modes
+                    .get(&config.current_mode)
+                    .cloned()
+                    .unwrap_or_else(|| {
+                        rog_scsi::AuraEffect::default_with_mode(config.current_mode)
+                    }),
+            },
+        }
+    }
+
+    /// Change the config to match `scene` and write it to the device. The
+    /// config is not saved.
+    async fn apply(&mut self, scene: &DeviceScene) -> Result<(), RogError> {
+        match (self, scene) {
+            (
+                Self::Aura(aura, config),
+                DeviceScene::Aura {
+                    mode,
+                    effects,
+                    brightness,
+                    enabled,
+                },
+            ) => {
+                config.set_mode(*mode);
+                for effect in effects {
+                    config.set_builtin(effect.clone());
+                }
+                config.set_brightness(*brightness);
+                config.enabled = enabled.clone();
+                aura.apply_power_policy(config).await
+            }
+            (
+                Self::Slash(slash, config),
+                DeviceScene::Slash {
+                    mode,
+                    brightness,
+                    interval,
+                },
+            ) => {
+                config.enabled = *brightness > 0;
+                config.brightness = *brightness;
+                config.display_interval = *interval;
+                config.display_mode = *mode;
+                slash.write_display(config).await
+            }
+            (Self::Scsi(scsi, config), DeviceScene::Scsi { effect }) => {
+                scsi.write_effect(effect).await?;
+                config.save_effect(effect.clone());
+                Ok(())
+            }
+            _ => Err(RogError::MissingFunction(
+                "Scene is for a different kind of device".to_owned(),
+            )),
+        }
+    }
+
+    fn save(&self) {
+        match self {
+            Self::Aura(_, config) => config.write(),
+            Self::Slash(_, config) => config.write(),
+            Self::Scsi(_, config) => config.write(),
+        }
+    }
+}
+
+/// Lock each device, sorted by key so that two callers can't deadlock
+async fn lock_devices(handles: &[(String, DeviceHandle)]) -> Vec<(&str, Locked<'_>)> {
+    let mut sorted: Vec<&(String, DeviceHandle)> = handles.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut locked = Vec::new();
+    for (key, handle) in sorted {
+        if let Some(device) = Locked::lock(handle).await {
+            locked.push((key.as_str(), device));
+        }
+    }
+    locked
+}
+
+/// Capture the current state of every device
+pub async fn capture_scene(handles: &[(String, DeviceHandle)]) -> Scene {
+    let devices = lock_devices(handles)
+        .await
+        .iter()
+        .map(|(key, device)| (key.to_string(), device.capture()))
+        .collect();
+    Scene { devices }
+}
+
+/// Apply `scene` to every device it has state for. If any device fails those
+/// already written are put back as they were, and nothing is saved.
+pub async fn apply_scene(
+    handles: &[(String, DeviceHandle)],
+    scene: &Scene,
+    save: bool,
+) -> Result<(), RogError> {
+    let mut locked = lock_devices(handles).await;
+    let mut previous = Vec::new();
+    let mut failed = None;
+    for (index, (key, device)) in locked.iter_mut().enumerate() {
+        let Some(state) = scene.devices.get(*key) else {
+            continue;
+        };
+        previous.push((index, device.capture()));
+        if let Err(e) = device.apply(state).await {
+            failed = Some(RogError::MissingFunction(format!(
+                "Could not apply scene to {key}: {e}"
+            )));
+            break;
+        }
+    }
+
+    if let Some(err) = failed {
+        for (index, state) in previous {
+            let (key, device) = &mut locked[index];
+            device
+                .apply(&state)
+                .await
+                .unwrap_or_else(|e| warn!("Could not restore {key}: {e}"));
+        }
+        return Err(err);
+    }
+    if save {
+        for (index, _) in previous {
+            locked[index].1.save();
+        }
+    }
+    Ok(())
+}
+
+/// Serves the saved scenes. Devices are found through the `DeviceRegistry`.
+#[derive(Clone)]
+pub struct Scenes {
+    registry: DeviceRegistry,
+    config: Arc<Mutex<ScenesConfig>>,
+}
+
+impl Scenes {
+    pub fn new(registry: DeviceRegistry, config: ScenesConfig) -> Self {
+        Self {
+            registry,
+            config: Arc::new(Mutex::new(config)),
+        }
+    }
+
+    pub async fn start_tasks(&self, connection: &Connection) -> Result<(), zbus::Error> {
+        connection
+            .object_server()
+            .at(DBUS_PATH, self.clone())
+            .await?;
+        Ok(())
+    }
+
+    /// The saved scenes, shared with `Schedules`
+    pub fn config(&self) -> Arc<Mutex<ScenesConfig>> {
+        self.config.clone()
+    }
+
+    async fn handles(&self) -> Vec<(String, DeviceHandle)> {
+        device_handles(&self.registry).await
+    }
+}
+
+/// The key of a device in a scene, such as `Aura:19b6` or
+/// `Scsi:1932:<serial>`. The dbus path can't be used as it has the USB device
+/// number, which changes on replug.
+pub fn scene_key(info: &DeviceInfo) -> String {
+    match info.kind {
+        DeviceKind::Scsi => format!("{:?}:{}:{}", info.kind, info.prod_id, info.serial),
+        kind => format!("{kind:?}:{}", info.prod_id),
+    }
+}
+
+/// Each device in the registry, keyed by `scene_key()`
+pub async fn device_handles(registry: &DeviceRegistry) -> Vec<(String, DeviceHandle)> {
+    registry
+        .handles()
+        .await
+        .into_iter()
+        .map(|(info, handle)| (scene_key(&info), handle))
+        .collect()
+}
+
+fn check_name(name: &str) -> Result<(), FdoErr> {
+    if name.trim().is_empty() {
+        return Err(FdoErr::InvalidArgs("A scene name is required".to_owned()));
+    }
+    Ok(())
+}
+
+#[interface(name = "xyz.ljones.Scenes")]
+impl Scenes {
+    /// The names of all saved scenes
+    async fn list_scenes(&self) -> Vec<String> {
+        self.config.lock().await.scenes.keys().cloned().collect()
+    }
+
+    /// Save the current state of every device as `name`, replacing any scene
+    /// of the same name
+    async fn save_scene(&self, name: &str) -> Result<(), FdoErr> {
+        check_name(name)?;
+        let scene = capture_scene(&self.handles().await).await;
+        debug!("Saving scene {name}: {scene:?}");
+        let mut config = self.config.lock().await;
+        config.scenes.insert(name.to_owned(), scene);
+        config.write();
+        Ok(())
+    }
+
+    /// Apply the scene to every device at once. Devices in the scene that are
+    /// not present are skipped.
+    async fn apply_scene(
+        &self,
+        name: &str,
+        #[zbus(signal_emitter)] ctxt: SignalEmitter<'_>,
+    ) -> Result<(), FdoErr> {
+        // Held so that scenes are applied one at a time
+        let config = self.config.lock().await;
+        let scene = config
+            .scenes
+            .get(name)
+            .ok_or_else(|| FdoErr::InvalidArgs(format!("No scene named {name}")))?;
+        apply_scene(&self.handles().await, scene, true).await?;
+        drop(config);
+        Self::scene_applied(&ctxt, name).await?;
+        Ok(())
+    }
+
+    async fn delete_scene(&self, name: &str) -> Result<(), FdoErr> {
+        let mut config = self.config.lock().await;
+        if config.scenes.remove(name).is_none() {
+            return Err(FdoErr::InvalidArgs(format!("No scene named {name}")));
+        }
+        config.write();
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn scene_applied(ctxt: &SignalEmitter<'_>, name: &str) -> zbus::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_util::lock::Mutex;
+    use rog_scsi::AuraMode;
+    use rog_slash::{SlashMode, SlashType};
+    use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+
+    use super::{apply_scene, capture_scene, scene_key, DeviceScene};
+    use crate::aura_scsi::config::ScsiConfig;
+    use crate::aura_scsi::ScsiAura;
+    use crate::aura_slash::config::SlashConfig;
+    use crate::aura_slash::Slash;
+    use crate::aura_types::DeviceHandle;
+    use crate::device_registry::{DeviceInfo, DeviceKind};
+    use crate::transport::RecordingTransport;
+
+    #[test]
+    fn keys_ignore_the_path() {
+        let info = |kind, serial, path| {
+            let path = OwnedObjectPath::from(ObjectPath::from_str_unchecked(path));
+            DeviceInfo::new(kind, "19b6", serial, path)
+        };
+        // The device number in the path changed on replug
+        assert_eq!(
+            scene_key(&info(DeviceKind::Aura, "", "/xyz/ljones/aura/19b6_3_7")),
+            scene_key(&info(DeviceKind::Aura, "", "/xyz/ljones/aura/19b6_5_7"))
+        );
+        assert_eq!(
+            scene_key(&info(DeviceKind::Aura, "", "/xyz/ljones/aura/19b6_3_7")),
+            "Aura:19b6"
+        );
+        // Two disks of the same model are told apart
+        assert_ne!(
+            scene_key(&info(DeviceKind::Scsi, "A", "/xyz/ljones/aura/A_scsi")),
+            scene_key(&info(DeviceKind::Scsi, "B", "/xyz/ljones/aura/B_scsi"))
+        );
+    }
+
+    #[tokio::test]
+    async fn capture_and_apply() {
+        let slash_rec = RecordingTransport::new();
+        let scsi_rec = RecordingTransport::new();
+        let slash = Slash::new(
+            slash_rec.shared(),
+            Arc::new(Mutex::new(SlashConfig {
+                slash_type: SlashType::GA605,
+                ..Default::default()
+            })),
+        );
+        let scsi = ScsiAura::new(scsi_rec.shared(), ScsiConfig::default());
+        let handles = vec![
+            ("Scsi:1932:A".to_owned(), DeviceHandle::Scsi(scsi.clone())),
+            ("Slash:193b".to_owned(), DeviceHandle::Slash(slash.clone())),
+        ];
+
+        let mut scene = capture_scene(&handles).await;
+        assert_eq!(scene.devices.len(), 2);
+        assert!(slash_rec.packets().is_empty());
+        scene
+            .devices
+            .insert("Slash:193b".to_owned(), DeviceScene::Slash {
+                mode: SlashMode::Flow,
+                brightness: 0,
+                interval: 2,
+            });
+        scene
+            .devices
+            .insert("Scsi:1932:A".to_owned(), DeviceScene::Scsi {
+                effect: rog_scsi::AuraEffect::default_with_mode(AuraMode::Breathe),
+            });
+        // A device that isn't present is skipped
+        scene
+            .devices
+            .insert("Slash:0000".to_owned(), DeviceScene::Slash {
+                mode: SlashMode::Bounce,
+                brightness: 1,
+                interval: 1,
+            });
+
+        apply_scene(&handles, &scene, false).await.unwrap();
+        let config = slash.lock_config().await;
+        assert_eq!(config.display_mode, SlashMode::Flow);
+        assert!(!config.enabled);
+        assert_eq!(config.display_interval, 2);
+        drop(config);
+        assert_eq!(scsi.state().current_mode, AuraMode::Breathe);
+        // options, mode, save
+        assert_eq!(slash_rec.packets().len(), 3);
+        assert!(!scsi_rec.packets().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_apply_is_rolled_back() {
+        let rec = RecordingTransport::new();
+        let first = ScsiAura::new(rec.shared(), ScsiConfig::default());
+        let second = ScsiAura::new(RecordingTransport::new().shared(), ScsiConfig::default());
+        let handles = vec![
+            ("Scsi:1932:A".to_owned(), DeviceHandle::Scsi(first.clone())),
+            ("Scsi:1932:B".to_owned(), DeviceHandle::Scsi(second)),
+        ];
+        let before = capture_scene(&handles).await;
+
+        let mut scene = before.clone();
+        scene
+            .devices
+            .insert("Scsi:1932:A".to_owned(), DeviceScene::Scsi {
+                effect: rog_scsi::AuraEffect::default_with_mode(AuraMode::Breathe),
+            });
+        // Can't be applied to a SCSI device
+        scene
+            .devices
+            .insert("Scsi:1932:B".to_owned(), DeviceScene::Slash {
+                mode: SlashMode::Flow,
+                brightness: 1,
+                interval: 1,
+            });
+        assert!(apply_scene(&handles, &scene, false).await.is_err());
+        assert_eq!(capture_scene(&handles).await, before);
+        // The first device was written, then restored
+        let restore = RecordingTransport::new();
+        ScsiAura::new(restore.shared(), ScsiConfig::default())
+            .do_initialization()
+            .await
+            .unwrap();
+        let packets = rec.packets();
+        assert!(packets.len() > restore.packets().len());
+        assert!(packets.ends_with(&restore.packets()));
+    }
+}
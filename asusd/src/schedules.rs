@@ -0,0 +1,486 @@
+//! Time-of-day lighting schedules, served as `xyz.ljones.Schedules` at
+//! `/xyz/ljones/Daemon`.
+//!
+//! Each rule covers a span of the day and may apply a saved scene, a keyboard
+//! brightness, and builtin effects. The rules are checked at the start of each
+//! minute in local time, so DST changes are followed, and again on resume
+//! since the timer does not run while suspended. When rules overlap the one
+//! with the highest priority wins. Lighting is only written when the winning
+//! rule changes, so changes made by hand last until the next rule starts.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use config_traits::{StdConfig, StdConfigLoad};
+use futures_util::lock::Mutex;
+use log::{debug, info, warn};
+use rog_aura::{AuraEffect, AuraZone, LedBrightness};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use zbus::fdo::Error as FdoErr;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::Type;
+use zbus::{interface, Connection};
+
+use crate::aura_types::DeviceHandle;
+use crate::device_registry::DeviceRegistry;
+use crate::error::RogError;
+use crate::scenes::{apply_scene, device_handles, Scenes, ScenesConfig};
+use crate::{CtrlTask, Reloadable, TaskHandles, ZbusRun, DBUS_PATH};
+
+const CONFIG_FILE: &str = "schedules.ron";
+
+/// A time of day in local time, written as `HH:MM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Type)]
+#[serde(try_from = "String", into = "String")]
+#[zvariant(signature = "s")]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl TimeOfDay {
+    pub fn new(hour: u8, minute: u8) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(Self { hour, minute })
+    }
+
+    /// The current local time
+    pub fn now() -> Self {
+        let (hour, minute, _) = local_time();
+        Self { hour, minute }
+    }
+
+    fn minutes(&self) -> u16 {
+        self.hour as u16 * 60 + self.minute as u16
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid time {s}, expected HH:MM");
+        let (hour, minute) = s.trim().split_once(':').ok_or_else(err)?;
+        let hour = hour.parse().map_err(|_| err())?;
+        let minute = minute.parse().map_err(|_| err())?;
+        Self::new(hour, minute).ok_or_else(err)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+extern "C" {
+    // Not in the libc crate. `localtime_r` doesn't reload the timezone itself.
+    fn tzset();
+}
+
+/// The local time as `(hour, minute, second)`. The timezone is read again on
+/// each call so that DST and timezone changes are seen.
+fn local_time() -> (u8, u8, u8) {
+    // SAFETY: `time` and `localtime_r` only write to the given pointers
+    let tm = unsafe {
+        tzset();
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    (tm.tm_hour as u8, tm.tm_min as u8, tm.tm_sec as u8)
+}
+
+/// The brightness a rule sets on Aura keyboards
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[zvariant(signature = "s")]
+pub enum RuleBrightness {
+    /// Leave the brightness as it is
+    #[default]
+    Unchanged,
+    Off,
+    Low,
+    Med,
+    High,
+}
+
+impl From<RuleBrightness> for Option<LedBrightness> {
+    fn from(brightness: RuleBrightness) -> Self {
+        match brightness {
+            RuleBrightness::Unchanged => None,
+            RuleBrightness::Off => Some(LedBrightness::Off),
+            RuleBrightness::Low => Some(LedBrightness::Low),
+            RuleBrightness::Med => Some(LedBrightness::Med),
+            RuleBrightness::High => Some(LedBrightness::High),
+        }
+    }
+}
+
+impl FromStr for RuleBrightness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "unchanged" => Ok(Self::Unchanged),
+            "off" => Ok(Self::Off),
+            "low" => Ok(Self::Low),
+            "med" => Ok(Self::Med),
+            "high" => Ok(Self::High),
+            _ => Err(format!(
+                "Invalid brightness {s}, expected off, low, med, or high"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct ScheduleRule {
+    pub start: TimeOfDay,
+    /// The rule stops at the start of this minute. If before `start` the rule
+    /// runs over midnight, if the same as `start` it runs all day.
+    pub end: TimeOfDay,
+    /// The highest priority wins when rules overlap
+    pub priority: i32,
+    /// A saved scene to apply first, empty for none
+    pub scene: String,
+    pub brightness: RuleBrightness,
+    /// Builtin effects for Aura keyboards, applied after the scene. Effects a
+    /// keyboard doesn't support are skipped.
+    pub effects: Vec<AuraEffect>,
+}
+
+impl ScheduleRule {
+    pub fn active_at(&self, time: TimeOfDay) -> bool {
+        let (start, end, now) = (self.start.minutes(), self.end.minutes(), time.minutes());
+        match start.cmp(&end) {
+            std::cmp::Ordering::Less => start <= now && now < end,
+            std::cmp::Ordering::Greater => now >= start || now < end,
+            std::cmp::Ordering::Equal => true,
+        }
+    }
+
+    /// Minutes since the rule last started
+    fn started_ago(&self, time: TimeOfDay) -> u16 {
+        (time.minutes() + 24 * 60 - self.start.minutes()) % (24 * 60)
+    }
+}
+
+/// The rule in force at `time`. Of overlapping rules the highest priority
+/// wins, then the one that started most recently, then the first by name.
+pub fn active_rule(
+    rules: &BTreeMap<String, ScheduleRule>,
+    time: TimeOfDay,
+) -> Option<(&String, &ScheduleRule)> {
+    rules
+        .iter()
+        .filter(|(_, rule)| rule.active_at(time))
+        .min_by_key(|(_, rule)| (-(rule.priority as i64), rule.started_ago(time)))
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct SchedulesConfig {
+    pub rules: BTreeMap<String, ScheduleRule>,
+}
+
+impl StdConfig for SchedulesConfig {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn file_name(&self) -> String {
+        CONFIG_FILE.to_owned()
+    }
+
+    fn config_dir() -> std::path::PathBuf {
+        std::path::PathBuf::from(crate::CONFIG_PATH_BASE)
+    }
+}
+
+impl StdConfigLoad for SchedulesConfig {}
+
+/// Runs the schedule rules. Scenes are shared with `Scenes` so both see the
+/// same saved scenes.
+#[derive(Clone)]
+pub struct Schedules {
+    registry: DeviceRegistry,
+    scenes: Arc<Mutex<ScenesConfig>>,
+    config: Arc<Mutex<SchedulesConfig>>,
+    /// The name of the rule last applied
+    active: Arc<Mutex<Option<String>>>,
+}
+
+impl Schedules {
+    pub fn new(registry: DeviceRegistry, scenes: &Scenes, config: SchedulesConfig) -> Self {
+        Self {
+            registry,
+            scenes: scenes.config(),
+            config: Arc::new(Mutex::new(config)),
+            active: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Apply the rule in force now if it differs from the one last applied, or
+    /// always if `force`
+    async fn evaluate(&self, force: bool) {
+        let config = self.config.lock().await;
+        let now = TimeOfDay::now();
+        let rule = active_rule(&config.rules, now);
+        let mut active = self.active.lock().await;
+        if !force && active.as_ref() == rule.map(|(name, _)| name) {
+            return;
+        }
+        *active = rule.map(|(name, _)| name.clone());
+        if let Some((name, rule)) = rule {
+            info!("Applying schedule rule {name} at {now}");
+            self.apply_rule(name, rule).await;
+        }
+    }
+
+    async fn apply_rule(&self, name: &str, rule: &ScheduleRule) {
+        let handles = device_handles(&self.registry).await;
+        if !rule.scene.is_empty() {
+            let scenes = self.scenes.lock().await;
+            match scenes.scenes.get(&rule.scene) {
+                Some(scene) => apply_scene(&handles, scene, true)
+                    .await
+                    .unwrap_or_else(|e| warn!("Schedule rule {name}: {e}")),
+                None => warn!("Schedule rule {name}: no scene named {}", rule.scene),
+            }
+        }
+
+        let brightness: Option<LedBrightness> = rule.brightness.into();
+        if brightness.is_none() && rule.effects.is_empty() {
+            return;
+        }
+        for (path, handle) in &handles {
+            let DeviceHandle::Aura(aura) = handle else {
+                continue;
+            };
+            let mut config = aura.lock_config().await;
+            if let Some(brightness) = brightness {
+                config.set_brightness(brightness);
+            }
+            for effect in &rule.effects {
+                let support = &config.support_data;
+                if support.basic_modes.contains(&effect.mode)
+                    && (effect.zone == AuraZone::None || support.basic_zones.contains(&effect.zone))
+                {
+                    config.set_builtin(effect.clone());
+                } else {
+                    debug!("Schedule rule {name}: {path} does not support {effect:?}");
+                }
+            }
+            match aura.apply_power_policy(&mut config).await {
+                Ok(()) => config.write(),
+                Err(e) => warn!("Schedule rule {name}: could not apply to {path}: {e}"),
+            }
+        }
+    }
+}
+
+fn check_rule(name: &str, rule: &ScheduleRule, scenes: &ScenesConfig) -> Result<(), FdoErr> {
+    if name.trim().is_empty() {
+        return Err(FdoErr::InvalidArgs("A rule name is required".to_owned()));
+    }
+    if rule.scene.is_empty()
+        && rule.brightness == RuleBrightness::Unchanged
+        && rule.effects.is_empty()
+    {
+        return Err(FdoErr::InvalidArgs(
+            "A rule must set a scene, brightness, or effect".to_owned(),
+        ));
+    }
+    if !rule.scene.is_empty() && !scenes.scenes.contains_key(&rule.scene) {
+        return Err(FdoErr::InvalidArgs(format!(
+            "No scene named {}",
+            rule.scene
+        )));
+    }
+    Ok(())
+}
+
+#[interface(name = "xyz.ljones.Schedules")]
+impl Schedules {
+    /// All rules, keyed by name
+    async fn list_rules(&self) -> BTreeMap<String, ScheduleRule> {
+        self.config.lock().await.rules.clone()
+    }
+
+    /// The name of the rule in force now, empty if none
+    async fn active_rule(&self) -> String {
+        let config = self.config.lock().await;
+        active_rule(&config.rules, TimeOfDay::now())
+            .map(|(name, _)| name.clone())
+            .unwrap_or_default()
+    }
+
+    /// Add a rule, or replace the rule of the same name. The rules are checked
+    /// again straight away.
+    async fn set_rule(&self, name: &str, rule: ScheduleRule) -> Result<(), FdoErr> {
+        check_rule(name, &rule, &*self.scenes.lock().await)?;
+        let mut config = self.config.lock().await;
+        config.rules.insert(name.to_owned(), rule);
+        config.write();
+        drop(config);
+        let replaced = self.active.lock().await.as_deref() == Some(name);
+        self.evaluate(replaced).await;
+        Ok(())
+    }
+
+    /// Remove a rule. Lighting is left as it is unless another rule now wins.
+    async fn delete_rule(&self, name: &str) -> Result<(), FdoErr> {
+        let mut config = self.config.lock().await;
+        if config.rules.remove(name).is_none() {
+            return Err(FdoErr::InvalidArgs(format!("No rule named {name}")));
+        }
+        config.write();
+        drop(config);
+        self.evaluate(false).await;
+        Ok(())
+    }
+}
+
+impl ZbusRun for Schedules {
+    async fn add_to_server(self, server: &mut Connection) {
+        Self::add_to_server_helper(self, DBUS_PATH, server).await;
+    }
+}
+
+impl Reloadable for Schedules {
+    /// Apply the rule in force on boot
+    async fn reload(&mut self) -> Result<(), RogError> {
+        self.evaluate(true).await;
+        Ok(())
+    }
+}
+
+impl CtrlTask for Schedules {
+    fn zbus_path() -> &'static str {
+        DBUS_PATH
+    }
+
+    async fn create_tasks(
+        &self,
+        signal_ctx: SignalEmitter<'static>,
+    ) -> Result<TaskHandles, RogError> {
+        let schedules = self.clone();
+        let mut tasks = self
+            .create_sys_event_tasks(
+                signal_ctx.connection(),
+                move |sleeping| {
+                    let schedules = schedules.clone();
+                    async move {
+                        // Rules may have started or ended while suspended
+                        if !sleeping {
+                            schedules.evaluate(false).await;
+                        }
+                    }
+                },
+                move |_| async {},
+                move |_| async {},
+                move |_| async {},
+            )
+            .await?;
+
+        let schedules = self.clone();
+        tasks.push(tokio::spawn(async move {
+            loop {
+                // Wake at the start of each minute. The wait is measured on
+                // the monotonic clock, so is checked again after a clock change.
+                let (_, _, second) = local_time();
+                sleep(Duration::from_secs(
+                    60u64.saturating_sub(second as u64).max(1),
+                ))
+                .await;
+                schedules.evaluate(false).await;
+            }
+        }));
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{active_rule, RuleBrightness, ScheduleRule, TimeOfDay};
+
+    fn rule(start: &str, end: &str, priority: i32) -> ScheduleRule {
+        ScheduleRule {
+            start: start.parse().unwrap(),
+            end: end.parse().unwrap(),
+            priority,
+            scene: String::new(),
+            brightness: RuleBrightness::Low,
+            effects: Vec::new(),
+        }
+    }
+
+    fn at(time: &str) -> TimeOfDay {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn time_of_day() {
+        assert_eq!(at("7:05"), TimeOfDay::new(7, 5).unwrap());
+        assert_eq!(at("22:00").to_string(), "22:00");
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("12:60".parse::<TimeOfDay>().is_err());
+        assert!("noon".parse::<TimeOfDay>().is_err());
+    }
+
+    #[test]
+    fn rule_spans() {
+        let day = rule("08:00", "22:00", 0);
+        assert!(day.active_at(at("08:00")));
+        assert!(day.active_at(at("21:59")));
+        assert!(!day.active_at(at("22:00")));
+        assert!(!day.active_at(at("07:59")));
+
+        let night = rule("22:00", "08:00", 0);
+        assert!(night.active_at(at("23:30")));
+        assert!(night.active_at(at("00:00")));
+        assert!(!night.active_at(at("08:00")));
+        assert!(!night.active_at(at("12:00")));
+
+        assert!(rule("06:00", "06:00", 0).active_at(at("05:59")));
+    }
+
+    #[test]
+    fn overlapping_rules() {
+        let mut rules = BTreeMap::new();
+        rules.insert("all-day".to_owned(), rule("00:00", "00:00", -1));
+        rules.insert("night".to_owned(), rule("22:00", "08:00", 0));
+        rules.insert("late".to_owned(), rule("23:00", "01:00", 0));
+        rules.insert("meeting".to_owned(), rule("09:00", "10:00", 5));
+        rules.insert("work".to_owned(), rule("08:30", "17:00", 5));
+
+        let name = |time| active_rule(&rules, at(time)).map(|(name, _)| name.as_str());
+        assert_eq!(name("12:00"), Some("work"));
+        // Same priority, the most recent start wins
+        assert_eq!(name("09:30"), Some("meeting"));
+        assert_eq!(name("22:30"), Some("night"));
+        assert_eq!(name("00:30"), Some("late"));
+        assert_eq!(name("07:00"), Some("night"));
+        assert_eq!(name("18:00"), Some("all-day"));
+        assert_eq!(active_rule(&BTreeMap::new(), at("12:00")), None);
+    }
+}
@@ -0,0 +1,148 @@
+//! Device config shared between dbus methods and tasks.
+//!
+//! Writers take the config lock as before, which queues them behind any write
+//! in flight such as a per-key frame. When the lock is released a snapshot of
+//! the parts readers use is published, so property reads never wait on or
+//! fail because of a writer.
+
+use std::ops::{Deref, DerefMut};
+
+use futures_util::lock::{Mutex, MutexGuard};
+use tokio::sync::watch;
+
+/// A config and the snapshot published from it
+#[derive(Debug)]
+pub struct SharedConfig<C, S> {
+    config: Mutex<C>,
+    state: watch::Sender<S>,
+}
+
+impl<C, S> SharedConfig<C, S>
+where
+    S: for<'a> From<&'a C> + PartialEq,
+{
+    pub fn new(config: C) -> Self {
+        let state = watch::Sender::new(S::from(&config));
+        Self {
+            config: Mutex::new(config),
+            state,
+        }
+    }
+
+    /// Lock the config for writing. Readers should use `state()` instead.
+    pub async fn lock(&self) -> ConfigGuard<'_, C, S> {
+        ConfigGuard {
+            config: self.config.lock().await,
+            state: &self.state,
+        }
+    }
+
+    /// The snapshot as of the last time the config was unlocked
+    pub fn state(&self) -> watch::Ref<'_, S> {
+        self.state.borrow()
+    }
+
+    /// To be notified of each change to the snapshot
+    pub fn subscribe(&self) -> watch::Receiver<S> {
+        self.state.subscribe()
+    }
+}
+
+/// Publishes a new snapshot when dropped, if it differs from the last
+pub struct ConfigGuard<'a, C, S>
+where
+    S: for<'b> From<&'b C> + PartialEq,
+{
+    config: MutexGuard<'a, C>,
+    state: &'a watch::Sender<S>,
+}
+
+impl<C, S> Deref for ConfigGuard<'_, C, S>
+where
+    S: for<'b> From<&'b C> + PartialEq,
+{
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.config
+    }
+}
+
+impl<C, S> DerefMut for ConfigGuard<'_, C, S>
+where
+    S: for<'b> From<&'b C> + PartialEq,
+{
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.config
+    }
+}
+
+impl<C, S> Drop for ConfigGuard<'_, C, S>
+where
+    S: for<'b> From<&'b C> + PartialEq,
+{
+    fn drop(&mut self) {
+        let new = S::from(&self.config);
+        self.state.send_if_modified(|state| {
+            if *state != new {
+                *state = new;
+                return true;
+            }
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::SharedConfig;
+
+    #[derive(Debug, Default)]
+    struct Config {
+        mode: u8,
+        frames: u32,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct State {
+        mode: u8,
+    }
+
+    impl From<&Config> for State {
+        fn from(c: &Config) -> Self {
+            Self { mode: c.mode }
+        }
+    }
+
+    #[tokio::test]
+    async fn readers_never_wait() {
+        let shared: Arc<SharedConfig<Config, State>> =
+            Arc::new(SharedConfig::new(Config::default()));
+        let mut changes = shared.subscribe();
+
+        let mut config = shared.lock().await;
+        config.mode = 3;
+        // A write is in flight, readers see the last snapshot
+        assert_eq!(shared.state().mode, 0);
+        drop(config);
+        assert_eq!(shared.state().mode, 3);
+        assert!(changes.has_changed().unwrap());
+        changes.borrow_and_update();
+
+        // Changes readers can't see aren't published
+        let writer = shared.clone();
+        tokio::spawn(async move {
+            for _ in 0..10 {
+                writer.lock().await.frames += 1;
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(!changes.has_changed().unwrap());
+        assert_eq!(shared.lock().await.frames, 10);
+    }
+}
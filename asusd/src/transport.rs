@@ -0,0 +1,178 @@
+//! A common raw packet transport for the devices asusd controls.
+//!
+//! Aura, Slash and SCSI controllers only ever write raw packets to their
+//! device. Hiding the concrete handle (`HidRaw`, `USBRaw`, `sg::Device`) behind
+//! `Transport` means the controllers can be driven by a `RecordingTransport`
+//! in tests, and the exact packets emitted can be checked.
+
+use std::fmt;
+use std::sync::Arc;
+
+use futures_util::lock::Mutex;
+use rog_platform::hid_raw::HidRaw;
+use rog_platform::usb_raw::USBRaw;
+use rog_scsi::task_from_packet;
+
+use crate::error::RogError;
+
+/// Something raw packets can be written to.
+pub trait Transport: Send {
+    /// Write a single raw packet to the device
+    fn write_bytes(&mut self, message: &[u8]) -> Result<(), RogError>;
+}
+
+impl fmt::Debug for dyn Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Transport")
+    }
+}
+
+/// The form controllers hold a transport in. A single device handle may be
+/// shared between controllers, e.g, a hidraw node used for both Aura and Slash.
+pub type SharedTransport = Arc<Mutex<dyn Transport>>;
+
+impl Transport for HidRaw {
+    fn write_bytes(&mut self, message: &[u8]) -> Result<(), RogError> {
+        Ok(HidRaw::write_bytes(self, message)?)
+    }
+}
+
+impl Transport for USBRaw {
+    fn write_bytes(&mut self, message: &[u8]) -> Result<(), RogError> {
+        USBRaw::write_bytes(self, message)?;
+        Ok(())
+    }
+}
+
+/// SCSI packets are the 16 byte CDB followed by the data to write, as created
+/// by `rog_scsi::AuraEffect::to_packets()`.
+impl Transport for rog_scsi::Device {
+    fn write_bytes(&mut self, message: &[u8]) -> Result<(), RogError> {
+        let task = task_from_packet(message);
+        self.perform(&task)
+            .map(|_| ())
+            .map_err(|e| RogError::DoTask(format!("SCSI task failed: {e:?}")))
+    }
+}
+
+/// A transport which records every packet written to it. Clones share the
+/// same record, so a clone can be kept to inspect packets after the original
+/// has been handed to a controller.
+///
+/// If created with `wrapping()` the packets are also passed through to the
+/// inner transport, which allows capturing a session on real hardware. The
+/// captured packets can then be replayed to any other transport.
+#[derive(Clone, Default)]
+pub struct RecordingTransport {
+    packets: Arc<std::sync::Mutex<Vec<Vec<u8>>>>,
+    inner: Option<Arc<std::sync::Mutex<Box<dyn Transport>>>>,
+}
+
+impl RecordingTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record all packets while also writing them to `inner`
+    pub fn wrapping(inner: impl Transport + 'static) -> Self {
+        Self {
+            packets: Default::default(),
+            inner: Some(Arc::new(std::sync::Mutex::new(Box::new(inner)))),
+        }
+    }
+
+    /// Create a recorder pre-filled with a capture, typically for replaying
+    pub fn from_packets(packets: Vec<Vec<u8>>) -> Self {
+        Self {
+            packets: Arc::new(std::sync::Mutex::new(packets)),
+            inner: None,
+        }
+    }
+
+    /// Convenience for handing a clone of this recorder to a controller
+    pub fn shared(&self) -> SharedTransport {
+        Arc::new(Mutex::new(self.clone()))
+    }
+
+    /// All packets written so far, in order
+    pub fn packets(&self) -> Vec<Vec<u8>> {
+        self.packets.lock().map(|p| p.clone()).unwrap_or_default()
+    }
+
+    /// Return and clear the packets written so far
+    pub fn take_packets(&self) -> Vec<Vec<u8>> {
+        self.packets
+            .lock()
+            .map(|mut p| std::mem::take(&mut *p))
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut p) = self.packets.lock() {
+            p.clear();
+        }
+    }
+
+    /// Write every recorded packet, in order, to `target`
+    pub fn replay(&self, target: &mut dyn Transport) -> Result<(), RogError> {
+        for pkt in self.packets() {
+            target.write_bytes(&pkt)?;
+        }
+        Ok(())
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn write_bytes(&mut self, message: &[u8]) -> Result<(), RogError> {
+        if let Ok(mut p) = self.packets.lock() {
+            p.push(message.to_vec());
+        }
+        if let Some(inner) = &self.inner {
+            if let Ok(mut inner) = inner.lock() {
+                inner.write_bytes(message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordingTransport, Transport};
+
+    #[test]
+    fn record_and_replay() {
+        let mut rec = RecordingTransport::new();
+        let check = rec.clone();
+        rec.write_bytes(&[
+            0x5d, 0xb3,
+        ])
+        .unwrap();
+        rec.write_bytes(&[
+            0x5d, 0xb5,
+        ])
+        .unwrap();
+        assert_eq!(check.packets(), vec![
+            vec![0x5d, 0xb3],
+            vec![0x5d, 0xb5]
+        ]);
+
+        let mut target = RecordingTransport::new();
+        check.replay(&mut target).unwrap();
+        assert_eq!(target.packets(), check.packets());
+
+        assert_eq!(check.take_packets().len(), 2);
+        assert!(rec.packets().is_empty());
+    }
+
+    #[test]
+    fn record_passthrough() {
+        let inner = RecordingTransport::new();
+        let mut rec = RecordingTransport::wrapping(inner.clone());
+        rec.write_bytes(&[1, 2, 3]).unwrap();
+        assert_eq!(inner.packets(), vec![vec![
+            1, 2, 3
+        ]]);
+        assert_eq!(rec.packets(), inner.packets());
+    }
+}
@@ -91,6 +91,18 @@ impl Default for Colour {
     }
 }
 
+impl Colour {
+    /// Mix with another colour, `t` runs from 0.0 for `self` to 1.0 for `to`
+    pub fn blend(self, to: Colour, t: f32) -> Colour {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Colour {
+            r: mix(self.r, to.r),
+            g: mix(self.g, to.g),
+            b: mix(self.b, to.b),
+        }
+    }
+}
+
 impl FromStr for Colour {
     type Err = Error;
 
@@ -544,6 +556,14 @@ mod tests {
         AuraEffect, AuraModeNum, AuraZone, Colour, Direction, Speed, AURA_LAPTOP_LED_MSG_LEN,
     };
 
+    #[test]
+    fn colour_blend() {
+        let black = Colour { r: 0, g: 0, b: 0 };
+        let red = Colour { r: 200, g: 0, b: 0 };
+        assert_eq!(black.blend(red, 0.5), Colour { r: 100, g: 0, b: 0 });
+        assert_eq!(red.blend(black, 1.0), black);
+    }
+
     #[test]
     fn check_led_static_packet() {
         let st = AuraEffect {
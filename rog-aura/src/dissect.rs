@@ -0,0 +1,552 @@
+//! Decode raw Aura USB packets back in to the structures they were created
+//! from. This is the inverse of `From<&AuraEffect> for [u8; 17]`,
+//! `LaptopAuraPower::to_bytes()` and `LedUsbPackets`.
+//!
+//! Every decoded packet is re-encoded and compared against the original, any
+//! byte that does not match is flagged as unknown. On a capture from a new
+//! model these are the bytes worth looking at.
+
+use std::fmt::{self, Display};
+
+use crate::keyboard::{AuraPowerState, LaptopAuraPower, LedCode, LedUsbPackets};
+use crate::usb::{AURA_LAPTOP_LED_APPLY, AURA_LAPTOP_LED_SET};
+use crate::{
+    AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, Colour, Direction, PowerZones, Speed,
+    AURA_LAPTOP_LED_MSG_LEN,
+};
+
+const REPORT_ID: u8 = 0x5d;
+const CMD_EFFECT: u8 = 0xb3;
+const CMD_APPLY: u8 = 0xb4;
+const CMD_SET: u8 = 0xb5;
+const CMD_CUSTOM: u8 = 0xbc;
+const CMD_POWER: u8 = 0xbd;
+const CMD_ALLY_POWER: u8 = 0xd1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuraPacket {
+    /// A builtin mode
+    Effect(AuraEffect),
+    /// Sent after an effect to set it
+    Set,
+    /// Makes the last set effect persist
+    Apply,
+    /// The LED power states
+    Power(LaptopAuraPower),
+    /// Switch the keyboard to software/per-key mode
+    PerKeyInit,
+    /// One group of per-key colours, each group is a single packet
+    PerKey {
+        group: u8,
+        keys: Vec<(LedCode, Colour)>,
+    },
+    /// The colours of all zones for zoned keyboards
+    Zoned { keys: Vec<(LedCode, Colour)> },
+    /// Not a packet known to rog-aura
+    Unknown,
+}
+
+/// A decoded packet and the bytes in it that were not understood
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedPacket {
+    pub packet: AuraPacket,
+    /// The index and value of each byte that differs from what encoding
+    /// `packet` would create
+    pub unknown: Vec<(usize, u8)>,
+}
+
+impl DecodedPacket {
+    pub fn is_unknown(&self) -> bool {
+        self.packet == AuraPacket::Unknown
+    }
+}
+
+/// Decode a single packet. The device type is required to decode power
+/// packets, as the bitfield differs between generations.
+pub fn decode(pkt: &[u8], dev_type: AuraDeviceType) -> DecodedPacket {
+    if pkt.len() < 2 || pkt[0] != REPORT_ID {
+        return unknown(pkt);
+    }
+    match pkt[1] {
+        CMD_EFFECT => decode_effect(pkt),
+        CMD_SET => decoded(AuraPacket::Set, pkt, &AURA_LAPTOP_LED_SET),
+        CMD_APPLY => decoded(AuraPacket::Apply, pkt, &AURA_LAPTOP_LED_APPLY),
+        CMD_POWER => decode_power(pkt, dev_type),
+        CMD_ALLY_POWER => decode_ally_power(pkt),
+        CMD_CUSTOM => {
+            if pkt[2..].iter().all(|b| *b == 0) {
+                decoded(AuraPacket::PerKeyInit, pkt, &LedUsbPackets::get_init_msg())
+            } else if byte(pkt, 2) == 0x01 {
+                decode_zoned(pkt)
+            } else {
+                decode_per_key(pkt)
+            }
+        }
+        _ => unknown(pkt),
+    }
+}
+
+fn byte(pkt: &[u8], index: usize) -> u8 {
+    pkt.get(index).copied().unwrap_or(0)
+}
+
+fn colour_at(pkt: &[u8], index: usize) -> Colour {
+    Colour {
+        r: byte(pkt, index),
+        g: byte(pkt, index + 1),
+        b: byte(pkt, index + 2),
+    }
+}
+
+/// Compare the captured packet against the re-encoded packet. A capture may
+/// be truncated (usbmon shows only the first 32 bytes by default), so only the
+/// captured bytes are checked.
+fn decoded(packet: AuraPacket, pkt: &[u8], expected: &[u8]) -> DecodedPacket {
+    let unknown = pkt
+        .iter()
+        .enumerate()
+        .filter(|(i, b)| **b != expected.get(*i).copied().unwrap_or(0))
+        .map(|(i, b)| (i, *b))
+        .collect();
+    DecodedPacket { packet, unknown }
+}
+
+fn unknown(pkt: &[u8]) -> DecodedPacket {
+    DecodedPacket {
+        packet: AuraPacket::Unknown,
+        unknown: pkt.iter().copied().enumerate().collect(),
+    }
+}
+
+fn decode_effect(pkt: &[u8]) -> DecodedPacket {
+    let effect = AuraEffect {
+        mode: AuraModeNum::from(byte(pkt, 3)),
+        zone: AuraZone::from(byte(pkt, 2) as i32),
+        colour1: colour_at(pkt, 4),
+        colour2: colour_at(pkt, 10),
+        speed: match byte(pkt, 7) {
+            0xe1 => Speed::Low,
+            0xf5 => Speed::High,
+            _ => Speed::Med,
+        },
+        direction: Direction::from(byte(pkt, 8) as i32),
+    };
+    let expected: [u8; AURA_LAPTOP_LED_MSG_LEN] = (&effect).into();
+    decoded(AuraPacket::Effect(effect), pkt, &expected)
+}
+
+fn state(zone: PowerZones, boot: bool, awake: bool, sleep: bool, shutdown: bool) -> AuraPowerState {
+    AuraPowerState {
+        zone,
+        boot,
+        awake,
+        sleep,
+        shutdown,
+    }
+}
+
+/// Find which of the power state bits for `zone` are set in `bits`
+fn state_from_bits(
+    zone: PowerZones,
+    bits: u32,
+    to_bits: impl Fn(AuraPowerState) -> u32,
+) -> AuraPowerState {
+    let is_set = |s: AuraPowerState| {
+        let mask = to_bits(s);
+        mask != 0 && bits & mask == mask
+    };
+    state(
+        zone,
+        is_set(state(zone, true, false, false, false)),
+        is_set(state(zone, false, true, false, false)),
+        is_set(state(zone, false, false, true, false)),
+        is_set(state(zone, false, false, false, true)),
+    )
+}
+
+fn decode_power(pkt: &[u8], dev_type: AuraDeviceType) -> DecodedPacket {
+    let states = if dev_type.is_old_laptop() {
+        // The keyboard and lightbar bits only say which zones the states apply to
+        let to_bits = |s: AuraPowerState| {
+            let b = LaptopAuraPower { states: vec![s] }.to_bytes(dev_type);
+            ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32
+        };
+        let bits =
+            ((byte(pkt, 3) as u32) << 16) | ((byte(pkt, 4) as u32) << 8) | byte(pkt, 5) as u32;
+        let zone_set = |zone| {
+            let mask = to_bits(state(zone, false, false, false, false));
+            bits & mask == mask
+        };
+        let zone = match (
+            zone_set(PowerZones::Keyboard),
+            zone_set(PowerZones::Lightbar),
+        ) {
+            (true, true) => PowerZones::KeyboardAndLightbar,
+            (true, false) => PowerZones::Keyboard,
+            (false, true) => PowerZones::Lightbar,
+            (false, false) => PowerZones::None,
+        };
+        let mut s = state_from_bits(PowerZones::None, bits, to_bits);
+        s.zone = zone;
+        vec![s]
+    } else {
+        let bits = u32::from_le_bytes([
+            byte(pkt, 3),
+            byte(pkt, 4),
+            byte(pkt, 5),
+            byte(pkt, 6),
+        ]);
+        [
+            PowerZones::Logo,
+            PowerZones::Keyboard,
+            PowerZones::Lightbar,
+            PowerZones::Lid,
+            PowerZones::RearGlow,
+        ]
+        .into_iter()
+        .map(|zone| state_from_bits(zone, bits, |s| s.new_to_byte()))
+        .filter(|s| s.boot || s.awake || s.sleep || s.shutdown)
+        .collect()
+    };
+
+    let power = LaptopAuraPower { states };
+    let mut expected = vec![
+        REPORT_ID, CMD_POWER, 0x01,
+    ];
+    expected.append(&mut power.to_bytes(dev_type));
+    decoded(AuraPacket::Power(power), pkt, &expected)
+}
+
+fn decode_ally_power(pkt: &[u8]) -> DecodedPacket {
+    let s = state_from_bits(PowerZones::Ally, byte(pkt, 4) as u32, |s| s.new_to_byte());
+    let power = LaptopAuraPower { states: vec![s] };
+    let expected = power.to_bytes(AuraDeviceType::Ally);
+    decoded(AuraPacket::Power(power), pkt, &expected)
+}
+
+/// Collect the colour of every `LedCode` in `codes` addressed in `row`. Where
+/// more than one `LedCode` shares an address only the first is used.
+fn keys_in_row(
+    pkt: &[u8],
+    row: usize,
+    zoned: bool,
+    codes: impl Iterator<Item = LedCode>,
+) -> Vec<(LedCode, Colour)> {
+    let mut seen = Vec::new();
+    let mut keys = Vec::new();
+    for code in codes {
+        if let Some((r, col)) = LedUsbPackets::packet_index(code, zoned) {
+            if r == row && !seen.contains(&col) {
+                seen.push(col);
+                keys.push((code, colour_at(pkt, col)));
+            }
+        }
+    }
+    keys
+}
+
+fn decode_per_key(pkt: &[u8]) -> DecodedPacket {
+    let group = byte(pkt, 6) >> 4;
+    let mut expected = LedUsbPackets::new_per_key();
+    if group as usize >= expected.get_ref().len() {
+        return unknown(pkt);
+    }
+
+    let codes = LedCode::ALL
+        .into_iter()
+        .filter(|c| !c.is_keyboard_zone() && *c != LedCode::SingleZone);
+    let keys = keys_in_row(pkt, group as usize, false, codes);
+    for (code, c) in &keys {
+        expected.set(*code, c.r, c.g, c.b);
+    }
+    let expected = &expected.get_ref()[group as usize];
+    decoded(AuraPacket::PerKey { group, keys }, pkt, expected)
+}
+
+fn decode_zoned(pkt: &[u8]) -> DecodedPacket {
+    let multizoned = byte(pkt, 4) == 0x04;
+    let codes = LedCode::ALL.into_iter().filter(|c| {
+        c.is_lightbar_zone()
+            || if multizoned {
+                c.is_keyboard_zone()
+            } else {
+                *c == LedCode::SingleZone
+            }
+    });
+    let keys = keys_in_row(pkt, 0, true, codes);
+    let mut expected = LedUsbPackets::new_zoned(multizoned);
+    for (code, c) in &keys {
+        expected.set(*code, c.r, c.g, c.b);
+    }
+    decoded(AuraPacket::Zoned { keys }, pkt, &expected.get_ref()[0])
+}
+
+fn write_colour(f: &mut fmt::Formatter<'_>, c: &Colour) -> fmt::Result {
+    write!(f, "#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+}
+
+fn write_keys(f: &mut fmt::Formatter<'_>, keys: &[(LedCode, Colour)]) -> fmt::Result {
+    for (i, (code, c)) in keys.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}: ", <&str>::from(code))?;
+        write_colour(f, c)?;
+    }
+    Ok(())
+}
+
+impl Display for AuraPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuraPacket::Effect(e) => {
+                write!(f, "Aura effect: {}, zone {:?}, colour1 ", e.mode, e.zone)?;
+                write_colour(f, &e.colour1)?;
+                write!(f, ", colour2 ")?;
+                write_colour(f, &e.colour2)?;
+                write!(f, ", speed {:?}, direction {:?}", e.speed, e.direction)
+            }
+            AuraPacket::Set => write!(f, "Aura set"),
+            AuraPacket::Apply => write!(f, "Aura apply"),
+            AuraPacket::Power(p) => {
+                write!(f, "Aura power:")?;
+                for s in &p.states {
+                    write!(
+                        f,
+                        " {:?}(boot {}, awake {}, sleep {}, shutdown {})",
+                        s.zone, s.boot, s.awake, s.sleep, s.shutdown
+                    )?;
+                }
+                Ok(())
+            }
+            AuraPacket::PerKeyInit => write!(f, "Aura per-key init"),
+            AuraPacket::PerKey { group, keys } => {
+                write!(f, "Aura per-key group {group}: ")?;
+                write_keys(f, keys)
+            }
+            AuraPacket::Zoned { keys } => {
+                write!(f, "Aura zoned: ")?;
+                write_keys(f, keys)
+            }
+            AuraPacket::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+impl Display for DecodedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.packet)?;
+        if !self.is_unknown() && !self.unknown.is_empty() {
+            write!(f, "\n  unknown bytes:")?;
+            for (i, b) in &self.unknown {
+                write!(f, " [{i}]={b:#04x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, AuraPacket};
+    use crate::keyboard::{AuraPowerState, LaptopAuraPower, LedCode, LedUsbPackets};
+    use crate::usb::{AURA_LAPTOP_LED_APPLY, AURA_LAPTOP_LED_SET};
+    use crate::{
+        AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, Colour, Direction, PowerZones, Speed,
+        AURA_LAPTOP_LED_MSG_LEN,
+    };
+
+    #[test]
+    fn effect_round_trip() {
+        let effect = AuraEffect {
+            mode: AuraModeNum::RainbowWave,
+            zone: AuraZone::Key2,
+            colour1: Colour {
+                r: 0xff,
+                g: 0x11,
+                b: 0xdd,
+            },
+            colour2: Colour { r: 1, g: 2, b: 3 },
+            speed: Speed::High,
+            direction: Direction::Up,
+        };
+        let bytes: [u8; AURA_LAPTOP_LED_MSG_LEN] = (&effect).into();
+        let decoded = decode(&bytes, AuraDeviceType::LaptopKeyboard2021);
+        assert_eq!(decoded.packet, AuraPacket::Effect(effect));
+        assert!(decoded.unknown.is_empty());
+
+        let decoded = decode(&AURA_LAPTOP_LED_SET, AuraDeviceType::LaptopKeyboard2021);
+        assert_eq!(decoded.packet, AuraPacket::Set);
+        let decoded = decode(&AURA_LAPTOP_LED_APPLY, AuraDeviceType::LaptopKeyboard2021);
+        assert_eq!(decoded.packet, AuraPacket::Apply);
+    }
+
+    #[test]
+    fn effect_unknown_bytes() {
+        // Mode 9 doesn't exist, and byte 9 is always zero
+        let bytes = [
+            0x5d, 0xb3, 0x00, 0x09, 0xff, 0x00, 0x00, 0xeb, 0x00, 0x42, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let decoded = decode(&bytes, AuraDeviceType::LaptopKeyboard2021);
+        assert!(matches!(decoded.packet, AuraPacket::Effect(_)));
+        assert_eq!(decoded.unknown, vec![
+            (3, 0x09),
+            (9, 0x42)
+        ]);
+    }
+
+    #[test]
+    fn power_round_trip() {
+        let power = LaptopAuraPower {
+            states: vec![
+                AuraPowerState {
+                    zone: PowerZones::Keyboard,
+                    boot: true,
+                    awake: true,
+                    sleep: false,
+                    shutdown: true,
+                },
+                AuraPowerState {
+                    zone: PowerZones::Lightbar,
+                    boot: false,
+                    awake: true,
+                    sleep: false,
+                    shutdown: false,
+                },
+            ],
+        };
+        let mut pkt = vec![
+            0x5d, 0xbd, 0x01,
+        ];
+        pkt.append(&mut power.to_bytes(AuraDeviceType::LaptopKeyboard2021));
+        let decoded = decode(&pkt, AuraDeviceType::LaptopKeyboard2021);
+        assert_eq!(decoded.packet, AuraPacket::Power(power));
+        assert!(decoded.unknown.is_empty());
+
+        // The Ally reset writes every bit, including ones no zone uses
+        let decoded = decode(
+            &[
+                0x5d, 0xbd, 0x01, 0xff, 0xff, 0xff, 0xff,
+            ],
+            AuraDeviceType::LaptopKeyboard2021,
+        );
+        assert_eq!(decoded.unknown, vec![
+            (4, 0xff),
+            (5, 0xff),
+            (6, 0xff)
+        ]);
+    }
+
+    #[test]
+    fn power_old_round_trip() {
+        let power = LaptopAuraPower {
+            states: vec![
+                AuraPowerState {
+                    zone: PowerZones::KeyboardAndLightbar,
+                    boot: true,
+                    awake: true,
+                    sleep: false,
+                    shutdown: false,
+                },
+            ],
+        };
+        let mut pkt = vec![
+            0x5d, 0xbd, 0x01,
+        ];
+        pkt.append(&mut power.to_bytes(AuraDeviceType::LaptopKeyboardPre2021));
+        let decoded = decode(&pkt, AuraDeviceType::LaptopKeyboardPre2021);
+        assert_eq!(decoded.packet, AuraPacket::Power(power));
+        assert!(decoded.unknown.is_empty());
+    }
+
+    #[test]
+    fn per_key_round_trip() {
+        let mut packets = LedUsbPackets::new_per_key();
+        packets.set(LedCode::Esc, 0xff, 0, 0);
+        packets.set(LedCode::F1, 0, 0xff, 0);
+        packets.set(LedCode::Q, 0, 0, 0xff);
+
+        let decoded = decode(&packets.get_ref()[1], AuraDeviceType::LaptopKeyboard2021);
+        let AuraPacket::PerKey { group, keys } = decoded.packet else {
+            panic!("Not a per-key packet");
+        };
+        assert_eq!(group, 1);
+        assert!(keys.contains(&(LedCode::Esc, Colour {
+            r: 0xff,
+            g: 0,
+            b: 0
+        })));
+        assert!(keys.contains(&(LedCode::F1, Colour {
+            r: 0,
+            g: 0xff,
+            b: 0
+        })));
+        assert!(decoded.unknown.is_empty());
+
+        let decoded = decode(&packets.get_ref()[4], AuraDeviceType::LaptopKeyboard2021);
+        let AuraPacket::PerKey { keys, .. } = decoded.packet else {
+            panic!("Not a per-key packet");
+        };
+        assert_eq!(
+            keys[0],
+            (LedCode::Q, Colour {
+                r: 0,
+                g: 0,
+                b: 0xff
+            })
+        );
+
+        // A colour at an address with no known LedCode
+        let mut row = packets.get_ref()[1].clone();
+        row[27] = 0x80;
+        let decoded = decode(&row, AuraDeviceType::LaptopKeyboard2021);
+        assert_eq!(decoded.unknown, vec![(27, 0x80)]);
+
+        let decoded = decode(
+            &LedUsbPackets::get_init_msg(),
+            AuraDeviceType::LaptopKeyboard2021,
+        );
+        assert_eq!(decoded.packet, AuraPacket::PerKeyInit);
+    }
+
+    #[test]
+    fn zoned_round_trip() {
+        let mut packets = LedUsbPackets::new_zoned(true);
+        packets.set(LedCode::ZonedKbLeft, 0xff, 0, 0);
+        packets.set(LedCode::ZonedKbRight, 0, 0, 0xff);
+        packets.set(LedCode::LightbarLeft, 0, 0xff, 0);
+
+        let decoded = decode(&packets.get_ref()[0], AuraDeviceType::LaptopKeyboard2021);
+        let AuraPacket::Zoned { keys } = decoded.packet else {
+            panic!("Not a zoned packet");
+        };
+        assert!(keys.contains(&(LedCode::ZonedKbLeft, Colour {
+            r: 0xff,
+            g: 0,
+            b: 0
+        })));
+        assert!(keys.contains(&(LedCode::ZonedKbRight, Colour {
+            r: 0,
+            g: 0,
+            b: 0xff
+        })));
+        assert!(keys.contains(&(LedCode::LightbarLeft, Colour {
+            r: 0,
+            g: 0xff,
+            b: 0
+        })));
+        assert!(decoded.unknown.is_empty());
+    }
+
+    #[test]
+    fn unknown_packet() {
+        let decoded = decode(
+            &[
+                0x5a, 0xba, 0xc5,
+            ],
+            AuraDeviceType::LaptopKeyboard2021,
+        );
+        assert!(decoded.is_unknown());
+        assert_eq!(decoded.unknown.len(), 3);
+    }
+}
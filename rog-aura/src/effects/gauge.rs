@@ -0,0 +1,75 @@
+use super::{AdvancedEffects, Effect, Static};
+use crate::keyboard::LedCode;
+use crate::Colour;
+
+impl AdvancedEffects {
+    /// A bar across `leds`, first to last, showing `percent`. The bar is red
+    /// when empty through to green when full, and while `charging` the LED
+    /// past the end of the bar is lit dimly. LEDs not part of the bar are off.
+    pub fn gauge(zoned: bool, leds: &[LedCode], percent: u8, charging: bool) -> Self {
+        let percent = u32::from(percent.min(100));
+        let colour = Colour {
+            r: (255 * (100 - percent) / 100) as u8,
+            g: (255 * percent / 100) as u8,
+            b: 0,
+        };
+        // Round to the nearest LED, but keep one lit until empty
+        let mut lit = (leds.len() as u32 * percent + 50) / 100;
+        if percent > 0 {
+            lit = lit.max(1);
+        }
+
+        let mut effects = Self::new(zoned);
+        for (i, led) in leds.iter().enumerate() {
+            let colour = if (i as u32) < lit {
+                colour
+            } else if charging && i as u32 == lit {
+                Colour {
+                    r: colour.r / 4,
+                    g: colour.g / 4,
+                    b: 0,
+                }
+            } else {
+                continue;
+            };
+            effects.push(Effect::Static(Static::new(*led, colour)));
+        }
+        effects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::effects::AdvancedEffects;
+    use crate::keyboard::{LedCode, LedUsbPackets};
+
+    #[test]
+    fn battery_gauge() {
+        let rgb = |packets: &Vec<Vec<u8>>, led: LedCode, zoned: bool| {
+            let (row, col) = LedUsbPackets::packet_index(led, zoned).unwrap();
+            packets[row][col..col + 3].to_vec()
+        };
+
+        let packets =
+            AdvancedEffects::gauge(false, &LedCode::FUNCTION_ROW, 50, false).create_packets();
+        assert_eq!(rgb(&packets, LedCode::F1, false), vec![127, 127, 0]);
+        assert_eq!(rgb(&packets, LedCode::F6, false), vec![127, 127, 0]);
+        assert_eq!(rgb(&packets, LedCode::F7, false), vec![0, 0, 0]);
+        assert_eq!(rgb(&packets, LedCode::F12, false), vec![0, 0, 0]);
+
+        let packets =
+            AdvancedEffects::gauge(false, &LedCode::FUNCTION_ROW, 1, true).create_packets();
+        assert_eq!(rgb(&packets, LedCode::F1, false), vec![252, 2, 0]);
+        assert_eq!(rgb(&packets, LedCode::F2, false), vec![63, 0, 0]);
+        assert_eq!(rgb(&packets, LedCode::F3, false), vec![0, 0, 0]);
+
+        let lightbar = [
+            LedCode::LightbarLeft,
+            LedCode::LightbarRight,
+        ];
+        let packets = AdvancedEffects::gauge(true, &lightbar, 100, true).create_packets();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(rgb(&packets, LedCode::LightbarLeft, true), vec![0, 255, 0]);
+        assert_eq!(rgb(&packets, LedCode::LightbarRight, true), vec![0, 255, 0]);
+    }
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+
+use super::{ramp_colour, EffectState};
+use crate::keyboard::{KeyLayout, LedCode};
+use crate::{effect_state_impl, Colour};
+
+/// A live system value for a `Heatmap` to show
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum MetricSource {
+    /// Load of all CPU cores together in percent
+    CpuTotal,
+    /// Load of one CPU core in percent, the first core is 0
+    CpuCore(usize),
+    /// The temperature in °C of the hwmon sensor with this name, such as
+    /// `coretemp` or `k10temp` for the CPU package
+    Temperature(String),
+}
+
+/// Colour an LED by a live value such as CPU load or temperature. The value is
+/// set from outside with `set_value()`, typically through
+/// `AdvancedEffects::update_metrics()`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Heatmap {
+    led: LedCode,
+    source: MetricSource,
+    /// The value shown as the first colour of `ramp`
+    min: f32,
+    /// The value shown as the last colour of `ramp`
+    max: f32,
+    /// Colours from `min` to `max`, values between two colours blend them
+    ramp: Vec<Colour>,
+    #[serde(skip)]
+    value: f32,
+    #[serde(skip)]
+    colour: Colour,
+}
+
+impl Heatmap {
+    pub fn new(
+        address: LedCode,
+        source: MetricSource,
+        min: f32,
+        max: f32,
+        ramp: Vec<Colour>,
+    ) -> Self {
+        let colour = ramp.first().copied().unwrap_or_default();
+        Self {
+            led: address,
+            source,
+            min,
+            max,
+            ramp,
+            value: min,
+            colour,
+        }
+    }
+
+    pub fn source(&self) -> &MetricSource {
+        &self.source
+    }
+
+    pub fn set_value(&mut self, value: f32) {
+        self.value = value;
+    }
+
+    /// The colour of `value` along the ramp
+    fn ramp_colour(&self, value: f32) -> Colour {
+        let range = self.max - self.min;
+        let t = if range > 0.0 {
+            (value - self.min) / range
+        } else {
+            0.0
+        };
+        ramp_colour(&self.ramp, t)
+    }
+}
+
+impl EffectState for Heatmap {
+    effect_state_impl!();
+
+    fn next_colour_state(&mut self, _layout: &KeyLayout) {
+        self.colour = self.ramp_colour(self.value);
+    }
+}
@@ -0,0 +1,534 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::keyboard::{KeyLayout, LedCode};
+use crate::Colour;
+
+/// Browsers show GIF frames with a delay under 20ms for 100ms, as do we
+const GIF_MIN_DELAY: u16 = 2;
+const GIF_DEFAULT_DELAY: Duration = Duration::from_millis(100);
+/// Larger images are refused, 4096x4096 or the like
+const MAX_PIXELS: usize = 4096 * 4096;
+/// Animations with more frames are refused
+const MAX_FRAMES: usize = 500;
+/// Frames are kept at most this size, still far more pixels than any
+/// keyboard has keys
+const SCALED_WIDTH: usize = 128;
+const SCALED_HEIGHT: usize = 48;
+
+/// One full frame of an image as RGBA rows, top to bottom, scaled down to at
+/// most `SCALED_WIDTH` by `SCALED_HEIGHT`
+#[derive(Clone)]
+struct Frame {
+    rgba: Vec<u8>,
+    delay: Duration,
+}
+
+/// A PNG, or an animated GIF played with its own frame delays, stretched
+/// across the whole keyboard. Each LED shows the average colour of the image
+/// under its key.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct KeyboardImage {
+    /// A PNG or GIF file
+    path: PathBuf,
+    #[serde(skip)]
+    width: usize,
+    #[serde(skip)]
+    height: usize,
+    #[serde(skip)]
+    frames: Vec<Frame>,
+    #[serde(skip)]
+    started: Option<Instant>,
+    /// The frame shown and the colour of each LED in it
+    #[serde(skip)]
+    shown: Option<(usize, Vec<(LedCode, Colour)>)>,
+}
+
+impl fmt::Debug for KeyboardImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyboardImage")
+            .field("path", &self.path)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("frames", &self.frames.len())
+            .finish()
+    }
+}
+
+impl KeyboardImage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut image = Self {
+            path: path.as_ref().to_path_buf(),
+            width: 0,
+            height: 0,
+            frames: Vec::new(),
+            started: None,
+            shown: None,
+        };
+        image.load()?;
+        Ok(image)
+    }
+
+    /// Read and decode the file, such as after the image was deserialised
+    pub fn load(&mut self) -> Result<(), Error> {
+        let path = self.path.to_string_lossy().to_string();
+        let bytes = std::fs::read(&self.path).map_err(|e| Error::IoPath(path.clone(), e))?;
+        let (width, height, frames) = if bytes.starts_with(b"\x89PNG") {
+            decode_png(&bytes)
+        } else if bytes.starts_with(b"GIF8") {
+            decode_gif(&bytes)
+        } else {
+            Err("not a PNG or GIF".to_owned())
+        }
+        .map_err(|e| Error::Image(path, e))?;
+
+        self.width = width;
+        self.height = height;
+        self.frames = frames;
+        self.started = None;
+        self.shown = None;
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The frame to show `elapsed` after the image started. Animations loop.
+    pub fn frame_at(&self, elapsed: Duration) -> usize {
+        let total: Duration = self.frames.iter().map(|frame| frame.delay).sum();
+        if total.is_zero() {
+            return 0;
+        }
+        let mut at = Duration::from_nanos((elapsed.as_nanos() % total.as_nanos()) as u64);
+        for (i, frame) in self.frames.iter().enumerate() {
+            if at < frame.delay {
+                return i;
+            }
+            at -= frame.delay;
+        }
+        0
+    }
+
+    /// The colour of each LED in `layout` for this frame. The image is
+    /// stretched over the keys of the layout, and each LED takes the average
+    /// of the pixels under its key drawn over black.
+    pub fn sample(&self, layout: &KeyLayout, frame: usize) -> Vec<(LedCode, Colour)> {
+        let Some(Frame { rgba, .. }) = self.frames.get(frame) else {
+            return Vec::new();
+        };
+        let Some((left, top, right, bottom)) =
+            layout
+                .led_rects()
+                .fold(None, |area, (_, (x, y, width, height))| {
+                    let (l, t, r, b) = area.unwrap_or((x, y, x + width, y + height));
+                    Some((l.min(x), t.min(y), r.max(x + width), b.max(y + height)))
+                })
+        else {
+            return Vec::new();
+        };
+        let scale_x = self.width as f32 / (right - left);
+        let scale_y = self.height as f32 / (bottom - top);
+        // The pixels from `start` to `end` in key units, at least one
+        let span = |start: f32, end: f32, scale: f32, size: usize| {
+            let first = ((start * scale).floor().max(0.0) as usize).min(size - 1);
+            let last = ((end * scale).ceil() as usize).clamp(first + 1, size);
+            first..last
+        };
+
+        layout
+            .led_rects()
+            .map(|(led, (x, y, width, height))| {
+                let mut sum = [0u32; 3];
+                let mut count = 0;
+                for py in span(y - top, y + height - top, scale_y, self.height) {
+                    for px in span(x - left, x + width - left, scale_x, self.width) {
+                        let pixel = &rgba[(py * self.width + px) * 4..][..4];
+                        let alpha = u32::from(pixel[3]);
+                        for (sum, c) in sum.iter_mut().zip(pixel) {
+                            *sum += u32::from(*c) * alpha;
+                        }
+                        count += 255;
+                    }
+                }
+                let average = |sum: u32| (sum / count.max(1)) as u8;
+                (led, Colour {
+                    r: average(sum[0]),
+                    g: average(sum[1]),
+                    b: average(sum[2]),
+                })
+            })
+            .collect()
+    }
+
+    /// Move to the frame to show now, the first call starts the animation
+    pub(super) fn next_state(&mut self, layout: &KeyLayout) {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let frame = self.frame_at(started.elapsed());
+        if self.shown.as_ref().is_none_or(|(shown, _)| *shown != frame) {
+            self.shown = Some((frame, self.sample(layout, frame)));
+        }
+    }
+
+    /// The LED colours last found by `next_state()`
+    pub(super) fn colours(&self) -> &[(LedCode, Colour)] {
+        self.shown
+            .as_ref()
+            .map(|(_, colours)| colours.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// The width, height and frames of an image, scaled with `Scaler`
+type Decoded = (usize, usize, Vec<Frame>);
+
+fn check_size(width: usize, height: usize) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("the image is empty".to_owned());
+    }
+    if width.saturating_mul(height) > MAX_PIXELS {
+        return Err(format!(
+            "the image is {width}x{height}, larger than {MAX_PIXELS} pixels"
+        ));
+    }
+    Ok(())
+}
+
+/// Scales frames of one size down to at most `SCALED_WIDTH` by
+/// `SCALED_HEIGHT`, so that large images and long animations don't take much
+/// memory
+struct Scaler {
+    width: usize,
+    height: usize,
+    scaled_width: usize,
+    scaled_height: usize,
+}
+
+impl Scaler {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            scaled_width: width.min(SCALED_WIDTH),
+            scaled_height: height.min(SCALED_HEIGHT),
+        }
+    }
+
+    /// Each scaled pixel is the average of the pixels it covers drawn over
+    /// black, as `KeyboardImage::sample()` does
+    fn scale(&self, rgba: &[u8]) -> Vec<u8> {
+        let (width, height) = (self.width, self.height);
+        let (scaled_width, scaled_height) = (self.scaled_width, self.scaled_height);
+        let mut scaled = Vec::with_capacity(scaled_width * scaled_height * 4);
+        for sy in 0..scaled_height {
+            let rows = sy * height / scaled_height..(sy + 1) * height / scaled_height;
+            for sx in 0..scaled_width {
+                let cols = sx * width / scaled_width..(sx + 1) * width / scaled_width;
+                let mut sum = [0u64; 3];
+                let mut count = 0;
+                for y in rows.clone() {
+                    for x in cols.clone() {
+                        let pixel = &rgba[(y * width + x) * 4..][..4];
+                        let alpha = u64::from(pixel[3]);
+                        for (sum, c) in sum.iter_mut().zip(pixel) {
+                            *sum += u64::from(*c) * alpha;
+                        }
+                        count += 255;
+                    }
+                }
+                let average = |sum: u64| (sum / count.max(1)) as u8;
+                scaled.extend([
+                    average(sum[0]),
+                    average(sum[1]),
+                    average(sum[2]),
+                    255,
+                ]);
+            }
+        }
+        scaled
+    }
+
+    fn decoded(&self, frames: Vec<Frame>) -> Decoded {
+        (self.scaled_width, self.scaled_height, frames)
+    }
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Decoded, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let (width, height) = (reader.info().width as usize, reader.info().height as usize);
+    check_size(width, height)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    let data = &buf[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => data.to_vec(),
+        png::ColorType::Rgb => data
+            .chunks_exact(3)
+            .flat_map(|p| {
+                [
+                    p[0], p[1], p[2], 255,
+                ]
+            })
+            .collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .flat_map(|p| {
+                [
+                    p[0], p[0], p[0], p[1],
+                ]
+            })
+            .collect(),
+        png::ColorType::Grayscale => data
+            .iter()
+            .flat_map(|g| {
+                [
+                    *g, *g, *g, 255,
+                ]
+            })
+            .collect(),
+        png::ColorType::Indexed => return Err("indexed colour was not expanded".to_owned()),
+    };
+    let scaler = Scaler::new(width, height);
+    Ok(scaler.decoded(vec![Frame {
+        rgba: scaler.scale(&rgba),
+        delay: Duration::ZERO,
+    }]))
+}
+
+fn decode_gif(bytes: &[u8]) -> Result<Decoded, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(bytes).map_err(|e| e.to_string())?;
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    check_size(width, height)?;
+    let scaler = Scaler::new(width, height);
+
+    // Frames may only cover part of the image, so are drawn over the last.
+    // Only the scaled result of each is kept.
+    let mut canvas = vec![0; width * height * 4];
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        if frames.len() == MAX_FRAMES {
+            return Err(format!("the image has more than {MAX_FRAMES} frames"));
+        }
+        let previous = (frame.dispose == gif::DisposalMethod::Previous).then(|| canvas.clone());
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        let frame_width = frame.width as usize;
+        let pixels = || {
+            (0..frame.height as usize).flat_map(move |row| {
+                (0..frame_width).map(move |col| (row, col, top + row, left + col))
+            })
+        };
+        for (row, col, y, x) in pixels() {
+            let pixel = &frame.buffer[(row * frame_width + col) * 4..][..4];
+            if x < width && y < height && pixel[3] != 0 {
+                canvas[(y * width + x) * 4..][..4].copy_from_slice(pixel);
+            }
+        }
+        frames.push(Frame {
+            rgba: scaler.scale(&canvas),
+            delay: if frame.delay < GIF_MIN_DELAY {
+                GIF_DEFAULT_DELAY
+            } else {
+                Duration::from_millis(u64::from(frame.delay) * 10)
+            },
+        });
+
+        match frame.dispose {
+            gif::DisposalMethod::Background => {
+                for (_, _, y, x) in pixels() {
+                    if x < width && y < height {
+                        canvas[(y * width + x) * 4..][..4].fill(0);
+                    }
+                }
+            }
+            gif::DisposalMethod::Previous => canvas = previous.unwrap_or(canvas),
+            _ => {}
+        }
+    }
+    if frames.is_empty() {
+        return Err("the image has no frames".to_owned());
+    }
+    Ok(scaler.decoded(frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::{KeyboardImage, MAX_FRAMES, SCALED_HEIGHT, SCALED_WIDTH};
+    use crate::keyboard::{KeyLayout, LedCode};
+    use crate::Colour;
+
+    fn write_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rog-aura-{}-{name}", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn colour_of(colours: &[(LedCode, Colour)], led: LedCode) -> Colour {
+        colours.iter().find(|(l, _)| *l == led).unwrap().1
+    }
+
+    #[test]
+    fn png_across_keyboard() {
+        // Left half red, right half half-transparent blue
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[
+                255, 0, 0, 255, 0, 0, 200, 128,
+            ])
+            .unwrap();
+        writer.finish().unwrap();
+        let path = write_file("image.png", &bytes);
+
+        let layout = KeyLayout::default_layout();
+        let image = KeyboardImage::open(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(image.frame_count(), 1);
+        assert_eq!(image.frame_at(Duration::from_secs(5)), 0);
+
+        let colours = image.sample(&layout, 0);
+        assert_eq!(colours.len(), layout.led_rects().count());
+        assert_eq!(colour_of(&colours, LedCode::Esc), Colour {
+            r: 255,
+            g: 0,
+            b: 0
+        });
+        assert_eq!(colour_of(&colours, LedCode::A), Colour {
+            r: 255,
+            g: 0,
+            b: 0
+        });
+        assert_eq!(colour_of(&colours, LedCode::Backspace), Colour {
+            r: 0,
+            g: 0,
+            b: 100
+        });
+
+        // Large images are kept scaled down
+        let (width, height) = (1000, 100);
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        let row: Vec<u8> = (0..width)
+            .flat_map(|x| {
+                if x < width / 2 {
+                    [
+                        255, 0, 0,
+                    ]
+                } else {
+                    [
+                        0, 0, 255,
+                    ]
+                }
+            })
+            .collect();
+        writer
+            .write_image_data(&row.repeat(height as usize))
+            .unwrap();
+        writer.finish().unwrap();
+        let path = write_file("large.png", &bytes);
+        let image = KeyboardImage::open(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!((image.width, image.height), (SCALED_WIDTH, SCALED_HEIGHT));
+        assert_eq!(image.frames[0].rgba.len(), SCALED_WIDTH * SCALED_HEIGHT * 4);
+        let colours = image.sample(&layout, 0);
+        assert_eq!(colour_of(&colours, LedCode::A), Colour {
+            r: 255,
+            g: 0,
+            b: 0
+        });
+        assert_eq!(colour_of(&colours, LedCode::Backspace), Colour {
+            r: 0,
+            g: 0,
+            b: 255
+        });
+
+        // Too large to decode
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 5000, 5000);
+        encoder.set_color(png::ColorType::Grayscale);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&vec![0; 5000 * 5000]).unwrap();
+        writer.finish().unwrap();
+        let path = write_file("huge.png", &bytes);
+        assert!(KeyboardImage::open(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+
+        assert!(KeyboardImage::open("/nonexistent.png").is_err());
+        let path = write_file("image.txt", b"not an image");
+        assert!(KeyboardImage::open(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn gif_frames() {
+        let red = [
+            255, 0, 0,
+        ];
+        let green = [
+            0, 255, 0,
+        ];
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut bytes, 1, 1, &[]).unwrap();
+            for (colour, delay) in [
+                (red, 10),
+                (green, 30),
+            ] {
+                let mut frame = gif::Frame::from_rgb(1, 1, &colour);
+                frame.delay = delay;
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        let path = write_file("image.gif", &bytes);
+        let layout = KeyLayout::default_layout();
+        let image = KeyboardImage::open(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(image.frame_count(), 2);
+        let frame_at = |ms| image.frame_at(Duration::from_millis(ms));
+        assert_eq!(frame_at(0), 0);
+        assert_eq!(frame_at(99), 0);
+        assert_eq!(frame_at(100), 1);
+        assert_eq!(frame_at(399), 1);
+        // Loops after 400ms
+        assert_eq!(frame_at(450), 0);
+
+        let colours = image.sample(&layout, 1);
+        assert_eq!(colour_of(&colours, LedCode::F), Colour {
+            r: 0,
+            g: 255,
+            b: 0
+        });
+        assert!(image.sample(&layout, 2).is_empty());
+
+        // Too many frames
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut bytes, 1, 1, &[]).unwrap();
+            for _ in 0..=MAX_FRAMES {
+                encoder
+                    .write_frame(&gif::Frame::from_rgb(1, 1, &red))
+                    .unwrap();
+            }
+        }
+        let path = write_file("long.gif", &bytes);
+        assert!(KeyboardImage::open(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -3,15 +3,28 @@ use serde::{Deserialize, Serialize};
 mod doom;
 pub use doom::*;
 
-mod base;
-pub use base::*;
-
 mod breathe;
 pub use breathe::*;
 
 mod static_;
 pub use static_::*;
 
+mod gauge;
+
+mod heatmap;
+pub use heatmap::*;
+
+mod spatial;
+pub use spatial::*;
+
+mod reactive;
+pub use reactive::*;
+
+#[cfg(feature = "image")]
+mod image;
+#[cfg(feature = "image")]
+pub use image::*;
+
 use crate::keyboard::{AuraLaptopUsbPackets, KeyLayout, LedCode, LedUsbPackets};
 use crate::Colour;
 
@@ -42,13 +55,23 @@ pub fn p_random() -> i32 {
     }
 }
 
-pub trait InputForEffect {
-    /// Calculate the next colour state
-    fn next_colour_state(&mut self);
+/// The colour at `t` along `ramp`, from 0.0 for the first colour to 1.0 for
+/// the last. Between two colours they are blended. Black if `ramp` is empty.
+fn ramp_colour(ramp: &[Colour], t: f32) -> Colour {
+    let Some(last) = ramp.len().checked_sub(1) else {
+        return Colour { r: 0, g: 0, b: 0 };
+    };
+    let pos = t.clamp(0.0, 1.0) * last as f32;
+    let i = (pos.floor() as usize).min(last);
+    ramp[i].blend(ramp[(i + 1).min(last)], pos - i as f32)
+}
 
-    /// Return the resulting colour. Implementers should store the colour to
-    /// return it.
-    fn get_colour(&self) -> Colour;
+/// An effect that reacts to key presses, fed by
+/// `AdvancedEffects::key_pressed()`
+pub trait InputForEffect {
+    /// A key was pressed, `leds` are all the LEDs of that key and `layout`
+    /// gives where they are
+    fn key_pressed(&mut self, leds: &[LedCode], layout: &KeyLayout);
 }
 
 pub(crate) trait EffectState {
@@ -68,6 +91,10 @@ pub(crate) trait EffectState {
 pub struct AdvancedEffects {
     effects: Vec<Effect>,
     zoned: bool,
+    /// Drawn under the effects, so any LED without an effect shows the image
+    #[cfg(feature = "image")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<KeyboardImage>,
 }
 
 impl AdvancedEffects {
@@ -76,9 +103,23 @@ impl AdvancedEffects {
         Self {
             effects: Default::default(),
             zoned,
+            #[cfg(feature = "image")]
+            image: None,
         }
     }
 
+    /// Show `image` under the effects, or stop showing one with `None`
+    #[cfg(feature = "image")]
+    pub fn set_image(&mut self, image: Option<KeyboardImage>) {
+        self.image = image;
+    }
+
+    /// Decode the image file, needed after the effects are deserialised
+    #[cfg(feature = "image")]
+    pub fn load_image(&mut self) -> Result<(), crate::error::Error> {
+        self.image.as_mut().map_or(Ok(()), KeyboardImage::load)
+    }
+
     #[inline]
     pub fn push(&mut self, action: Effect) {
         self.effects.push(action);
@@ -100,7 +141,45 @@ impl AdvancedEffects {
         None
     }
 
+    /// Set the value of every `Heatmap` from `read`. A `Heatmap` whose source
+    /// has no value is left as it is.
+    pub fn update_metrics(&mut self, mut read: impl FnMut(&MetricSource) -> Option<f32>) {
+        for effect in &mut self.effects {
+            if let Effect::Heatmap(heatmap) = effect {
+                if let Some(value) = read(heatmap.source()) {
+                    heatmap.set_value(value);
+                }
+            }
+        }
+    }
+
+    /// If any effect is a `Heatmap`, which needs `update_metrics()`
+    pub fn uses_metrics(&self) -> bool {
+        self.effects
+            .iter()
+            .any(|effect| matches!(effect, Effect::Heatmap(_)))
+    }
+
+    /// If any effect reacts to key presses
+    pub fn reacts_to_keys(&self) -> bool {
+        self.effects.iter().any(|effect| effect.input().is_some())
+    }
+
+    /// Pass a key press to every effect that reacts to them. `leds` are the
+    /// LEDs of the key, such as from `LedCode::for_evdev_key()`.
+    pub fn key_pressed(&mut self, leds: &[LedCode], layout: &KeyLayout) {
+        for effect in &mut self.effects {
+            if let Some(input) = effect.input_mut() {
+                input.key_pressed(leds, layout);
+            }
+        }
+    }
+
     pub fn next_state(&mut self, layout: &KeyLayout) {
+        #[cfg(feature = "image")]
+        if let Some(image) = self.image.as_mut() {
+            image.next_state(layout);
+        }
         for effect in &mut self.effects {
             effect.next_state(layout);
         }
@@ -115,6 +194,12 @@ impl AdvancedEffects {
             LedUsbPackets::new_per_key()
         };
 
+        #[cfg(feature = "image")]
+        if let Some(image) = &self.image {
+            for (led, c) in image.colours() {
+                usb_packets.set(*led, c.r, c.g, c.b);
+            }
+        }
         for effect in &self.effects {
             let c = effect.colour();
             usb_packets.set(effect.led(), c.r, c.g, c.b);
@@ -187,6 +272,13 @@ pub enum Effect {
     Breathe(Breathe),
     DoomFlicker(DoomFlicker),
     DoomLightFlash(DoomLightFlash),
+    Heatmap(Heatmap),
+    Gradient(Gradient),
+    Wave(Wave),
+    RainbowSweep(RainbowSweep),
+    KeyFade(KeyFade),
+    Ripple(Ripple),
+    TypingHeat(TypingHeat),
 }
 
 impl Default for Effect {
@@ -195,11 +287,38 @@ impl Default for Effect {
     }
 }
 
-effect_impl!(Static, Breathe, DoomFlicker, DoomLightFlash);
+impl Effect {
+    /// The effect as one reacting to key presses, if it does
+    fn input(&self) -> Option<&dyn InputForEffect> {
+        match self {
+            Effect::KeyFade(e) => Some(e),
+            Effect::Ripple(e) => Some(e),
+            Effect::TypingHeat(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn input_mut(&mut self) -> Option<&mut dyn InputForEffect> {
+        match self {
+            Effect::KeyFade(e) => Some(e),
+            Effect::Ripple(e) => Some(e),
+            Effect::TypingHeat(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+effect_impl!(
+    Static, Breathe, DoomFlicker, DoomLightFlash, Heatmap, Gradient, Wave, RainbowSweep, KeyFade,
+    Ripple, TypingHeat
+);
 
 #[cfg(test)]
 mod tests {
-    use crate::effects::{AdvancedEffects, Breathe, DoomFlicker, Effect, Static};
+    use crate::effects::{
+        AdvancedEffects, Breathe, DoomFlicker, Effect, Gradient, Heatmap, KeyFade, MetricSource,
+        RainbowSweep, Ripple, Static, Sweep, TypingHeat, Wave,
+    };
     use crate::keyboard::{KeyLayout, LedCode};
     use crate::{Colour, Speed};
 
@@ -302,4 +421,211 @@ mod tests {
         assert_eq!(packets[5][34], 87);
         assert_eq!(packets[5][35], 40);
     }
+
+    #[test]
+    fn heatmap_from_metrics() {
+        let layout = KeyLayout::default_layout();
+        let mut seq = AdvancedEffects::new(false);
+        assert!(!seq.uses_metrics());
+        let ramp = vec![
+            Colour { r: 0, g: 0, b: 255 },
+            Colour { r: 0, g: 255, b: 0 },
+            Colour { r: 255, g: 0, b: 0 },
+        ];
+        seq.push(Effect::Heatmap(Heatmap::new(
+            LedCode::N1,
+            MetricSource::CpuCore(0),
+            0.0,
+            100.0,
+            ramp.clone(),
+        )));
+        seq.push(Effect::Heatmap(Heatmap::new(
+            LedCode::N2,
+            MetricSource::Temperature("k10temp".to_owned()),
+            40.0,
+            90.0,
+            ramp,
+        )));
+        assert!(seq.uses_metrics());
+
+        seq.update_metrics(|source| match source {
+            MetricSource::CpuCore(0) => Some(25.0),
+            MetricSource::Temperature(_) => Some(120.0),
+            _ => None,
+        });
+        seq.next_state(&layout);
+        assert_eq!(seq.effects[0].colour(), Colour {
+            r: 0,
+            g: 128,
+            b: 128
+        });
+        assert_eq!(seq.effects[1].colour(), Colour { r: 255, g: 0, b: 0 });
+
+        // No value keeps the last one
+        seq.update_metrics(|_| None);
+        seq.next_state(&layout);
+        assert_eq!(seq.effects[1].colour(), Colour { r: 255, g: 0, b: 0 });
+    }
+
+    #[test]
+    fn spatial_effects() {
+        let layout = KeyLayout::default_layout();
+        let black = Colour { r: 0, g: 0, b: 0 };
+        let white = Colour {
+            r: 255,
+            g: 255,
+            b: 255,
+        };
+        let colour_of = |seq: &AdvancedEffects, led| {
+            seq.effects
+                .iter()
+                .find(|effect| effect.led() == led)
+                .map(Effect::colour)
+                .unwrap()
+        };
+
+        let mut seq = AdvancedEffects::spatial(&layout, |led| {
+            Effect::Gradient(Gradient::new(led, Sweep::Horizontal, black, white))
+        });
+        seq.next_state(&layout);
+        // Every LED in the layout, left column black and right column white
+        assert_eq!(seq.effects.len(), layout.led_centres().count());
+        assert_eq!(colour_of(&seq, LedCode::Esc), black);
+        assert_eq!(colour_of(&seq, LedCode::Tilde), black);
+        assert_eq!(colour_of(&seq, LedCode::Backspace), white);
+        let g = colour_of(&seq, LedCode::G);
+        assert!(g.r > 60 && g.r < 120, "{g:?}");
+
+        let mut seq = AdvancedEffects::spatial(&layout, |led| {
+            Effect::Gradient(Gradient::new(led, Sweep::Vertical, black, white))
+        });
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::F12), black);
+        assert_eq!(colour_of(&seq, LedCode::LCtrl), white);
+        assert_eq!(
+            colour_of(&seq, LedCode::A),
+            colour_of(&seq, LedCode::Return)
+        );
+
+        // Outwards from G, keys the same distance away match
+        let (x, y) = layout.led_centre(LedCode::G).unwrap();
+        let mut seq = AdvancedEffects::spatial(&layout, |led| {
+            Effect::Wave(Wave::new(
+                led,
+                Sweep::Radial { x, y },
+                black,
+                white,
+                1.0,
+                Speed::High,
+            ))
+        });
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::G), black);
+        assert_eq!(colour_of(&seq, LedCode::F), colour_of(&seq, LedCode::H));
+        assert_ne!(colour_of(&seq, LedCode::F), black);
+        // The wave moves outwards, so G lights up
+        seq.next_state(&layout);
+        assert_ne!(colour_of(&seq, LedCode::G), black);
+
+        let mut seq = AdvancedEffects::spatial(&layout, |led| {
+            Effect::RainbowSweep(RainbowSweep::new(led, Sweep::Horizontal, 1.0, Speed::Med))
+        });
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::Esc), Colour { r: 255, g: 0, b: 0 });
+        assert_eq!(colour_of(&seq, LedCode::Backspace), Colour {
+            r: 255,
+            g: 0,
+            b: 0
+        });
+        let mid = colour_of(&seq, LedCode::G);
+        assert!(mid.r < 255 || mid.b > 0, "{mid:?}");
+        // A LED not in the layout takes the colour at the start
+        let mut lone = RainbowSweep::new(LedCode::Home, Sweep::Vertical, 1.0, Speed::Med);
+        crate::effects::EffectState::next_colour_state(&mut lone, &layout);
+        assert_eq!(crate::effects::EffectState::get_colour(&lone), Colour {
+            r: 255,
+            g: 0,
+            b: 0
+        });
+    }
+
+    #[test]
+    fn reactive_effects() {
+        let layout = KeyLayout::default_layout();
+        let black = Colour { r: 0, g: 0, b: 0 };
+        let red = Colour { r: 240, g: 0, b: 0 };
+        let colour_of = |seq: &AdvancedEffects, led| {
+            seq.effects
+                .iter()
+                .find(|effect| effect.led() == led)
+                .map(Effect::colour)
+                .unwrap()
+        };
+
+        let mut seq =
+            AdvancedEffects::spatial(&layout, |led| Effect::KeyFade(KeyFade::new(led, red, 3)));
+        assert!(seq.reacts_to_keys());
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::A), black);
+        seq.key_pressed(LedCode::for_evdev_key(30), &layout);
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::A), red);
+        assert_eq!(colour_of(&seq, LedCode::S), black);
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::A), Colour { r: 160, g: 0, b: 0 });
+        seq.next_state(&layout);
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::A), black);
+
+        let mut seq = AdvancedEffects::spatial(&layout, |led| {
+            Effect::Ripple(Ripple::new(led, red, Speed::High))
+        });
+        seq.key_pressed(&[LedCode::G], &layout);
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::G), red);
+        assert_eq!(colour_of(&seq, LedCode::Esc), black);
+        // The ring spreads out, keys the same distance away match
+        for _ in 0..2 {
+            seq.next_state(&layout);
+        }
+        let f = colour_of(&seq, LedCode::F);
+        assert_eq!(f, colour_of(&seq, LedCode::H));
+        assert!(colour_of(&seq, LedCode::D).r > colour_of(&seq, LedCode::G).r);
+        // Until it has passed the whole keyboard
+        for _ in 0..30 {
+            seq.next_state(&layout);
+        }
+        assert!(seq.effects.iter().all(|effect| effect.colour() == black));
+
+        let ramp = vec![
+            black,
+            Colour { r: 255, g: 0, b: 0 },
+        ];
+        let mut seq = AdvancedEffects::new(false);
+        seq.push(Effect::TypingHeat(TypingHeat::new(
+            LedCode::E,
+            ramp,
+            Speed::High,
+        )));
+        seq.push(Effect::Static(Static::new(LedCode::Q, red)));
+        for _ in 0..2 {
+            seq.key_pressed(&[LedCode::E], &layout);
+        }
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::E), Colour { r: 128, g: 0, b: 0 });
+        // Fully hot after four presses
+        for _ in 0..4 {
+            seq.key_pressed(&[LedCode::E], &layout);
+        }
+        seq.next_state(&layout);
+        assert_eq!(colour_of(&seq, LedCode::E), Colour { r: 255, g: 0, b: 0 });
+        for _ in 0..150 {
+            seq.next_state(&layout);
+        }
+        assert_eq!(colour_of(&seq, LedCode::E), black);
+
+        let mut seq = AdvancedEffects::new(false);
+        seq.push(Effect::Static(Static::new(LedCode::Q, red)));
+        assert!(!seq.reacts_to_keys());
+    }
 }
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+use super::{ramp_colour, EffectState, InputForEffect};
+use crate::keyboard::{KeyLayout, LedCode};
+use crate::{effect_state_impl, Colour, Speed};
+
+const BLACK: Colour = Colour { r: 0, g: 0, b: 0 };
+/// How wide the ring of a `Ripple` is, in key units
+const RING_WIDTH: f32 = 1.5;
+/// The heat a `TypingHeat` gains for each press, at 1.0 it's fully hot
+const HEAT_PER_PRESS: f32 = 0.25;
+
+/// The key lights in `press_colour` when pressed, then fades out
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeyFade {
+    led: LedCode,
+    press_colour: Colour,
+    /// Frames the fade takes, 30 is about a second
+    frames: u32,
+    #[serde(skip)]
+    left: u32,
+    #[serde(skip)]
+    colour: Colour,
+}
+
+impl KeyFade {
+    pub fn new(address: LedCode, press_colour: Colour, frames: u32) -> Self {
+        Self {
+            led: address,
+            press_colour,
+            frames,
+            left: 0,
+            colour: BLACK,
+        }
+    }
+}
+
+impl EffectState for KeyFade {
+    effect_state_impl!();
+
+    fn next_colour_state(&mut self, _layout: &KeyLayout) {
+        self.colour = BLACK.blend(
+            self.press_colour,
+            self.left as f32 / self.frames.max(1) as f32,
+        );
+        self.left = self.left.saturating_sub(1);
+    }
+}
+
+impl InputForEffect for KeyFade {
+    fn key_pressed(&mut self, leds: &[LedCode], _layout: &KeyLayout) {
+        if leds.contains(&self.led) {
+            self.left = self.frames;
+        }
+    }
+}
+
+/// A ring spreading out across the keyboard from each pressed key, fading as
+/// it goes
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Ripple {
+    led: LedCode,
+    press_colour: Colour,
+    speed: Speed,
+    /// The centre and radius of each ring
+    #[serde(skip)]
+    rings: Vec<((f32, f32), f32)>,
+    #[serde(skip)]
+    colour: Colour,
+}
+
+impl Ripple {
+    pub fn new(address: LedCode, press_colour: Colour, speed: Speed) -> Self {
+        Self {
+            led: address,
+            press_colour,
+            speed,
+            rings: Vec::new(),
+            colour: BLACK,
+        }
+    }
+
+    /// Key units the rings grow each frame
+    fn step(&self) -> f32 {
+        match self.speed {
+            Speed::Low => 0.25,
+            Speed::Med => 0.5,
+            Speed::High => 1.0,
+        }
+    }
+}
+
+impl EffectState for Ripple {
+    effect_state_impl!();
+
+    fn next_colour_state(&mut self, layout: &KeyLayout) {
+        let (Some((x, y)), Some(((min_x, min_y), (max_x, max_y)))) =
+            (layout.led_centre(self.led), layout.led_bounds())
+        else {
+            self.rings.clear();
+            self.colour = BLACK;
+            return;
+        };
+        let furthest = (max_x - min_x).hypot(max_y - min_y).max(1.0);
+        let brightness = self
+            .rings
+            .iter()
+            .map(|&((cx, cy), radius)| {
+                let on_ring = 1.0 - ((x - cx).hypot(y - cy) - radius).abs() / RING_WIDTH;
+                on_ring.max(0.0) * (1.0 - radius / furthest).max(0.0)
+            })
+            .fold(0.0, f32::max);
+        self.colour = BLACK.blend(self.press_colour, brightness);
+
+        let step = self.step();
+        for (_, radius) in &mut self.rings {
+            *radius += step;
+        }
+        self.rings.retain(|(_, radius)| *radius < furthest);
+    }
+}
+
+impl InputForEffect for Ripple {
+    fn key_pressed(&mut self, leds: &[LedCode], layout: &KeyLayout) {
+        let centres: Vec<(f32, f32)> = leds
+            .iter()
+            .filter_map(|led| layout.led_centre(*led))
+            .collect();
+        if centres.is_empty() {
+            return;
+        }
+        let count = centres.len() as f32;
+        let (x, y) = centres
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        self.rings.push(((x / count, y / count), 0.0));
+    }
+}
+
+/// The key warms along `ramp` the more it's pressed, and cools down when left
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TypingHeat {
+    led: LedCode,
+    /// Colours from cold to hot, the first is shown when the key is unused
+    ramp: Vec<Colour>,
+    /// How quickly the key cools
+    speed: Speed,
+    #[serde(skip)]
+    heat: f32,
+    #[serde(skip)]
+    colour: Colour,
+}
+
+impl TypingHeat {
+    pub fn new(address: LedCode, ramp: Vec<Colour>, speed: Speed) -> Self {
+        let colour = ramp_colour(&ramp, 0.0);
+        Self {
+            led: address,
+            ramp,
+            speed,
+            heat: 0.0,
+            colour,
+        }
+    }
+
+    /// Heat lost each frame, from fully hot it takes 20s, 10s, or 5s to cool
+    fn cooling(&self) -> f32 {
+        match self.speed {
+            Speed::Low => 1.0 / 600.0,
+            Speed::Med => 1.0 / 300.0,
+            Speed::High => 1.0 / 150.0,
+        }
+    }
+}
+
+impl EffectState for TypingHeat {
+    effect_state_impl!();
+
+    fn next_colour_state(&mut self, _layout: &KeyLayout) {
+        self.colour = ramp_colour(&self.ramp, self.heat);
+        self.heat = (self.heat - self.cooling()).max(0.0);
+    }
+}
+
+impl InputForEffect for TypingHeat {
+    fn key_pressed(&mut self, leds: &[LedCode], _layout: &KeyLayout) {
+        if leds.contains(&self.led) {
+            self.heat = (self.heat + HEAT_PER_PRESS).min(1.0);
+        }
+    }
+}
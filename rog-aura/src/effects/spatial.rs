@@ -0,0 +1,214 @@
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+
+use super::{AdvancedEffects, Effect, EffectState};
+use crate::keyboard::{AdvancedAuraType, KeyLayout, LedCode};
+use crate::{effect_state_impl, Colour, Speed};
+
+/// How a spatial effect is laid across the keyboard, using the position of
+/// each LED in the `KeyLayout`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Sweep {
+    /// From the left edge to the right
+    Horizontal,
+    /// From the top row to the bottom
+    Vertical,
+    /// Outwards from a point, in key units from the top left as with
+    /// `KeyLayout::led_centre()`
+    Radial { x: f32, y: f32 },
+}
+
+impl Sweep {
+    /// How far along the sweep `led` is, from 0.0 at the start to 1.0 at the
+    /// furthest LED. `None` if the layout doesn't have the LED.
+    pub fn position(&self, layout: &KeyLayout, led: LedCode) -> Option<f32> {
+        let (x, y) = layout.led_centre(led)?;
+        let ((min_x, min_y), (max_x, max_y)) = layout.led_bounds()?;
+        let (along, length) = match *self {
+            Self::Horizontal => (x - min_x, max_x - min_x),
+            Self::Vertical => (y - min_y, max_y - min_y),
+            Self::Radial { x: ox, y: oy } => {
+                let furthest = [
+                    (min_x, min_y),
+                    (max_x, min_y),
+                    (min_x, max_y),
+                    (max_x, max_y),
+                ]
+                .iter()
+                .map(|(cx, cy)| (cx - ox).hypot(cy - oy))
+                .fold(0.0, f32::max);
+                ((x - ox).hypot(y - oy), furthest)
+            }
+        };
+        if length > 0.0 {
+            Some((along / length).clamp(0.0, 1.0))
+        } else {
+            Some(0.0)
+        }
+    }
+}
+
+/// The part of a cycle a moving effect advances each frame. At 30 frames a
+/// second a cycle takes 3s, 2s, and 1s.
+fn cycles_per_frame(speed: Speed) -> f32 {
+    match speed {
+        Speed::Low => 1.0 / 90.0,
+        Speed::Med => 1.0 / 60.0,
+        Speed::High => 1.0 / 30.0,
+    }
+}
+
+/// A fully saturated colour, `hue` runs from 0.0 to 1.0 round the colour
+/// wheel starting at red
+fn hue_colour(hue: f32) -> Colour {
+    let h = hue.rem_euclid(1.0) * 6.0 % 6.0;
+    let rise = ((h % 1.0) * 255.0).round() as u8;
+    let fall = 255 - rise;
+    let (r, g, b) = match h as u8 {
+        0 => (255, rise, 0),
+        1 => (fall, 255, 0),
+        2 => (0, 255, rise),
+        3 => (0, fall, 255),
+        4 => (rise, 0, 255),
+        _ => (255, 0, fall),
+    };
+    Colour { r, g, b }
+}
+
+/// A fixed blend from `colour1` at the start of the sweep to `colour2` at the
+/// end
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Gradient {
+    led: LedCode,
+    sweep: Sweep,
+    colour1: Colour,
+    colour2: Colour,
+    #[serde(skip)]
+    colour: Colour,
+}
+
+impl Gradient {
+    pub fn new(address: LedCode, sweep: Sweep, colour1: Colour, colour2: Colour) -> Self {
+        Self {
+            led: address,
+            sweep,
+            colour1,
+            colour2,
+            colour: colour1,
+        }
+    }
+}
+
+impl EffectState for Gradient {
+    effect_state_impl!();
+
+    fn next_colour_state(&mut self, layout: &KeyLayout) {
+        let t = self.sweep.position(layout, self.led).unwrap_or_default();
+        self.colour = self.colour1.blend(self.colour2, t);
+    }
+}
+
+/// Bands moving along the sweep, fading between `colour1` and `colour2`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Wave {
+    led: LedCode,
+    sweep: Sweep,
+    colour1: Colour,
+    colour2: Colour,
+    /// How much of the sweep one wave covers, `0.5` gives two waves across the
+    /// keyboard
+    length: f32,
+    speed: Speed,
+    #[serde(skip)]
+    phase: f32,
+    #[serde(skip)]
+    colour: Colour,
+}
+
+impl Wave {
+    pub fn new(
+        address: LedCode,
+        sweep: Sweep,
+        colour1: Colour,
+        colour2: Colour,
+        length: f32,
+        speed: Speed,
+    ) -> Self {
+        Self {
+            led: address,
+            sweep,
+            colour1,
+            colour2,
+            length,
+            speed,
+            phase: 0.0,
+            colour: colour1,
+        }
+    }
+}
+
+impl EffectState for Wave {
+    effect_state_impl!();
+
+    fn next_colour_state(&mut self, layout: &KeyLayout) {
+        let t = self.sweep.position(layout, self.led).unwrap_or_default();
+        let cycle = t / self.length.max(f32::EPSILON) - self.phase;
+        self.colour = self
+            .colour1
+            .blend(self.colour2, (1.0 - (cycle * TAU).cos()) / 2.0);
+        self.phase = (self.phase + cycles_per_frame(self.speed)).fract();
+    }
+}
+
+/// The colour wheel moving along the sweep
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RainbowSweep {
+    led: LedCode,
+    sweep: Sweep,
+    /// How much of the sweep one pass of the colour wheel covers
+    length: f32,
+    speed: Speed,
+    #[serde(skip)]
+    phase: f32,
+    #[serde(skip)]
+    colour: Colour,
+}
+
+impl RainbowSweep {
+    pub fn new(address: LedCode, sweep: Sweep, length: f32, speed: Speed) -> Self {
+        Self {
+            led: address,
+            sweep,
+            length,
+            speed,
+            phase: 0.0,
+            colour: hue_colour(0.0),
+        }
+    }
+}
+
+impl EffectState for RainbowSweep {
+    effect_state_impl!();
+
+    fn next_colour_state(&mut self, layout: &KeyLayout) {
+        let t = self.sweep.position(layout, self.led).unwrap_or_default();
+        self.colour = hue_colour(t / self.length.max(f32::EPSILON) - self.phase);
+        self.phase = (self.phase + cycles_per_frame(self.speed)).fract();
+    }
+}
+
+impl AdvancedEffects {
+    /// One effect for every LED in `layout`, made by `effect`. Used with the
+    /// spatial effects to cover the whole keyboard.
+    pub fn spatial(layout: &KeyLayout, effect: impl Fn(LedCode) -> Effect) -> Self {
+        let zoned = matches!(layout.advanced_type(), AdvancedAuraType::Zoned(_));
+        let mut leds: Vec<LedCode> = layout.led_centres().map(|(led, _)| led).collect();
+        leds.sort();
+        let mut effects = Self::new(zoned);
+        for led in leds {
+            effects.push(effect(led));
+        }
+        effects
+    }
+}
@@ -7,6 +7,8 @@ pub enum Error {
     ParseDirection,
     ParseBrightness,
     IoPath(String, std::io::Error),
+    /// The path and why the image could not be read
+    Image(String, String),
     Ron(ron::Error),
     RonParse(ron::error::SpannedError),
 }
@@ -20,6 +22,7 @@ impl fmt::Display for Error {
             Error::ParseDirection => write!(f, "Could not parse direction"),
             Error::ParseBrightness => write!(f, "Could not parse brightness"),
             Error::IoPath(path, io) => write!(f, "IO Error: {path}, {io}"),
+            Error::Image(path, e) => write!(f, "Image Error: {path}, {e}"),
             Error::Ron(e) => write!(f, "RON Parse Error: {e}"),
             Error::RonParse(e) => write!(f, "RON Parse Error: {e}"),
         }
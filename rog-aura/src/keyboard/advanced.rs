@@ -9,7 +9,9 @@ use zbus::zvariant::Type;
 /// The `LedCode` used in setting up keyboard layouts is important because it
 /// determines the idexing for an RGB value in the final USB packets (for
 /// per-key addressable keyboards).
-#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[derive(
+    Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Serialize, Deserialize,
+)]
 pub enum LedCode {
     VolUp,
     VolDown,
@@ -161,6 +163,10 @@ pub enum LedCode {
     ZonedKbRightMid,
     /// Use if the keyboard supports 4 zones, this is the right zone
     ZonedKbRight,
+    /// An LED without a name here, such as the ISO `<>` key, given by the
+    /// packet row and the column of its red byte. Layout files name these
+    /// with `extra_leds`.
+    Raw(u8, u8),
     /// To be ignored by effects
     Spacing,
     /// To be ignored by effects
@@ -168,6 +174,160 @@ pub enum LedCode {
 }
 
 impl LedCode {
+    /// Every `LedCode` except `Raw`, in declaration order
+    pub const ALL: [LedCode; 135] = [
+        Self::VolUp,
+        Self::VolDown,
+        Self::MicMute,
+        Self::RogApp,
+        Self::RogFan,
+        Self::Esc,
+        Self::F1,
+        Self::F2,
+        Self::F3,
+        Self::F4,
+        Self::F5,
+        Self::F6,
+        Self::F7,
+        Self::F8,
+        Self::F9,
+        Self::F10,
+        Self::F11,
+        Self::F12,
+        Self::Del,
+        Self::Tilde,
+        Self::N1,
+        Self::N2,
+        Self::N3,
+        Self::N4,
+        Self::N5,
+        Self::N6,
+        Self::N7,
+        Self::N8,
+        Self::N9,
+        Self::N0,
+        Self::Hyphen,
+        Self::Equals,
+        Self::Backspace,
+        Self::Backspace3_1,
+        Self::Backspace3_2,
+        Self::Backspace3_3,
+        Self::Home,
+        Self::Tab,
+        Self::Q,
+        Self::W,
+        Self::E,
+        Self::R,
+        Self::T,
+        Self::Y,
+        Self::U,
+        Self::I,
+        Self::O,
+        Self::P,
+        Self::LBracket,
+        Self::RBracket,
+        Self::BackSlash,
+        Self::PgUp,
+        Self::Caps,
+        Self::A,
+        Self::S,
+        Self::D,
+        Self::F,
+        Self::G,
+        Self::H,
+        Self::J,
+        Self::K,
+        Self::L,
+        Self::SemiColon,
+        Self::Quote,
+        Self::Return,
+        Self::Return3_1,
+        Self::Return3_2,
+        Self::Return3_3,
+        Self::PgDn,
+        Self::LShift,
+        Self::LShift3_1,
+        Self::LShift3_2,
+        Self::LShift3_3,
+        Self::Z,
+        Self::X,
+        Self::C,
+        Self::V,
+        Self::B,
+        Self::N,
+        Self::M,
+        Self::Comma,
+        Self::Period,
+        Self::FwdSlash,
+        Self::Star,
+        Self::NumPadDel,
+        Self::NumPadPlus,
+        Self::NumPadEnter,
+        Self::NumPadPause,
+        Self::NumPadPrtSc,
+        Self::NumPadHome,
+        Self::NumLock,
+        Self::Rshift,
+        Self::Rshift3_1,
+        Self::Rshift3_2,
+        Self::Rshift3_3,
+        Self::End,
+        Self::LCtrl,
+        Self::LFn,
+        Self::Meta,
+        Self::LAlt,
+        Self::Spacebar,
+        Self::Spacebar5_1,
+        Self::Spacebar5_2,
+        Self::Spacebar5_3,
+        Self::Spacebar5_4,
+        Self::Spacebar5_5,
+        Self::Pause,
+        Self::RAlt,
+        Self::PrtSc,
+        Self::RCtrl,
+        Self::Up,
+        Self::Down,
+        Self::Left,
+        Self::Right,
+        Self::RFn,
+        Self::MediaPlay,
+        Self::MediaStop,
+        Self::MediaNext,
+        Self::MediaPrev,
+        Self::LidLogo,
+        Self::LidLeft,
+        Self::LidRight,
+        Self::LightbarRight,
+        Self::LightbarRightCorner,
+        Self::LightbarRightBottom,
+        Self::LightbarLeftBottom,
+        Self::LightbarLeftCorner,
+        Self::LightbarLeft,
+        Self::SingleZone,
+        Self::ZonedKbLeft,
+        Self::ZonedKbLeftMid,
+        Self::ZonedKbRightMid,
+        Self::ZonedKbRight,
+        Self::Spacing,
+        Self::Blocking,
+    ];
+    /// The function keys, from left to right
+    pub const FUNCTION_ROW: [LedCode; 12] = [
+        Self::F1,
+        Self::F2,
+        Self::F3,
+        Self::F4,
+        Self::F5,
+        Self::F6,
+        Self::F7,
+        Self::F8,
+        Self::F9,
+        Self::F10,
+        Self::F11,
+        Self::F12,
+    ];
+
     pub fn is_placeholder(&self) -> bool {
         matches!(self, Self::Spacing | Self::Blocking)
     }
@@ -190,6 +350,137 @@ impl LedCode {
                 | Self::LightbarRight
         )
     }
+
+    /// The LEDs of the key with this evdev key code, as in
+    /// `linux/input-event-codes.h`. Keys with more than one LED give them all.
+    /// Empty if the key has no LED.
+    pub fn for_evdev_key(code: u16) -> &'static [LedCode] {
+        match code {
+            1 => &[Self::Esc],
+            2 => &[Self::N1],
+            3 => &[Self::N2],
+            4 => &[Self::N3],
+            5 => &[Self::N4],
+            6 => &[Self::N5],
+            7 => &[Self::N6],
+            8 => &[Self::N7],
+            9 => &[Self::N8],
+            10 => &[Self::N9],
+            11 => &[Self::N0],
+            12 => &[Self::Hyphen],
+            13 => &[Self::Equals],
+            14 => &[
+                Self::Backspace,
+                Self::Backspace3_1,
+                Self::Backspace3_2,
+                Self::Backspace3_3,
+            ],
+            15 => &[Self::Tab],
+            16 => &[Self::Q],
+            17 => &[Self::W],
+            18 => &[Self::E],
+            19 => &[Self::R],
+            20 => &[Self::T],
+            21 => &[Self::Y],
+            22 => &[Self::U],
+            23 => &[Self::I],
+            24 => &[Self::O],
+            25 => &[Self::P],
+            26 => &[Self::LBracket],
+            27 => &[Self::RBracket],
+            28 => &[
+                Self::Return,
+                Self::Return3_1,
+                Self::Return3_2,
+                Self::Return3_3,
+            ],
+            29 => &[Self::LCtrl],
+            30 => &[Self::A],
+            31 => &[Self::S],
+            32 => &[Self::D],
+            33 => &[Self::F],
+            34 => &[Self::G],
+            35 => &[Self::H],
+            36 => &[Self::J],
+            37 => &[Self::K],
+            38 => &[Self::L],
+            39 => &[Self::SemiColon],
+            40 => &[Self::Quote],
+            41 => &[Self::Tilde],
+            42 => &[
+                Self::LShift,
+                Self::LShift3_1,
+                Self::LShift3_2,
+                Self::LShift3_3,
+            ],
+            43 => &[Self::BackSlash],
+            44 => &[Self::Z],
+            45 => &[Self::X],
+            46 => &[Self::C],
+            47 => &[Self::V],
+            48 => &[Self::B],
+            49 => &[Self::N],
+            50 => &[Self::M],
+            51 => &[Self::Comma],
+            52 => &[Self::Period],
+            53 => &[Self::FwdSlash],
+            54 => &[
+                Self::Rshift,
+                Self::Rshift3_1,
+                Self::Rshift3_2,
+                Self::Rshift3_3,
+            ],
+            55 => &[Self::Star],
+            56 => &[Self::LAlt],
+            57 => &[
+                Self::Spacebar,
+                Self::Spacebar5_1,
+                Self::Spacebar5_2,
+                Self::Spacebar5_3,
+                Self::Spacebar5_4,
+                Self::Spacebar5_5,
+            ],
+            58 => &[Self::Caps],
+            59 => &[Self::F1],
+            60 => &[Self::F2],
+            61 => &[Self::F3],
+            62 => &[Self::F4],
+            63 => &[Self::F5],
+            64 => &[Self::F6],
+            65 => &[Self::F7],
+            66 => &[Self::F8],
+            67 => &[Self::F9],
+            68 => &[Self::F10],
+            69 => &[Self::NumLock],
+            78 => &[Self::NumPadPlus],
+            83 => &[Self::NumPadDel],
+            87 => &[Self::F11],
+            88 => &[Self::F12],
+            96 => &[Self::NumPadEnter],
+            97 => &[Self::RCtrl],
+            99 => &[Self::PrtSc],
+            100 => &[Self::RAlt],
+            102 => &[Self::Home],
+            103 => &[Self::Up],
+            104 => &[Self::PgUp],
+            105 => &[Self::Left],
+            106 => &[Self::Right],
+            107 => &[Self::End],
+            108 => &[Self::Down],
+            109 => &[Self::PgDn],
+            111 => &[Self::Del],
+            114 => &[Self::VolDown],
+            115 => &[Self::VolUp],
+            119 => &[Self::Pause],
+            125 => &[Self::Meta],
+            163 => &[Self::MediaNext],
+            164 => &[Self::MediaPlay],
+            165 => &[Self::MediaPrev],
+            166 => &[Self::MediaStop],
+            248 => &[Self::MicMute],
+            _ => &[],
+        }
+    }
 }
 
 /// Represents the per-key raw USB packets
@@ -304,7 +595,21 @@ impl LedUsbPackets {
     /// Indexing is different for `zoned` and assumes that only one packet is
     /// generated for all the zones
     fn rgb_for_led_code(&mut self, led_code: LedCode) -> Option<&mut [u8]> {
-        let zoned = self.zoned;
+        let (row, col) = Self::packet_index(led_code, self.zoned)?;
+
+        if self.zoned && row > 0 {
+            warn!(
+                "LedCode {led_code:?} for zoned is not correct or out of Zone range. Setting to 0",
+            );
+            return None;
+        }
+
+        self.usb_packets.get_mut(row)?.get_mut(col..=col + 2)
+    }
+
+    /// The `(row, column)` of the first of the three R,G,B bytes for an
+    /// `LedCode`, or `None` if the `LedCode` has no known addressing
+    pub(crate) fn packet_index(led_code: LedCode, zoned: bool) -> Option<(usize, usize)> {
         // Tuples are indexes in to array
         #[allow(clippy::match_same_arms)]
         let (row, col) = match led_code {
@@ -436,6 +741,7 @@ impl LedUsbPackets {
             LedCode::LightbarLeftBottom => if zoned {(0, 36)}  else{(11, 24)},
             LedCode::LightbarLeftCorner => if zoned {(0, 39)}  else{(11, 27)},
             LedCode::LightbarLeft => if zoned {(0, 42)}  else{(11, 30)},
+            LedCode::Raw(row, col) => (row as usize, col as usize),
             //
             LedCode::Spacing
             | LedCode::Blocking
@@ -457,15 +763,7 @@ impl LedUsbPackets {
             | LedCode::Spacebar
             | LedCode::Backspace => return None,
         };
-
-        if self.zoned && row > 0 {
-            warn!(
-                "LedCode {led_code:?} for zoned is not correct or out of Zone range. Setting to 0",
-            );
-            return None;
-        }
-
-        Some(&mut self.usb_packets[row][col..=col + 2])
+        Some((row, col))
     }
 
     #[inline]
@@ -629,6 +927,7 @@ impl From<&LedCode> for &str {
             LedCode::LightbarLeftCorner => "Lightbar Left Corner",
             LedCode::LightbarLeft => "Lightbar Left",
             LedCode::Spacing | LedCode::Blocking => "",
+            LedCode::Raw(..) => "Extra LED",
             LedCode::SingleZone => "Single Zoned Keyboard",
             LedCode::ZonedKbLeft => "Left Zone (zone 1)",
             LedCode::ZonedKbLeftMid => "Center-left Zone (zone 2)",
@@ -657,6 +956,27 @@ mod tests {
         };
     }
 
+    #[test]
+    fn evdev_keys() {
+        // KEY_ESC, KEY_A, KEY_F12
+        assert_eq!(LedCode::for_evdev_key(1), &[LedCode::Esc]);
+        assert_eq!(LedCode::for_evdev_key(30), &[LedCode::A]);
+        assert_eq!(LedCode::for_evdev_key(88), &[LedCode::F12]);
+        // KEY_SPACE lights every spacebar LED
+        assert!(LedCode::for_evdev_key(57).contains(&LedCode::Spacebar5_3));
+        // KEY_RESERVED and BTN_LEFT
+        assert!(LedCode::for_evdev_key(0).is_empty());
+        assert!(LedCode::for_evdev_key(0x110).is_empty());
+        // Each LED is given by one key only
+        let mut seen = Vec::new();
+        for code in 0..=255 {
+            for led in LedCode::for_evdev_key(code) {
+                assert!(!seen.contains(led), "{led:?} is given twice");
+                seen.push(*led);
+            }
+        }
+    }
+
     #[test]
     fn zone_to_packet_check() {
         let zone = LedUsbPackets::new_zoned(true);
@@ -724,5 +1044,12 @@ mod tests {
         assert_eq!(pkt[7][13], 0xff); // M
         assert_eq!(pkt[7][14], 0xff); // M
         assert_eq!(pkt[7][15], 0x00); // M
+
+        let mut per_key = LedUsbPackets::new_per_key();
+        per_key.set(LedCode::Raw(6, 39), 0xff, 0x80, 0x01);
+        assert!(per_key.rgb_for_led_code(LedCode::Raw(20, 9)).is_none());
+        assert!(per_key.rgb_for_led_code(LedCode::Raw(6, 62)).is_none());
+        let pkt: AuraLaptopUsbPackets = per_key.into();
+        assert_eq!(&pkt[6][39..42], &[0xff, 0x80, 0x01]);
     }
 }
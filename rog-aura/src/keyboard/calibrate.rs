@@ -0,0 +1,357 @@
+use crate::keyboard::{AuraLaptopUsbPackets, ExtraLed, KeyLayout, LedCode, LedUsbPackets};
+use crate::Colour;
+
+/// The first byte of LED colours in a per-key packet, after the header
+const FIRST_COLUMN: u8 = 9;
+
+/// Where keys are usually placed, row by row. A layout made by calibration
+/// puts the keys found in this order.
+const ROWS: [&[LedCode]; 9] = [
+    &[
+        LedCode::VolDown,
+        LedCode::VolUp,
+        LedCode::MicMute,
+        LedCode::RogApp,
+        LedCode::RogFan,
+    ],
+    &[
+        LedCode::Esc,
+        LedCode::F1,
+        LedCode::F2,
+        LedCode::F3,
+        LedCode::F4,
+        LedCode::F5,
+        LedCode::F6,
+        LedCode::F7,
+        LedCode::F8,
+        LedCode::F9,
+        LedCode::F10,
+        LedCode::F11,
+        LedCode::F12,
+        LedCode::Del,
+        LedCode::Pause,
+        LedCode::NumPadPause,
+        LedCode::NumPadPrtSc,
+        LedCode::NumPadHome,
+    ],
+    &[
+        LedCode::Tilde,
+        LedCode::N1,
+        LedCode::N2,
+        LedCode::N3,
+        LedCode::N4,
+        LedCode::N5,
+        LedCode::N6,
+        LedCode::N7,
+        LedCode::N8,
+        LedCode::N9,
+        LedCode::N0,
+        LedCode::Hyphen,
+        LedCode::Equals,
+        LedCode::Backspace,
+        LedCode::Backspace3_1,
+        LedCode::Backspace3_2,
+        LedCode::Backspace3_3,
+        LedCode::Home,
+        LedCode::NumLock,
+        LedCode::Star,
+        LedCode::NumPadDel,
+    ],
+    &[
+        LedCode::Tab,
+        LedCode::Q,
+        LedCode::W,
+        LedCode::E,
+        LedCode::R,
+        LedCode::T,
+        LedCode::Y,
+        LedCode::U,
+        LedCode::I,
+        LedCode::O,
+        LedCode::P,
+        LedCode::LBracket,
+        LedCode::RBracket,
+        LedCode::BackSlash,
+        LedCode::PgUp,
+        LedCode::NumPadPlus,
+    ],
+    &[
+        LedCode::Caps,
+        LedCode::A,
+        LedCode::S,
+        LedCode::D,
+        LedCode::F,
+        LedCode::G,
+        LedCode::H,
+        LedCode::J,
+        LedCode::K,
+        LedCode::L,
+        LedCode::SemiColon,
+        LedCode::Quote,
+        LedCode::Return,
+        LedCode::Return3_1,
+        LedCode::Return3_2,
+        LedCode::Return3_3,
+        LedCode::PgDn,
+    ],
+    &[
+        LedCode::LShift,
+        LedCode::LShift3_1,
+        LedCode::LShift3_2,
+        LedCode::LShift3_3,
+        LedCode::Z,
+        LedCode::X,
+        LedCode::C,
+        LedCode::V,
+        LedCode::B,
+        LedCode::N,
+        LedCode::M,
+        LedCode::Comma,
+        LedCode::Period,
+        LedCode::FwdSlash,
+        LedCode::Rshift,
+        LedCode::Rshift3_1,
+        LedCode::Rshift3_2,
+        LedCode::Rshift3_3,
+        LedCode::Up,
+        LedCode::End,
+        LedCode::NumPadEnter,
+    ],
+    &[
+        LedCode::LCtrl,
+        LedCode::LFn,
+        LedCode::Meta,
+        LedCode::LAlt,
+        LedCode::Spacebar,
+        LedCode::Spacebar5_1,
+        LedCode::Spacebar5_2,
+        LedCode::Spacebar5_3,
+        LedCode::Spacebar5_4,
+        LedCode::Spacebar5_5,
+        LedCode::RAlt,
+        LedCode::PrtSc,
+        LedCode::RFn,
+        LedCode::RCtrl,
+        LedCode::Left,
+        LedCode::Down,
+        LedCode::Right,
+        LedCode::MediaPlay,
+        LedCode::MediaStop,
+        LedCode::MediaPrev,
+        LedCode::MediaNext,
+    ],
+    &[
+        LedCode::LidLogo,
+        LedCode::LidLeft,
+        LedCode::LidRight,
+    ],
+    &[
+        LedCode::LightbarLeft,
+        LedCode::LightbarLeftCorner,
+        LedCode::LightbarLeftBottom,
+        LedCode::LightbarRightBottom,
+        LedCode::LightbarRightCorner,
+        LedCode::LightbarRight,
+    ],
+];
+
+/// Every LED a per-key keyboard could have, as `LedCode::Raw` in packet
+/// order. These are lit one at a time to find the key of each.
+pub fn calibration_leds() -> Vec<LedCode> {
+    let packets = LedUsbPackets::new_per_key();
+    let rows = packets.get_ref();
+    (0..rows.len() as u8)
+        .flat_map(|row| {
+            let width = rows[row as usize].len() as u8;
+            (FIRST_COLUMN..width - 2)
+                .step_by(3)
+                .map(move |col| LedCode::Raw(row, col))
+        })
+        .collect()
+}
+
+/// Per-key packets with only `led` lit
+pub fn lit_packets(led: LedCode, colour: Colour) -> AuraLaptopUsbPackets {
+    let mut packets = LedUsbPackets::new_per_key();
+    packets.set(led, colour.r, colour.g, colour.b);
+    packets.into()
+}
+
+/// The keys found while calibrating, to make the layout of a new model
+#[derive(Debug, Default, Clone)]
+pub struct LayoutCalibration {
+    /// The LED as used in the layout, and the key it's placed as if known
+    found: Vec<(LedCode, Option<LedCode>)>,
+    extra_leds: Vec<ExtraLed>,
+}
+
+impl LayoutCalibration {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that lighting `raw`, from `calibration_leds()`, lit `key`.
+    /// Returns the `LedCode` the layout uses for it, which is `key` if the
+    /// packets already address `key` at `raw`, otherwise `raw` named as
+    /// `key` in the `extra_leds`.
+    pub fn found(&mut self, raw: LedCode, key: LedCode) -> LedCode {
+        self.forget(raw);
+        let led =
+            if LedUsbPackets::packet_index(key, false) == LedUsbPackets::packet_index(raw, false) {
+                key
+            } else {
+                let evdev_key = (0..=u16::from(u8::MAX))
+                    .find(|code| LedCode::for_evdev_key(*code).contains(&key));
+                self.extra_leds.push(ExtraLed {
+                    led: raw,
+                    name: <&str>::from(key).to_owned(),
+                    evdev_key,
+                });
+                raw
+            };
+        self.found.push((led, Some(key)));
+        led
+    }
+
+    /// Record that lighting `raw` lit a key with no `LedCode`, such as the
+    /// ISO `<>` key. It goes on a row of its own at the bottom of the
+    /// layout, to be moved in to place by hand.
+    pub fn found_extra(&mut self, raw: LedCode, name: &str, evdev_key: Option<u16>) {
+        self.forget(raw);
+        self.extra_leds.push(ExtraLed {
+            led: raw,
+            name: name.to_owned(),
+            evdev_key,
+        });
+        self.found.push((raw, None));
+    }
+
+    /// Remove what was found for `raw`, such as to record it again
+    pub fn forget(&mut self, raw: LedCode) {
+        let index = LedUsbPackets::packet_index(raw, false);
+        self.found
+            .retain(|(led, _)| LedUsbPackets::packet_index(*led, false) != index);
+        self.extra_leds.retain(|extra| extra.led != raw);
+    }
+
+    /// How many LEDs have been found
+    pub fn len(&self) -> usize {
+        self.found.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.found.is_empty()
+    }
+
+    /// A layout of the keys found. Each key is a regular 1.0 wide key, so the
+    /// shapes of wider keys need to be added by hand.
+    pub fn layout(&self, locale: &str) -> KeyLayout {
+        let position = |key: LedCode| {
+            ROWS.iter()
+                .enumerate()
+                .find_map(|(row, keys)| keys.iter().position(|k| *k == key).map(|col| (row, col)))
+        };
+        let mut rows = vec![Vec::new(); ROWS.len() + 1];
+        let mut placed: Vec<((usize, usize), LedCode)> = self
+            .found
+            .iter()
+            .map(|(led, key)| {
+                let at = key.and_then(position).unwrap_or((ROWS.len(), usize::MAX));
+                (at, *led)
+            })
+            .collect();
+        placed.sort_by_key(|(at, _)| *at);
+        for ((row, _), led) in placed {
+            rows[row].push(led);
+        }
+        rows.retain(|row| !row.is_empty());
+        let extra_leds = self
+            .extra_leds
+            .iter()
+            .filter(|extra| self.found.iter().any(|(led, _)| *led == extra.led))
+            .cloned()
+            .collect();
+        KeyLayout::from_rows(locale, rows, extra_leds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{calibration_leds, lit_packets, LayoutCalibration};
+    use crate::keyboard::{KeyLayout, LedCode};
+    use crate::Colour;
+
+    #[test]
+    fn calibrate_layout() {
+        let leds = calibration_leds();
+        assert_eq!(leds.len(), 11 * 18);
+        assert_eq!(leds[0], LedCode::Raw(0, 9));
+        assert_eq!(leds[17], LedCode::Raw(0, 60));
+        assert_eq!(leds[18], LedCode::Raw(1, 9));
+
+        let white = Colour {
+            r: 255,
+            g: 255,
+            b: 255,
+        };
+        let packets = lit_packets(LedCode::Raw(5, 24), white);
+        assert_eq!(&packets[5][24..27], &[255, 255, 255]);
+        assert_eq!(packets.iter().flatten().filter(|b| **b == 255).count(), 3);
+
+        let mut calibration = LayoutCalibration::new();
+        // Where the packets already have them
+        assert_eq!(
+            calibration.found(LedCode::Raw(5, 27), LedCode::S),
+            LedCode::S
+        );
+        assert_eq!(
+            calibration.found(LedCode::Raw(5, 24), LedCode::A),
+            LedCode::A
+        );
+        assert_eq!(
+            calibration.found(LedCode::Raw(1, 24), LedCode::Esc),
+            LedCode::Esc
+        );
+        // Somewhere else on this model
+        let tab = LedCode::Raw(3, 51);
+        assert_eq!(calibration.found(tab, LedCode::Tab), tab);
+        let less_than = LedCode::Raw(6, 39);
+        calibration.found_extra(less_than, "<>", Some(86));
+        // Recording an LED again replaces it
+        calibration.found(LedCode::Raw(7, 12), LedCode::M);
+        calibration.found_extra(LedCode::Raw(7, 12), "?", None);
+        calibration.forget(LedCode::Raw(7, 12));
+        assert_eq!(calibration.len(), 5);
+
+        let layout = calibration.layout("ISO");
+        assert_eq!(layout.locale(), "ISO");
+        let rows: Vec<Vec<LedCode>> = layout
+            .rows()
+            .map(|row| row.row().map(|(led, _)| *led).collect())
+            .collect();
+        assert_eq!(rows, vec![
+            vec![LedCode::Esc],
+            vec![tab],
+            vec![
+                LedCode::A,
+                LedCode::S
+            ],
+            vec![less_than],
+        ]);
+        assert_eq!(layout.led_name(tab), "Tab");
+        assert_eq!(layout.leds_for_evdev_key(15), [
+            LedCode::Tab,
+            tab
+        ]);
+        assert_eq!(layout.led_name(less_than), "<>");
+        assert!(
+            layout.led_centre(LedCode::A).unwrap().0 < layout.led_centre(LedCode::S).unwrap().0
+        );
+
+        // The file written can be read back
+        let text = ron::ser::to_string_pretty(&layout, Default::default()).unwrap();
+        let mut read: KeyLayout = ron::from_str(&text).unwrap();
+        assert!(read.build().is_empty());
+        assert_eq!(read.extra_leds(), layout.extra_leds());
+    }
+}
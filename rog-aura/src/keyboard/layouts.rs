@@ -5,7 +5,7 @@ use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::slice::Iter;
 
-use log::warn;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::aura_detection::LedSupportData;
@@ -163,10 +163,68 @@ impl KeyRow {
     }
 }
 
+/// The locale of the layout used when no other is found
+pub const DEFAULT_LOCALE: &str = "US";
+
+/// Locales whose keyboards have the ISO shape, with a tall Enter, a short Left
+/// Shift, and the `<>` key beside it
+const ISO_LOCALES: [&str; 20] = [
+    "BE", "CH", "CZ", "DE", "DK", "EE", "ES", "FI", "FR", "GR", "HU", "IS", "IT", "LT", "NO", "PT",
+    "SE", "SK", "TR", "UK",
+];
+
+/// The locales to try for a layout, in order. A locale is followed by its
+/// physical layout, `ISO` or `JIS`, then `US`. Such as `DE` giving `DE`, `ISO`,
+/// `US`.
+pub fn locale_fallbacks(locale: &str) -> Vec<String> {
+    let locale = locale.trim().to_uppercase();
+    let mut locales = Vec::new();
+    if !locale.is_empty() {
+        locales.push(locale.clone());
+    }
+    if ISO_LOCALES.contains(&locale.as_str()) {
+        locales.push("ISO".to_owned());
+    } else if locale == "JP" {
+        locales.push("JIS".to_owned());
+    }
+    if !locales.iter().any(|l| l == DEFAULT_LOCALE) {
+        locales.push(DEFAULT_LOCALE.to_owned());
+    }
+    locales
+}
+
+/// The layout locale for an XKB layout such as `gb` or `de,us`, as given by
+/// `localectl`. Only the first layout is used.
+pub fn locale_from_xkb(xkb_layout: &str) -> String {
+    let layout = xkb_layout.split(',').next().unwrap_or_default().trim();
+    match layout.to_lowercase().as_str() {
+        "" => DEFAULT_LOCALE.to_owned(),
+        "gb" => "UK".to_owned(),
+        other => other.to_uppercase(),
+    }
+}
+
+/// An LED a layout file names that has no `LedCode` of its own, usually a
+/// `LedCode::Raw`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ExtraLed {
+    pub led: LedCode,
+    /// Shown in place of the `LedCode` name, such as `<>`
+    pub name: String,
+    /// The evdev key code of the key, so that it works with effects reacting
+    /// to key presses
+    #[serde(default)]
+    pub evdev_key: Option<u16>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct KeyLayout {
     /// Localization of this keyboard layout
     locale: String,
+    /// LEDs particular to this layout, such as the ISO `<>` key. These can be
+    /// used in the rows.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extra_leds: Vec<ExtraLed>,
     /// The shapes of keys used
     key_shapes: HashMap<String, KeyShape>,
     /// The rows of keys of this layout
@@ -186,6 +244,9 @@ pub struct KeyLayout {
     /// layout, but different EC features.
     #[serde(skip)]
     advanced_type: AdvancedAuraType,
+    /// The key of each LED as `(x, y, width, height)`, built with the rows
+    #[serde(skip)]
+    rects: HashMap<LedCode, (f32, f32, f32, f32)>,
 }
 
 impl KeyLayout {
@@ -199,30 +260,157 @@ impl KeyLayout {
             ))
         } else {
             let mut data = ron::from_str::<Self>(&buf)?;
+            let unused = data.build();
+            if !unused.is_empty() {
+                warn!("The layout {path:?} had unused shapes {unused:?}",);
+            }
 
-            let mut unused = HashSet::new();
-            for k in data.key_shapes.keys() {
-                unused.insert(k);
+            Ok(data)
+        }
+    }
+
+    /// A layout of rows of regular keys, such as for a new model where the
+    /// shapes of the keys aren't known yet
+    pub(crate) fn from_rows(
+        locale: &str,
+        rows: Vec<Vec<LedCode>>,
+        extra_leds: Vec<ExtraLed>,
+    ) -> Self {
+        let mut layout = Self {
+            locale: locale.to_owned(),
+            extra_leds,
+            key_shapes: HashMap::from([(
+                "regular".to_owned(),
+                KeyShape::new_led(1.0, 1.0, 0.1, 0.1, 0.1, 0.1),
+            )]),
+            key_rows: rows
+                .into_iter()
+                .map(|row| {
+                    KeyRow::new(
+                        0.1,
+                        0.1,
+                        row.into_iter()
+                            .map(|led| (led, "regular".to_owned()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+            basic_modes: Vec::new(),
+            basic_zones: Vec::new(),
+            advanced_type: AdvancedAuraType::PerKey,
+            rects: HashMap::new(),
+        };
+        layout.build();
+        layout
+    }
+
+    /// Merge the key shapes in to the rows and find where each LED is.
+    /// Returns the names of any shapes not used.
+    pub(crate) fn build(&mut self) -> HashSet<String> {
+        let mut unused: HashSet<String> = self.key_shapes.keys().cloned().collect();
+        for row in &mut self.key_rows {
+            row.built_row.clear();
+            for k in &row.row {
+                if let Some(shape) = self.key_shapes.get(&k.1) {
+                    row.built_row.push((k.0, shape.clone()));
+                    unused.remove(&k.1);
+                } else {
+                    warn!("Key {:?} was missing matching shape {}", k.0, k.1);
+                }
             }
+        }
 
-            let rows = &mut data.key_rows;
-            for row in rows {
-                for k in &mut row.row {
-                    if let Some(shape) = data.key_shapes.get(&k.1) {
-                        row.built_row.push((k.0, shape.clone()));
-                        unused.remove(&k.1);
-                    } else {
-                        warn!("Key {:?} was missing matching shape {}", k.0, k.1);
+        self.rects.clear();
+        let mut row_y = 0.0;
+        for row in &self.key_rows {
+            row_y += row.pad_top;
+            let mut x = row.pad_left;
+            for (led, shape) in row.row() {
+                match shape {
+                    KeyShape::Led {
+                        width,
+                        height,
+                        pad_left,
+                        pad_right,
+                        pad_top,
+                        ..
+                    } => {
+                        if !led.is_placeholder() {
+                            let rect = (x + pad_left, row_y + pad_top, *width, *height);
+                            self.rects.entry(*led).or_insert(rect);
+                        }
+                        x += pad_left + width + pad_right;
                     }
+                    KeyShape::Blank { width, .. } => x += width,
                 }
             }
+            row_y += row.height();
+        }
+        unused
+    }
 
-            if !unused.is_empty() {
-                warn!("The layout {path:?} had unused shapes {unused:?}",);
-            }
+    /// The centre of `led` in key units, `(x, y)` from the top left of the
+    /// layout. A key like 'A' is 1.0 wide. `None` if the layout doesn't
+    /// have the LED.
+    pub fn led_centre(&self, led: LedCode) -> Option<(f32, f32)> {
+        self.led_rect(led)
+            .map(|(x, y, width, height)| (x + width / 2.0, y + height / 2.0))
+    }
 
-            Ok(data)
-        }
+    /// Every LED in the layout with its centre, as from `led_centre()`
+    pub fn led_centres(&self) -> impl Iterator<Item = (LedCode, (f32, f32))> + '_ {
+        self.led_rects()
+            .map(|(led, (x, y, width, height))| (led, (x + width / 2.0, y + height / 2.0)))
+    }
+
+    /// The key of `led` as `(x, y, width, height)` in key units, not
+    /// including padding. `None` if the layout doesn't have the LED.
+    pub fn led_rect(&self, led: LedCode) -> Option<(f32, f32, f32, f32)> {
+        self.rects.get(&led).copied()
+    }
+
+    /// Every LED in the layout with its key, as from `led_rect()`
+    pub fn led_rects(&self) -> impl Iterator<Item = (LedCode, (f32, f32, f32, f32))> + '_ {
+        self.rects.iter().map(|(led, rect)| (*led, *rect))
+    }
+
+    /// The smallest and largest LED centres, `((min_x, min_y), (max_x,
+    /// max_y))`. `None` if the layout has no LEDs.
+    pub fn led_bounds(&self) -> Option<((f32, f32), (f32, f32))> {
+        self.led_centres().fold(None, |bounds, (_, (x, y))| {
+            let ((min_x, min_y), (max_x, max_y)) = bounds.unwrap_or(((x, y), (x, y)));
+            Some(((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y))))
+        })
+    }
+
+    /// The locale of the layout file, such as `US` or `ISO`
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    pub fn extra_leds(&self) -> &[ExtraLed] {
+        &self.extra_leds
+    }
+
+    /// The name of `led`, from `extra_leds` if the layout names it
+    pub fn led_name(&self, led: LedCode) -> &str {
+        self.extra_leds
+            .iter()
+            .find(|extra| extra.led == led)
+            .map_or_else(|| led.into(), |extra| extra.name.as_str())
+    }
+
+    /// As `LedCode::for_evdev_key()`, including the `extra_leds` of this
+    /// layout
+    pub fn leds_for_evdev_key(&self, code: u16) -> Vec<LedCode> {
+        let mut leds = LedCode::for_evdev_key(code).to_vec();
+        leds.extend(
+            self.extra_leds
+                .iter()
+                .filter(|extra| extra.evdev_key == Some(code))
+                .map(|extra| extra.led),
+        );
+        leds
     }
 
     pub fn rows(&self) -> Iter<'_, KeyRow> {
@@ -278,20 +466,36 @@ impl KeyLayout {
     }
 
     /// Find a layout matching the name in `LaptopLedData` in the provided dir
-    pub fn find_layout(led_data: LedSupportData, mut data_path: PathBuf) -> Result<Self, Error> {
-        // TODO: locales
+    pub fn find_layout(led_data: LedSupportData, data_path: PathBuf) -> Result<Self, Error> {
+        Self::find_layout_for_locale(led_data, data_path, DEFAULT_LOCALE)
+    }
+
+    /// As `find_layout()`, but for a `locale` such as `UK` or `DE`. If the
+    /// model has no layout for the locale the next of `locale_fallbacks()`
+    /// is tried.
+    pub fn find_layout_for_locale(
+        led_data: LedSupportData,
+        mut data_path: PathBuf,
+        locale: &str,
+    ) -> Result<Self, Error> {
         let layout_name = if led_data.layout_name.is_empty() {
             "ga401q".to_owned() // Need some sort of default here due to ROGCC
                                 // expecting it
         } else {
             led_data.layout_name
         };
-        let layout_file = format!("{layout_name}_US.ron");
         data_path.push("layouts");
-        data_path.push(layout_file);
-        let path = data_path.as_path();
+        let locales = locale_fallbacks(locale);
+        let path = locales
+            .iter()
+            .map(|locale| data_path.join(format!("{layout_name}_{locale}.ron")))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| data_path.join(format!("{layout_name}_{DEFAULT_LOCALE}.ron")));
+        if !path.ends_with(format!("{layout_name}_{}.ron", locales[0])) {
+            info!("No {} layout for {layout_name}, using {path:?}", locales[0]);
+        }
 
-        let mut tmp = KeyLayout::from_file(path)?;
+        let mut tmp = KeyLayout::from_file(&path)?;
         tmp.basic_modes = led_data.basic_modes;
         tmp.basic_zones = led_data.basic_zones;
         tmp.advanced_type = led_data.advanced_type;
@@ -321,8 +525,9 @@ impl KeyLayout {
 
 impl KeyLayout {
     pub fn default_layout() -> Self {
-        Self {
-            locale: "US".to_owned(),
+        let mut layout = Self {
+            locale: DEFAULT_LOCALE.to_owned(),
+            extra_leds: Vec::new(),
             basic_modes: vec![
                 AuraModeNum::Static,
                 AuraModeNum::Breathe,
@@ -423,7 +628,10 @@ impl KeyLayout {
                     (LedCode::RCtrl, "regular".to_owned()),
                 ]),
             ],
-        }
+            rects: HashMap::new(),
+        };
+        layout.build();
+        layout
     }
 }
 
@@ -434,8 +642,9 @@ mod tests {
     use std::io::Read;
     use std::path::PathBuf;
 
-    use crate::aura_detection::LedSupportFile;
-    use crate::keyboard::KeyLayout;
+    use super::{locale_fallbacks, locale_from_xkb, ExtraLed};
+    use crate::aura_detection::{LedSupportData, LedSupportFile};
+    use crate::keyboard::{KeyLayout, LedCode};
 
     #[test]
     fn check_parse_all() {
@@ -492,6 +701,127 @@ mod tests {
         // data).unwrap(); file.write_all(json.as_bytes()).unwrap();
     }
 
+    #[test]
+    fn led_centres() {
+        let layout = KeyLayout::default_layout();
+        let near = |led, (x, y): (f32, f32)| {
+            let (cx, cy) = layout.led_centre(led).unwrap();
+            assert!(
+                (cx - x).abs() < 0.001 && (cy - y).abs() < 0.001,
+                "{led:?} is at ({cx}, {cy})"
+            );
+        };
+        // Row and key padding are both 0.1, keys are 1.0
+        near(LedCode::Esc, (0.7, 0.7));
+        near(LedCode::F1, (1.9, 0.7));
+        near(LedCode::Tilde, (0.7, 2.0));
+        near(LedCode::A, (1.9, 4.6));
+        assert_eq!(layout.led_centre(LedCode::Home), None);
+        assert_eq!(layout.led_rect(LedCode::Esc), Some((0.2, 0.2, 1.0, 1.0)));
+
+        let ((min_x, min_y), (max_x, max_y)) = layout.led_bounds().unwrap();
+        assert!((min_x - 0.7).abs() < 0.001 && (min_y - 0.7).abs() < 0.001);
+        assert!(max_x > 15.0 && max_y > 7.0);
+
+        let mut data_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        data_path.push("data/layouts/g513i-per-key_US.ron");
+        let layout = KeyLayout::from_file(&data_path).unwrap();
+        let (esc, _) = layout.led_centre(LedCode::Esc).unwrap();
+        let (f12, _) = layout.led_centre(LedCode::F12).unwrap();
+        let (_, space) = layout.led_centre(LedCode::Spacebar).unwrap();
+        let (_, n1) = layout.led_centre(LedCode::N1).unwrap();
+        assert!(esc < f12);
+        assert!(n1 < space);
+    }
+
+    #[test]
+    fn layout_locales() {
+        assert_eq!(locale_fallbacks("de"), ["DE", "ISO", "US"]);
+        assert_eq!(locale_fallbacks("UK"), ["UK", "ISO", "US"]);
+        assert_eq!(locale_fallbacks("JP"), ["JP", "JIS", "US"]);
+        assert_eq!(locale_fallbacks("US"), ["US"]);
+        assert_eq!(locale_fallbacks("KR"), ["KR", "US"]);
+        assert_eq!(locale_fallbacks(""), ["US"]);
+        assert_eq!(locale_from_xkb("gb"), "UK");
+        assert_eq!(locale_from_xkb("de,us"), "DE");
+        assert_eq!(locale_from_xkb(""), "US");
+
+        // A model with US and ISO layouts, the ISO one with the `<>` key
+        let dir = std::env::temp_dir().join(format!("rog-aura-locales-{}", std::process::id()));
+        fs::create_dir_all(dir.join("layouts")).unwrap();
+        let us = KeyLayout::default_layout();
+        let mut iso = KeyLayout::default_layout();
+        iso.locale = "ISO".to_owned();
+        let less_than = LedCode::Raw(6, 39);
+        iso.extra_leds.push(ExtraLed {
+            led: less_than,
+            name: "<>".to_owned(),
+            evdev_key: Some(86),
+        });
+        iso.key_rows[4]
+            .row
+            .insert(1, (less_than, "regular".to_owned()));
+        for (layout, file) in [
+            (us, "test_US.ron"),
+            (iso, "test_ISO.ron"),
+        ] {
+            fs::write(
+                dir.join("layouts").join(file),
+                ron::to_string(&layout).unwrap(),
+            )
+            .unwrap();
+        }
+
+        let data = LedSupportData {
+            layout_name: "test".to_owned(),
+            ..Default::default()
+        };
+        let find = |locale| KeyLayout::find_layout_for_locale(data.clone(), dir.clone(), locale);
+        let de = find("DE").unwrap();
+        assert_eq!(de.locale(), "ISO");
+        assert_eq!(de.led_name(less_than), "<>");
+        assert_eq!(de.led_name(LedCode::Esc), "Escape");
+        assert_eq!(de.leds_for_evdev_key(86), [less_than]);
+        assert_eq!(de.leds_for_evdev_key(1), [LedCode::Esc]);
+        let (x, _) = de.led_centre(less_than).unwrap();
+        assert!(x < de.led_centre(LedCode::Z).unwrap().0);
+
+        let us = KeyLayout::find_layout(data.clone(), dir.clone()).unwrap();
+        assert_eq!(us.locale(), "US");
+        assert!(us.leds_for_evdev_key(86).is_empty());
+        assert_eq!(find("KR").unwrap().locale(), "US");
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(find("DE").is_err());
+    }
+
+    #[test]
+    fn shipped_iso_layout() {
+        let data = LedSupportData {
+            layout_name: "g513i-per-key".to_owned(),
+            ..Default::default()
+        };
+        let data_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("data");
+        let de = KeyLayout::find_layout_for_locale(data, data_path, "DE").unwrap();
+        assert_eq!(de.locale(), "ISO");
+        let less_than = LedCode::Raw(6, 39);
+        assert_eq!(de.led_name(less_than), "<>");
+        assert_eq!(de.leds_for_evdev_key(86), [less_than]);
+        let (x, _) = de.led_centre(less_than).unwrap();
+        assert!(de.led_centre(LedCode::LShift).unwrap().0 < x);
+        assert!(x < de.led_centre(LedCode::Z).unwrap().0);
+
+        // The tall Return spans the rows of `]` and `'`, with `#` beside it
+        let (_, top, ..) = de.led_rect(LedCode::RBracket).unwrap();
+        let (quote_x, quote_y, _, height) = de.led_rect(LedCode::Quote).unwrap();
+        let (ret_x, ret_y, _, ret_height) = de.led_rect(LedCode::Return).unwrap();
+        assert!((ret_y - top).abs() < 0.001);
+        assert!((ret_y + ret_height - (quote_y + height)).abs() < 0.001);
+        let (hash_x, hash_y, ..) = de.led_rect(LedCode::BackSlash).unwrap();
+        assert!((hash_y - quote_y).abs() < 0.001);
+        assert!(quote_x < hash_x && hash_x < ret_x);
+    }
+
     #[test]
     fn check_layout_file_links() {
         const DATA_DIR: &str = env!("CARGO_MANIFEST_DIR");
@@ -9,6 +9,10 @@ pub use layouts::*;
 mod power;
 pub use power::*;
 
+/// Finding the key of each LED to make layouts for new models
+mod calibrate;
+pub use calibrate::*;
+
 #[derive(Debug, Clone, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
 pub enum AdvancedAuraType {
     /// A `None` will apply the effect to the whole keyboard via basic-static
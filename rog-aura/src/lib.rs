@@ -18,6 +18,7 @@ pub use builtin_modes::*;
 
 /// Helper for detecting what is available
 pub mod aura_detection;
+pub mod dissect;
 pub mod error;
 pub mod usb;
 
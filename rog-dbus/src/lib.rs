@@ -1,8 +1,14 @@
 pub use asusd::{DBUS_IFACE, DBUS_NAME, DBUS_PATH};
 use zbus::proxy::ProxyImpl;
 
+pub mod asus_armoury;
 pub mod scsi_aura;
+pub mod zbus_asusd;
 pub mod zbus_aura;
+pub mod zbus_backlight;
+pub mod zbus_platform;
+pub mod zbus_scenes;
+pub mod zbus_schedules;
 pub mod zbus_slash;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,28 @@
+//! # `DBus` interface proxy for: `xyz.ljones.Asusd`
+//!
+//! The device registry, listing each device asusd controls and signalling
+//! when one is added or removed.
+
+pub use asusd::device_registry::{DeviceInfo, DeviceKind};
+use zbus::proxy;
+
+#[proxy(
+    interface = "xyz.ljones.Asusd",
+    default_service = "xyz.ljones.Asusd",
+    default_path = "/xyz/ljones/Daemon"
+)]
+pub trait Asusd {
+    #[zbus(property)]
+    fn version(&self) -> zbus::Result<String>;
+
+    /// Devices method
+    fn devices(&self) -> zbus::Result<Vec<DeviceInfo>>;
+
+    /// DeviceAdded signal
+    #[zbus(signal)]
+    fn device_added(&self, device: DeviceInfo) -> zbus::Result<()>;
+
+    /// DeviceRemoved signal
+    #[zbus(signal)]
+    fn device_removed(&self, device: DeviceInfo) -> zbus::Result<()>;
+}
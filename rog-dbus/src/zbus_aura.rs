@@ -23,7 +23,9 @@
 use std::collections::BTreeMap;
 
 use rog_aura::keyboard::{AuraLaptopUsbPackets, LaptopAuraPower};
-use rog_aura::{AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, LedBrightness, PowerZones};
+use rog_aura::{
+    AuraDeviceType, AuraEffect, AuraModeNum, AuraZone, Colour, LedBrightness, PowerZones,
+};
 use zbus::blocking::Connection;
 use zbus::{proxy, Result};
 
@@ -38,6 +40,10 @@ pub trait Aura {
     /// AllModeData method
     fn all_mode_data(&self) -> zbus::Result<BTreeMap<AuraModeNum, AuraEffect>>;
 
+    /// Flash method
+    fn flash(&self, colour: Colour, count: u32, period_ms: u32, zone: AuraZone)
+        -> zbus::Result<()>;
+
     /// DirectAddressingRaw method
     fn direct_addressing_raw(&self, data: AuraLaptopUsbPackets) -> zbus::Result<()>;
 
@@ -69,6 +75,12 @@ pub trait Aura {
     #[zbus(property)]
     fn set_led_power(&self, value: LaptopAuraPower) -> zbus::Result<()>;
 
+    /// TransitionTime property
+    #[zbus(property)]
+    fn transition_time(&self) -> zbus::Result<u32>;
+    #[zbus(property)]
+    fn set_transition_time(&self, value: u32) -> zbus::Result<()>;
+
     /// SupportedBrightness property
     #[zbus(property)]
     fn supported_brightness(&self) -> zbus::Result<Vec<LedBrightness>>;
@@ -0,0 +1,28 @@
+//! # `DBus` interface proxy for: `xyz.ljones.Scenes`
+//!
+//! Named lighting scenes covering every Aura, Slash and SCSI device.
+
+use zbus::proxy;
+
+#[proxy(
+    interface = "xyz.ljones.Scenes",
+    default_service = "xyz.ljones.Asusd",
+    default_path = "/xyz/ljones/Daemon"
+)]
+pub trait Scenes {
+    /// ListScenes method
+    fn list_scenes(&self) -> zbus::Result<Vec<String>>;
+
+    /// SaveScene method
+    fn save_scene(&self, name: &str) -> zbus::Result<()>;
+
+    /// ApplyScene method
+    fn apply_scene(&self, name: &str) -> zbus::Result<()>;
+
+    /// DeleteScene method
+    fn delete_scene(&self, name: &str) -> zbus::Result<()>;
+
+    /// SceneApplied signal
+    #[zbus(signal)]
+    fn scene_applied(&self, name: &str) -> zbus::Result<()>;
+}
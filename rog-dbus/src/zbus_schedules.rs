@@ -0,0 +1,27 @@
+//! # `DBus` interface proxy for: `xyz.ljones.Schedules`
+//!
+//! Time-of-day rules applying scenes, brightness and effects.
+
+use std::collections::BTreeMap;
+
+pub use asusd::schedules::{RuleBrightness, ScheduleRule, TimeOfDay};
+use zbus::proxy;
+
+#[proxy(
+    interface = "xyz.ljones.Schedules",
+    default_service = "xyz.ljones.Asusd",
+    default_path = "/xyz/ljones/Daemon"
+)]
+pub trait Schedules {
+    /// ListRules method
+    fn list_rules(&self) -> zbus::Result<BTreeMap<String, ScheduleRule>>;
+
+    /// ActiveRule method
+    fn active_rule(&self) -> zbus::Result<String>;
+
+    /// SetRule method
+    fn set_rule(&self, name: &str, rule: &ScheduleRule) -> zbus::Result<()>;
+
+    /// DeleteRule method
+    fn delete_rule(&self, name: &str) -> zbus::Result<()>;
+}
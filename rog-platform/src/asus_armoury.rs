@@ -7,10 +7,11 @@ use serde::{Deserialize, Serialize};
 use zbus::zvariant::{OwnedValue, Type, Value};
 
 use crate::error::PlatformError;
+use crate::DEFAULT_ROOT;
 
-/// The root sysfs path. This path should never change in kernel so
-/// using udev to find it *should* not be required.
-const BASE_DIR: &str = "/sys/class/firmware-attributes/asus-armoury/attributes/";
+/// The sysfs path, relative to the root. This path should never change in
+/// kernel so using udev to find it *should* not be required.
+const BASE_DIR: &str = "sys/class/firmware-attributes/asus-armoury/attributes/";
 
 fn read_i32(path: &Path) -> Result<i32, PlatformError> {
     if let Ok(mut f) = File::open(path) {
@@ -95,7 +96,7 @@ impl Attribute {
             _ => return Err(PlatformError::InvalidValue),
         };
 
-        let mut file = OpenOptions::new().write(true).open(&path)?;
+        let mut file = OpenOptions::new().write(true).truncate(true).open(&path)?;
         file.write_all(value_str.as_bytes())?;
         Ok(())
     }
@@ -124,6 +125,47 @@ impl Attribute {
         &self.scalar_increment
     }
 
+    /// Check an integer value against `min_value`, `max_value`,
+    /// `scalar_increment` and `possible_values`, where the attribute has them
+    pub fn check_value(&self, value: i32) -> Result<(), PlatformError> {
+        let name = self.name();
+        if let AttrValue::Integer(min) = self.min_value {
+            if value < min {
+                return Err(PlatformError::OutOfRange(format!(
+                    "{name}: {value} is below the minimum of {min}"
+                )));
+            }
+        }
+        if let AttrValue::Integer(max) = self.max_value {
+            if value > max {
+                return Err(PlatformError::OutOfRange(format!(
+                    "{name}: {value} is above the maximum of {max}"
+                )));
+            }
+        }
+        if let AttrValue::Integer(step) = self.scalar_increment {
+            let base = match self.min_value {
+                AttrValue::Integer(min) => min,
+                _ => 0,
+            };
+            if step > 1 && (value - base) % step != 0 {
+                return Err(PlatformError::OutOfRange(format!(
+                    "{name}: {value} is not a multiple of {step} from {base}"
+                )));
+            }
+        }
+        match &self.possible_values {
+            AttrValue::EnumInt(values) if !values.contains(&value) => Err(
+                PlatformError::OutOfRange(format!("{name}: {value} is not one of {values:?}")),
+            ),
+            AttrValue::Integer(only) if *only != value => Err(PlatformError::OutOfRange(format!(
+                "{name}: {value} is not {only}"
+            ))),
+            AttrValue::String(_) | AttrValue::EnumStr(_) => Err(PlatformError::InvalidValue),
+            _ => Ok(()),
+        }
+    }
+
     /// Read all the immutable values to struct data. These should *never*
     /// change, if they do then it is possibly a driver issue - although this is
     /// subject to `firmware_attributes` class changes in kernel.
@@ -200,8 +242,13 @@ pub struct FirmwareAttributes {
 #[allow(clippy::new_without_default)]
 impl FirmwareAttributes {
     pub fn new() -> Self {
+        Self::with_root(Path::new(DEFAULT_ROOT))
+    }
+
+    /// As `new()`, but read the attributes under `root` instead of `/`
+    pub fn with_root(root: &Path) -> Self {
         let mut attrs = Vec::new();
-        if let Ok(dir) = read_dir(BASE_DIR) {
+        if let Ok(dir) = read_dir(root.join(BASE_DIR)) {
             for entry in dir.flatten() {
                 let base_path = entry.path();
                 let name = base_path.file_name().unwrap().to_string_lossy().to_string();
@@ -403,6 +450,60 @@ impl From<FirmwareAttribute> for &str {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fixture::Fixture;
+
+    const ATTRS: &str = "sys/class/firmware-attributes/asus-armoury/attributes";
+
+    #[test]
+    fn fixture_attributes() {
+        let fix = Fixture::new("armoury");
+        fix.attr(&format!("{ATTRS}/pending_reboot/current_value"), "0\n")
+            .attr(&format!("{ATTRS}/ppt_pl1_spl/current_value"), "80\n")
+            .attr(&format!("{ATTRS}/ppt_pl1_spl/default_value"), "70\n")
+            .attr(&format!("{ATTRS}/ppt_pl1_spl/min_value"), "15\n")
+            .attr(&format!("{ATTRS}/ppt_pl1_spl/max_value"), "120\n")
+            .attr(&format!("{ATTRS}/ppt_pl1_spl/scalar_increment"), "1\n")
+            .attr(
+                &format!("{ATTRS}/ppt_pl1_spl/display_name"),
+                "Set the CPU slow package limit\n",
+            )
+            .attr(&format!("{ATTRS}/boot_sound/current_value"), "1\n")
+            .attr(&format!("{ATTRS}/boot_sound/possible_values"), "0;1\n");
+
+        let mut attrs = FirmwareAttributes::with_root(fix.root());
+        assert_eq!(attrs.attributes().len(), 2);
+
+        let ppt = attrs.ppt_pl1_spl().unwrap();
+        assert_eq!(ppt.help(), "Set the CPU slow package limit");
+        assert_eq!(ppt.current_value().unwrap(), AttrValue::Integer(80));
+        assert_eq!(ppt.default_value(), &AttrValue::Integer(70));
+        assert_eq!(ppt.min_value(), &AttrValue::Integer(15));
+        assert_eq!(ppt.max_value(), &AttrValue::Integer(120));
+        assert_eq!(ppt.scalar_increment(), &AttrValue::Integer(1));
+
+        let mut watch = ppt.get_watcher("current_value").unwrap();
+        ppt.set_current_value(&AttrValue::Integer(100)).unwrap();
+        let mut buffer = [0; 32];
+        assert!(watch.read_events_blocking(&mut buffer).is_ok());
+        assert_eq!(ppt.current_value().unwrap(), AttrValue::Integer(100));
+        ppt.restore_default().unwrap();
+        assert_eq!(ppt.current_value().unwrap(), AttrValue::Integer(70));
+        assert!(ppt.get_watcher("no_such_attr").is_err());
+        assert!(ppt.check_value(15).is_ok());
+        assert!(ppt.check_value(120).is_ok());
+        assert!(ppt.check_value(14).is_err());
+        assert!(ppt.check_value(121).is_err());
+
+        let boot_sound = attrs.boot_sound_mut().unwrap();
+        assert_eq!(
+            boot_sound.possible_values(),
+            &AttrValue::EnumInt(vec![0, 1])
+        );
+        assert!(boot_sound.check_value(1).is_ok());
+        assert!(boot_sound.check_value(2).is_err());
+        assert_eq!(boot_sound.min_value(), &AttrValue::None);
+        assert!(attrs.dgpu_disable().is_none());
+    }
 
     #[test]
     #[ignore = "Can't check in docker env"]
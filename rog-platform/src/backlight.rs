@@ -1,9 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use log::{info, warn};
+use log::info;
 
 use crate::error::{PlatformError, Result};
-use crate::{attr_num, to_device};
+use crate::{attr_num, enumerate, DEFAULT_ROOT};
 
 /// The "backlight" device provides access to screen brightness control
 #[derive(Debug, PartialEq, Eq, PartialOrd, Clone)]
@@ -26,23 +26,16 @@ impl Backlight {
     attr_num!("bl_power", path, i32);
 
     pub fn new(device_type: BacklightType) -> Result<Self> {
-        let mut enumerator = udev::Enumerator::new().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("enumerator failed".into(), err)
-        })?;
-        enumerator.match_subsystem("backlight").map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("match_subsystem failed".into(), err)
-        })?;
-
-        for device in enumerator.scan_devices().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("scan_devices failed".into(), err)
-        })? {
+        Self::with_root(Path::new(DEFAULT_ROOT), device_type)
+    }
+
+    /// As `new()`, but find the device under `root` instead of `/`
+    pub fn with_root(root: &Path, device_type: BacklightType) -> Result<Self> {
+        for device in enumerate(root, "backlight")? {
             info!("Backlight: Checking {:?}", device.syspath());
             match device_type {
                 BacklightType::Primary => {
-                    if device.sysname().to_string_lossy() == "intel_backlight" {
+                    if device.sysname() == "intel_backlight" {
                         info!("Found primary backlight at {:?}", device.sysname());
                         return Ok(Self {
                             path: device.syspath().to_path_buf(),
@@ -51,7 +44,7 @@ impl Backlight {
                     }
                 }
                 BacklightType::Screenpad => {
-                    let name = device.sysname().to_string_lossy();
+                    let name = device.sysname();
                     if name == "asus_screenpad" || name == "asus_screenpad_backlight" {
                         info!("Found screenpad backlight at {:?}", device.sysname());
                         return Ok(Self {
@@ -73,3 +66,42 @@ impl Backlight {
         &self.device_type
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Backlight, BacklightType};
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn find_primary_and_screenpad() {
+        let fix = Fixture::new("backlight");
+        fix.attr("sys/class/backlight/intel_backlight/brightness", "1200\n")
+            .attr(
+                "sys/class/backlight/intel_backlight/max_brightness",
+                "19393\n",
+            )
+            .attr("sys/class/backlight/asus_screenpad/brightness", "100\n")
+            .attr("sys/class/backlight/asus_screenpad/max_brightness", "255\n")
+            .attr("sys/class/backlight/asus_screenpad/bl_power", "0\n");
+
+        let primary = Backlight::with_root(fix.root(), BacklightType::Primary).unwrap();
+        assert_eq!(primary.device_type(), &BacklightType::Primary);
+        assert_eq!(primary.get_max_brightness().unwrap(), 19393);
+        assert!(!primary.has_bl_power());
+        assert!(primary.monitor_bl_power().is_err());
+
+        let screenpad = Backlight::with_root(fix.root(), BacklightType::Screenpad).unwrap();
+        assert_eq!(screenpad.get_brightness().unwrap(), 100);
+        screenpad.set_bl_power(4).unwrap();
+        assert_eq!(fix.read("sys/class/backlight/asus_screenpad/bl_power"), "4");
+        // Make sure only the screenpad was written to
+        assert_eq!(primary.get_brightness().unwrap(), 1200);
+    }
+
+    #[test]
+    fn no_screenpad() {
+        let fix = Fixture::new("backlight_none");
+        fix.attr("sys/class/backlight/intel_backlight/brightness", "1");
+        assert!(Backlight::with_root(fix.root(), BacklightType::Screenpad).is_err());
+    }
+}
@@ -1,12 +1,12 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use log::{info, warn};
+use log::info;
 use serde::{Deserialize, Serialize};
 use zbus::zvariant::{OwnedValue, Type, Value};
 
 use crate::error::{PlatformError, Result};
 use crate::platform::PlatformProfile;
-use crate::{read_attr_string, to_device};
+use crate::{enumerate, read_attr_string, write_attr_string, DEFAULT_ROOT};
 
 const ATTR_AVAILABLE_GOVERNORS: &str = "cpufreq/scaling_available_governors";
 const ATTR_GOVERNOR: &str = "cpufreq/scaling_governor";
@@ -23,21 +23,14 @@ pub struct CPUControl {
 
 impl CPUControl {
     pub fn new() -> Result<Self> {
-        let mut enumerator = udev::Enumerator::new().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("enumerator failed".into(), err)
-        })?;
-        enumerator.match_subsystem("cpu").map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("match_subsystem failed".into(), err)
-        })?;
+        Self::with_root(Path::new(DEFAULT_ROOT))
+    }
 
+    /// As `new()`, but find the devices under `root` instead of `/`
+    pub fn with_root(root: &Path) -> Result<Self> {
         let mut supported = false;
         let mut cpu = CPUControl { paths: Vec::new() };
-        for device in enumerator.scan_devices().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("CPU: scan_devices failed".into(), err)
-        })? {
+        for device in enumerate(root, "cpu")? {
             if !supported {
                 info!(
                     "Found CPU support at {:?}, checking supported items",
@@ -85,7 +78,7 @@ impl CPUControl {
 
     pub fn get_governor(&self) -> Result<CPUGovernor> {
         if let Some(path) = self.paths.first() {
-            let s = read_attr_string(&to_device(path)?, ATTR_GOVERNOR)?;
+            let s = read_attr_string(path, ATTR_GOVERNOR)?;
             Ok(s.as_str().into())
             // TODO: check cpu are sync
         } else {
@@ -95,7 +88,7 @@ impl CPUControl {
 
     pub fn get_available_governors(&self) -> Result<Vec<CPUGovernor>> {
         if let Some(path) = self.paths.first() {
-            read_attr_string(&to_device(path)?, ATTR_AVAILABLE_GOVERNORS)
+            read_attr_string(path, ATTR_AVAILABLE_GOVERNORS)
                 .map(|s| s.split_whitespace().map(|s| s.into()).collect())
             // TODO: check cpu are sync
         } else {
@@ -108,15 +101,14 @@ impl CPUControl {
             return Err(PlatformError::CPU(format!("{gov:?} is not available")));
         }
         for path in &self.paths {
-            let mut dev = to_device(path)?;
-            dev.set_attribute_value(ATTR_AVAILABLE_GOVERNORS, String::from(gov))?;
+            write_attr_string(path, ATTR_GOVERNOR, &String::from(gov))?;
         }
         Ok(())
     }
 
     pub fn get_epp(&self) -> Result<CPUEPP> {
         if let Some(path) = self.paths.first() {
-            let s = read_attr_string(&to_device(path)?, ATTR_EPP)?;
+            let s = read_attr_string(path, ATTR_EPP)?;
             Ok(s.as_str().into())
             // TODO: check cpu are sync
         } else {
@@ -126,7 +118,7 @@ impl CPUControl {
 
     pub fn get_available_epp(&self) -> Result<Vec<CPUEPP>> {
         if let Some(path) = self.paths.first() {
-            read_attr_string(&to_device(path)?, ATTR_AVAILABLE_EPP)
+            read_attr_string(path, ATTR_AVAILABLE_EPP)
                 .map(|s| s.split_whitespace().map(|s| s.into()).collect())
             // TODO: check cpu are sync
         } else {
@@ -139,8 +131,7 @@ impl CPUControl {
             return Err(PlatformError::CPU(format!("{epp:?} is not available")));
         }
         for path in &self.paths {
-            let mut dev = to_device(path)?;
-            dev.set_attribute_value(ATTR_EPP, String::from(epp))?;
+            write_attr_string(path, ATTR_EPP, &String::from(epp))?;
         }
         Ok(())
     }
@@ -261,6 +252,81 @@ impl From<CPUEPP> for i32 {
 mod tests {
     use super::CPUControl;
     use crate::cpu::{CPUGovernor, CPUEPP};
+    use crate::fixture::Fixture;
+
+    fn cpu_fixture(name: &str, cpus: usize) -> Fixture {
+        let fix = Fixture::new(name);
+        for n in 0..cpus {
+            let cpufreq = format!("sys/bus/cpu/devices/cpu{n}/cpufreq");
+            fix.attr(
+                &format!("{cpufreq}/scaling_available_governors"),
+                "performance powersave\n",
+            )
+            .attr(&format!("{cpufreq}/scaling_governor"), "powersave\n")
+            .attr(
+                &format!("{cpufreq}/energy_performance_available_preferences"),
+                "default performance balance_performance balance_power power \n",
+            )
+            .attr(
+                &format!("{cpufreq}/energy_performance_preference"),
+                "balance_performance\n",
+            );
+        }
+        fix
+    }
+
+    #[test]
+    fn cpu_fixture_governor() {
+        let fix = cpu_fixture("cpu_governor", 2);
+        let cpu = CPUControl::with_root(fix.root()).unwrap();
+        assert_eq!(cpu.get_governor().unwrap(), CPUGovernor::Powersave);
+        assert_eq!(cpu.get_available_governors().unwrap(), vec![
+            CPUGovernor::Performance,
+            CPUGovernor::Powersave
+        ]);
+
+        cpu.set_governor(CPUGovernor::Performance).unwrap();
+        for n in 0..2 {
+            let cpufreq = format!("sys/bus/cpu/devices/cpu{n}/cpufreq");
+            assert_eq!(
+                fix.read(&format!("{cpufreq}/scaling_governor")),
+                "performance"
+            );
+            assert_eq!(
+                fix.read(&format!("{cpufreq}/scaling_available_governors")),
+                "performance powersave\n"
+            );
+        }
+        assert!(cpu.set_governor(CPUGovernor::BadValue).is_err());
+    }
+
+    #[test]
+    fn cpu_fixture_epp() {
+        let fix = cpu_fixture("cpu_epp", 1);
+        let cpu = CPUControl::with_root(fix.root()).unwrap();
+        assert_eq!(cpu.get_epp().unwrap(), CPUEPP::BalancePerformance);
+        assert_eq!(cpu.get_available_epp().unwrap().len(), 5);
+
+        cpu.set_epp(CPUEPP::Power).unwrap();
+        assert_eq!(
+            fix.read("sys/bus/cpu/devices/cpu0/cpufreq/energy_performance_preference"),
+            "power"
+        );
+    }
+
+    #[test]
+    fn cpu_fixture_missing_epp() {
+        let fix = Fixture::new("cpu_no_epp");
+        fix.attr(
+            "sys/bus/cpu/devices/cpu0/cpufreq/scaling_available_governors",
+            "performance powersave",
+        )
+        .attr(
+            "sys/bus/cpu/devices/cpu0/cpufreq/scaling_governor",
+            "powersave",
+        );
+        assert!(CPUControl::with_root(fix.root()).is_err());
+    }
 
     #[test]
     #[ignore = "Can't run this in a docker image"]
@@ -20,6 +20,7 @@ pub enum PlatformError {
     IoPath(String, std::io::Error),
     Io(std::io::Error),
     InvalidValue,
+    OutOfRange(String),
     NoAuraKeyboard,
     NoAuraNode,
     CPU(String),
@@ -42,6 +43,7 @@ impl fmt::Display for PlatformError {
             PlatformError::InvalidValue => {
                 write!(f, "The input value did not match the attribute value type")
             }
+            PlatformError::OutOfRange(deets) => write!(f, "Value out of range: {}", deets),
             PlatformError::MissingFunction(deets) => write!(f, "Missing functionality: {}", deets),
             PlatformError::MissingLedBrightNode(path, error) => write!(
                 f,
@@ -76,6 +78,9 @@ impl From<PlatformError> for FdoErr {
         log::error!("PlatformError: got: {error}");
         match error {
             PlatformError::NotSupported => FdoErr::NotSupported("".to_owned()),
+            PlatformError::InvalidValue | PlatformError::OutOfRange(_) => {
+                FdoErr::InvalidArgs(format!("{error}"))
+            }
             _ => FdoErr::Failed(format!("Failed with {error}")),
         }
     }
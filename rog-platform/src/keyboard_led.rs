@@ -1,9 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use log::{info, warn};
+use log::info;
 
 use crate::error::{PlatformError, Result};
-use crate::{attr_num, has_attr, set_attr_u8_array, to_device};
+use crate::{attr_num, enumerate, has_attr, set_attr_u8_array, watch_attr, DEFAULT_ROOT};
 
 /// The sysfs control for backlight levels. This is only for the 3-step
 /// backlight setting, and for TUF laptops. It is not a hard requirement
@@ -16,6 +16,15 @@ pub struct KeyboardBacklight {
 impl KeyboardBacklight {
     attr_num!("brightness", path, u8);
 
+    has_attr!(
+        /// Notified by the kernel when the brightness is changed by hardware,
+        /// such as the Fn keys
+        "brightness_hw_changed"
+        path
+    );
+
+    watch_attr!("brightness_hw_changed" path);
+
     has_attr!("kbd_rgb_mode" path);
 
     set_attr_u8_array!(
@@ -33,21 +42,13 @@ impl KeyboardBacklight {
     );
 
     pub fn new() -> Result<Self> {
-        let mut enumerator = udev::Enumerator::new().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("enumerator failed".into(), err)
-        })?;
-
-        enumerator.match_subsystem("leds").map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("match_subsystem failed".into(), err)
-        })?;
-
-        for device in enumerator.scan_devices().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("scan_devices failed".into(), err)
-        })? {
-            let sys = device.sysname().to_string_lossy();
+        Self::with_root(Path::new(DEFAULT_ROOT))
+    }
+
+    /// As `new()`, but find the device under `root` instead of `/`
+    pub fn with_root(root: &Path) -> Result<Self> {
+        for device in enumerate(root, "leds")? {
+            let sys = device.sysname();
             if sys.contains("kbd_backlight") || sys.contains("ally:rgb:gamepad") {
                 info!("Found keyboard LED controls at {:?}", device.sysname());
                 return Ok(Self {
@@ -60,3 +61,66 @@ impl KeyboardBacklight {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::KeyboardBacklight;
+    use crate::fixture::Fixture;
+
+    const LED: &str = "sys/class/leds/asus::kbd_backlight";
+
+    #[test]
+    fn find_and_control() {
+        let fix = Fixture::new("kbd_led");
+        fix.attr("sys/class/leds/input3::capslock/brightness", "0")
+            .attr(&format!("{LED}/brightness"), "2\n")
+            .attr(&format!("{LED}/kbd_rgb_mode"), "");
+
+        let led = KeyboardBacklight::with_root(fix.root()).unwrap();
+        assert!(led.has_brightness());
+        assert!(led.has_kbd_rgb_mode());
+        assert!(!led.has_kbd_rgb_state());
+        assert!(!led.has_brightness_hw_changed());
+        assert_eq!(led.get_brightness().unwrap(), 2);
+
+        led.set_brightness(3).unwrap();
+        assert_eq!(fix.read(&format!("{LED}/brightness")), "3");
+        led.set_kbd_rgb_mode(&[
+            1, 0, 255, 0, 0, 0,
+        ])
+        .unwrap();
+        assert_eq!(fix.read(&format!("{LED}/kbd_rgb_mode")), "1 0 255 0 0 0");
+        // sysfs can't create attributes, neither should writes to a fixture
+        assert!(led.set_kbd_rgb_state(&[1, 1, 1, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn monitor_brightness() {
+        let fix = Fixture::new("kbd_led_monitor");
+        fix.attr(&format!("{LED}/brightness"), "0");
+        let led = KeyboardBacklight::with_root(fix.root()).unwrap();
+
+        let mut watch = led.monitor_brightness().unwrap();
+        led.set_brightness(1).unwrap();
+        let mut buffer = [0; 32];
+        let events = watch.read_events_blocking(&mut buffer).unwrap();
+        assert!(events
+            .into_iter()
+            .any(|e| e.mask.contains(inotify::EventMask::MODIFY)));
+
+        fix.attr(&format!("{LED}/brightness_hw_changed"), "0");
+        let mut watch = led.monitor_brightness_hw_changed().unwrap();
+        fix.attr(&format!("{LED}/brightness_hw_changed"), "3");
+        let events = watch.read_events_blocking(&mut buffer).unwrap();
+        assert!(events
+            .into_iter()
+            .any(|e| e.mask.contains(inotify::EventMask::MODIFY)));
+    }
+
+    #[test]
+    fn not_found() {
+        let fix = Fixture::new("kbd_led_none");
+        fix.attr("sys/class/leds/input3::capslock/brightness", "0");
+        assert!(KeyboardBacklight::with_root(fix.root()).is_err());
+    }
+}
@@ -8,37 +8,113 @@ pub mod error;
 pub mod hid_raw;
 pub mod keyboard_led;
 pub(crate) mod macros;
+pub mod metrics;
 pub mod platform;
 pub mod power;
 pub mod usb_raw;
 
-use std::path::Path;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 use error::{PlatformError, Result};
 use log::warn;
 use platform::PlatformProfile;
-use udev::Device;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-pub(crate) fn to_device(sys_path: &Path) -> Result<Device> {
-    Device::from_syspath(sys_path)
-        .map_err(|e| PlatformError::Udev("Couldn't transform syspath to device".to_owned(), e))
+/// The real root directory that sysfs is found under. Every device type also
+/// has a `with_root()` constructor which takes an alternate root instead, e.g,
+/// a tempdir tree of fake attribute files for testing.
+pub const DEFAULT_ROOT: &str = "/";
+
+/// A device found during enumeration
+#[derive(Debug, Clone)]
+pub(crate) struct SysDevice {
+    syspath: PathBuf,
+    sysname: String,
 }
 
-pub fn has_attr(device: &Device, attr_name: &str) -> bool {
-    for attr in device.attributes() {
-        if attr.name() == attr_name {
-            return true;
-        }
+impl SysDevice {
+    pub(crate) fn syspath(&self) -> &Path {
+        &self.syspath
+    }
+
+    pub(crate) fn sysname(&self) -> &str {
+        &self.sysname
+    }
+
+    pub(crate) fn attribute_value(&self, attr_name: &str) -> Option<String> {
+        read_attr(&self.syspath, attr_name).ok()
+    }
+}
+
+/// Find all devices in `subsystem`. Under the real root this uses udev. Under
+/// any other root there is no udev database, so the devices are instead the
+/// directories in `<root>/sys/class/<subsystem>/`, or in
+/// `<root>/sys/bus/<subsystem>/devices/` if there is no such class.
+pub(crate) fn enumerate(root: &Path, subsystem: &str) -> Result<Vec<SysDevice>> {
+    if root == Path::new(DEFAULT_ROOT) {
+        let mut enumerator = udev::Enumerator::new().map_err(|err| {
+            warn!("{}", err);
+            PlatformError::Udev("enumerator failed".into(), err)
+        })?;
+        enumerator.match_subsystem(subsystem).map_err(|err| {
+            warn!("{}", err);
+            PlatformError::Udev("match_subsystem failed".into(), err)
+        })?;
+        let devices = enumerator.scan_devices().map_err(|err| {
+            warn!("{}", err);
+            PlatformError::Udev("scan_devices failed".into(), err)
+        })?;
+        return Ok(devices
+            .map(|device| SysDevice {
+                syspath: device.syspath().to_path_buf(),
+                sysname: device.sysname().to_string_lossy().to_string(),
+            })
+            .collect());
+    }
+
+    let mut dir = root.join("sys/class").join(subsystem);
+    if !dir.is_dir() {
+        dir = root.join("sys/bus").join(subsystem).join("devices");
     }
-    false
+    let mut devices: Vec<SysDevice> = fs::read_dir(&dir)
+        .map_err(|e| PlatformError::Path(dir.to_string_lossy().to_string(), e))?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| SysDevice {
+            syspath: entry.path(),
+            sysname: entry.file_name().to_string_lossy().to_string(),
+        })
+        .collect();
+    devices.sort_by(|a, b| a.sysname.cmp(&b.sysname));
+    Ok(devices)
+}
+
+/// Read an attribute file, with the trailing newline removed as udev does
+fn read_attr(sys_path: &Path, attr_name: &str) -> std::io::Result<String> {
+    let value = fs::read_to_string(sys_path.join(attr_name))?;
+    Ok(value.trim_end_matches('\n').to_string())
+}
+
+/// Write an attribute file. The file must already exist, sysfs does not allow
+/// creating new attributes and neither should a fixture tree.
+fn write_attr(sys_path: &Path, attr_name: &str, value: &str) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(sys_path.join(attr_name))?;
+    file.write_all(value.as_bytes())
+}
+
+pub fn has_attr(sys_path: &Path, attr_name: &str) -> bool {
+    sys_path.join(attr_name).is_file()
 }
 
-pub fn read_attr_bool(device: &Device, attr_name: &str) -> Result<bool> {
-    if let Some(value) = device.attribute_value(attr_name) {
-        let tmp = value.to_string_lossy();
-        if tmp.trim() == "0" {
+pub fn read_attr_bool(sys_path: &Path, attr_name: &str) -> Result<bool> {
+    if let Ok(value) = read_attr(sys_path, attr_name) {
+        if value.trim() == "0" {
             return Ok(false);
         }
         return Ok(true);
@@ -46,45 +122,41 @@ pub fn read_attr_bool(device: &Device, attr_name: &str) -> Result<bool> {
     Err(PlatformError::AttrNotFound(attr_name.to_owned()))
 }
 
-pub fn write_attr_bool(device: &mut Device, attr: &str, value: bool) -> Result<()> {
+pub fn write_attr_bool(sys_path: &Path, attr: &str, value: bool) -> Result<()> {
     let value = if value { 1 } else { 0 };
-    device
-        .set_attribute_value(attr, value.to_string())
-        .map_err(|e| {
-            warn!("attr write error: {e:?}");
-            PlatformError::IoPath(attr.into(), e)
-        })
+    write_attr(sys_path, attr, &value.to_string()).map_err(|e| {
+        warn!("attr write error: {e:?}");
+        PlatformError::IoPath(attr.into(), e)
+    })
 }
 
-pub fn read_attr_num<T>(device: &Device, attr_name: &str) -> Result<T>
+pub fn read_attr_num<T>(sys_path: &Path, attr_name: &str) -> Result<T>
 where
     T: std::str::FromStr,
     <T as std::str::FromStr>::Err: std::fmt::Debug,
 {
-    if let Some(value) = device.attribute_value(attr_name) {
-        let tmp = value.to_string_lossy();
-        return tmp.parse::<T>().map_err(|_e| PlatformError::ParseNum);
+    if let Ok(value) = read_attr(sys_path, attr_name) {
+        return value
+            .trim()
+            .parse::<T>()
+            .map_err(|_e| PlatformError::ParseNum);
     }
     Err(PlatformError::AttrNotFound(attr_name.to_owned()))
 }
 
-pub fn write_attr_num<T>(device: &mut Device, attr_name: &str, value: T) -> Result<()>
+pub fn write_attr_num<T>(sys_path: &Path, attr_name: &str, value: T) -> Result<()>
 where
     T: std::fmt::Display,
 {
-    if device
-        .set_attribute_value(attr_name, format!("{value}"))
-        .is_err()
-    {
+    if write_attr(sys_path, attr_name, &format!("{value}")).is_err() {
         return Err(PlatformError::AttrNotFound(attr_name.to_owned()));
     }
     Ok(())
 }
 
-pub fn read_attr_u8_array(device: &Device, attr_name: &str) -> Result<Vec<u8>> {
-    if let Some(value) = device.attribute_value(attr_name) {
-        let tmp = value.to_string_lossy();
-        let tmp = tmp
+pub fn read_attr_u8_array(sys_path: &Path, attr_name: &str) -> Result<Vec<u8>> {
+    if let Ok(value) = read_attr(sys_path, attr_name) {
+        let tmp = value
             .split(' ')
             .map(|v| v.parse::<u8>().unwrap_or(0))
             .collect();
@@ -93,45 +165,77 @@ pub fn read_attr_u8_array(device: &Device, attr_name: &str) -> Result<Vec<u8>> {
     Err(PlatformError::AttrNotFound(attr_name.to_owned()))
 }
 
-pub fn write_attr_u8_array(device: &mut Device, attr: &str, values: &[u8]) -> Result<()> {
+pub fn write_attr_u8_array(sys_path: &Path, attr: &str, values: &[u8]) -> Result<()> {
     let mut tmp = String::new();
     for n in values {
         tmp.push_str(&n.to_string());
         tmp.push(' '); // space padding required
     }
     tmp.pop();
-    device
-        .set_attribute_value(attr, tmp.trim())
-        .map_err(|e| PlatformError::IoPath(attr.into(), e))
+    write_attr(sys_path, attr, tmp.trim()).map_err(|e| PlatformError::IoPath(attr.into(), e))
 }
 
-pub fn read_attr_string(device: &Device, attr_name: &str) -> Result<String> {
-    if let Some(value) = device.attribute_value(attr_name) {
-        let tmp = value.to_string_lossy().to_string();
-        return Ok(tmp);
-    }
-    Err(PlatformError::AttrNotFound(attr_name.to_owned()))
+pub fn read_attr_string(sys_path: &Path, attr_name: &str) -> Result<String> {
+    read_attr(sys_path, attr_name).map_err(|_| PlatformError::AttrNotFound(attr_name.to_owned()))
 }
 
-pub fn write_attr_string(device: &mut Device, attr: &str, value: &str) -> Result<()> {
+pub fn write_attr_string(sys_path: &Path, attr: &str, value: &str) -> Result<()> {
     let tmp = value.trim();
-    device
-        .set_attribute_value(attr, tmp)
-        .map_err(|e| PlatformError::IoPath(attr.into(), e))
+    write_attr(sys_path, attr, tmp).map_err(|e| PlatformError::IoPath(attr.into(), e))
 }
 
-pub fn read_attr_string_array(device: &Device, attr_name: &str) -> Result<Vec<PlatformProfile>> {
-    if let Some(value) = device.attribute_value(attr_name) {
-        let tmp: Vec<PlatformProfile> = value
-            .to_string_lossy()
-            .split(' ')
-            .map(PlatformProfile::from)
-            .collect();
+pub fn read_attr_string_array(sys_path: &Path, attr_name: &str) -> Result<Vec<PlatformProfile>> {
+    if let Ok(value) = read_attr(sys_path, attr_name) {
+        let tmp: Vec<PlatformProfile> = value.split(' ').map(PlatformProfile::from).collect();
         return Ok(tmp);
     }
     Err(PlatformError::AttrNotFound(attr_name.to_owned()))
 }
 
+/// A tempdir tree of fake sysfs attribute files, removed on drop
+#[cfg(any(test, feature = "fixture"))]
+pub mod fixture {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    pub struct Fixture {
+        root: PathBuf,
+    }
+
+    impl Fixture {
+        /// `name` must be unique per test as tests run in parallel
+        pub fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("rog-platform-{name}-{}", std::process::id()));
+            fs::remove_dir_all(&root).ok();
+            fs::create_dir_all(&root).unwrap();
+            Self { root }
+        }
+
+        pub fn root(&self) -> &Path {
+            &self.root
+        }
+
+        /// Create the file `path` (relative to root) containing `value`
+        pub fn attr(&self, path: &str, value: &str) -> &Self {
+            let path = self.root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, value).unwrap();
+            self
+        }
+
+        pub fn read(&self, path: &str) -> String {
+            fs::read_to_string(self.root.join(path)).unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.root).ok();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,152 @@
+//! Readers for system load, used by effects that show it on the keyboard.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{PlatformError, Result};
+use crate::{enumerate, read_attr_num, DEFAULT_ROOT};
+
+/// CPU load from the time counters in `/proc/stat`. The load is found from the
+/// change in the counters between two samples.
+#[derive(Debug, Clone)]
+pub struct CpuLoad {
+    stat: PathBuf,
+    /// `(busy, total)` time of all cores, then each core
+    last: Vec<(u64, u64)>,
+}
+
+impl CpuLoad {
+    pub fn new() -> Result<Self> {
+        Self::with_root(Path::new(DEFAULT_ROOT))
+    }
+
+    /// As `new()`, but read `<root>/proc/stat`
+    pub fn with_root(root: &Path) -> Result<Self> {
+        let stat = root.join("proc/stat");
+        let last = read_stat(&stat)?;
+        Ok(Self { stat, last })
+    }
+
+    /// The load since the last sample, from 0.0 for idle to 1.0 for fully
+    /// busy. The first entry is all cores together, then each core in order.
+    pub fn sample(&mut self) -> Result<Vec<f32>> {
+        let now = read_stat(&self.stat)?;
+        let load = now
+            .iter()
+            .zip(self.last.iter().chain(std::iter::repeat(&(0, 0))))
+            .map(|((busy, total), (last_busy, last_total))| {
+                let total = total.saturating_sub(*last_total);
+                if total == 0 {
+                    return 0.0;
+                }
+                busy.saturating_sub(*last_busy) as f32 / total as f32
+            })
+            .collect();
+        self.last = now;
+        Ok(load)
+    }
+}
+
+/// Parse the `cpu` lines of `/proc/stat` in to `(busy, total)` times. Idle and
+/// iowait are the only times that are not busy.
+fn read_stat(path: &Path) -> Result<Vec<(u64, u64)>> {
+    let stat = fs::read_to_string(path)
+        .map_err(|e| PlatformError::Read(path.to_string_lossy().to_string(), e))?;
+    let times: Vec<(u64, u64)> = stat
+        .lines()
+        .filter(|line| line.starts_with("cpu"))
+        .map(|line| {
+            let fields: Vec<u64> = line
+                .split_whitespace()
+                .skip(1)
+                .filter_map(|n| n.parse().ok())
+                .collect();
+            let total: u64 = fields.iter().sum();
+            let idle = fields.get(3).unwrap_or(&0) + fields.get(4).unwrap_or(&0);
+            (total - idle, total)
+        })
+        .collect();
+    if times.is_empty() {
+        return Err(PlatformError::MissingFunction(format!(
+            "No cpu times in {path:?}"
+        )));
+    }
+    Ok(times)
+}
+
+/// A hwmon temperature sensor, such as `coretemp` or `k10temp` for the CPU
+/// package
+#[derive(Debug, Clone)]
+pub struct Hwmon {
+    path: PathBuf,
+}
+
+impl Hwmon {
+    /// Find the hwmon device with this `name`
+    pub fn find(name: &str) -> Result<Self> {
+        Self::find_with_root(Path::new(DEFAULT_ROOT), name)
+    }
+
+    /// As `find()`, but look under `root` instead of `/`
+    pub fn find_with_root(root: &Path, name: &str) -> Result<Self> {
+        enumerate(root, "hwmon")?
+            .into_iter()
+            .find(|device| {
+                device
+                    .attribute_value("name")
+                    .is_some_and(|n| n.trim() == name)
+            })
+            .map(|device| Self {
+                path: device.syspath().to_path_buf(),
+            })
+            .ok_or_else(|| PlatformError::MissingFunction(format!("No hwmon named {name}")))
+    }
+
+    /// The first temperature, `temp1_input`, in °C. For `coretemp` and
+    /// `k10temp` this is the package temperature.
+    pub fn temperature(&self) -> Result<f32> {
+        Ok(read_attr_num::<i32>(&self.path, "temp1_input")? as f32 / 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CpuLoad, Hwmon};
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn cpu_load() {
+        let fix = Fixture::new("cpu_load");
+        fix.attr(
+            "proc/stat",
+            "cpu  100 0 100 700 100 0 0 0 0 0\ncpu0 50 0 50 350 50 0 0 0 0 0\ncpu1 50 0 50 350 50 \
+             0 0 0 0 0\nintr 12345\n",
+        );
+        let mut cpu = CpuLoad::with_root(fix.root()).unwrap();
+        assert_eq!(cpu.sample().unwrap(), vec![0.0, 0.0, 0.0]);
+
+        // cpu0 busy for all of 100, cpu1 idle for all of 100
+        fix.attr(
+            "proc/stat",
+            "cpu  200 0 100 800 100 0 0 0 0 0\ncpu0 150 0 50 350 50 0 0 0 0 0\ncpu1 50 0 50 450 \
+             50 0 0 0 0 0\nintr 12345\n",
+        );
+        assert_eq!(cpu.sample().unwrap(), vec![0.5, 1.0, 0.0]);
+
+        fix.attr("proc/stat", "intr 12345\n");
+        assert!(cpu.sample().is_err());
+    }
+
+    #[test]
+    fn hwmon_temperature() {
+        let fix = Fixture::new("hwmon");
+        fix.attr("sys/class/hwmon/hwmon0/name", "acpitz\n")
+            .attr("sys/class/hwmon/hwmon0/temp1_input", "40000\n")
+            .attr("sys/class/hwmon/hwmon3/name", "k10temp\n")
+            .attr("sys/class/hwmon/hwmon3/temp1_input", "67500\n");
+
+        let hwmon = Hwmon::find_with_root(fix.root(), "k10temp").unwrap();
+        assert_eq!(hwmon.temperature().unwrap(), 67.5);
+        assert!(Hwmon::find_with_root(fix.root(), "coretemp").is_err());
+    }
+}
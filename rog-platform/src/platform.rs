@@ -1,5 +1,5 @@
 use std::fmt::Display;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use log::{info, warn};
@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 use zbus::zvariant::{OwnedValue, Type, Value};
 
 use crate::error::{PlatformError, Result};
-use crate::{attr_string, attr_string_array, to_device};
+use crate::{attr_string, attr_string_array, enumerate, DEFAULT_ROOT};
 
 /// The "platform" device provides access to things like:
 /// - `dgpu_disable`
@@ -37,29 +37,19 @@ impl RogPlatform {
     );
 
     pub fn new() -> Result<Self> {
-        let mut enumerator = udev::Enumerator::new().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("enumerator failed".into(), err)
-        })?;
-        enumerator.match_subsystem("platform").map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("match_subsystem failed".into(), err)
-        })?;
-        enumerator.match_sysname("asus-nb-wmi").map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("match_subsystem failed".into(), err)
-        })?;
-
-        if let Some(device) = (enumerator.scan_devices().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("scan_devices failed".into(), err)
-        })?)
-        .next()
+        Self::with_root(Path::new(DEFAULT_ROOT))
+    }
+
+    /// As `new()`, but find the device under `root` instead of `/`
+    pub fn with_root(root: &Path) -> Result<Self> {
+        if let Some(device) = enumerate(root, "platform")?
+            .into_iter()
+            .find(|device| device.sysname() == "asus-nb-wmi")
         {
             info!("Found platform support at {:?}", device.sysname());
             return Ok(Self {
                 path: device.syspath().to_owned(),
-                pp_path: PathBuf::from_str("/sys/firmware/acpi").unwrap(),
+                pp_path: root.join("sys/firmware/acpi"),
             });
         }
         Err(PlatformError::MissingFunction(
@@ -316,3 +306,45 @@ pub enum Properties {
     EgpuEnable,
     ThrottlePolicy,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{PlatformProfile, RogPlatform};
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn platform_profile() {
+        let fix = Fixture::new("platform");
+        fix.attr("sys/bus/platform/devices/PNP0C14:00/driver_override", "")
+            .attr("sys/bus/platform/devices/asus-nb-wmi/modalias", "")
+            .attr("sys/firmware/acpi/platform_profile", "balanced\n")
+            .attr(
+                "sys/firmware/acpi/platform_profile_choices",
+                "quiet balanced performance\n",
+            );
+
+        let platform = RogPlatform::with_root(fix.root()).unwrap();
+        assert!(platform.has_platform_profile());
+        assert_eq!(platform.get_platform_profile().unwrap(), "balanced");
+        assert_eq!(platform.get_platform_profile_choices().unwrap(), vec![
+            PlatformProfile::Quiet,
+            PlatformProfile::Balanced,
+            PlatformProfile::Performance
+        ]);
+
+        platform
+            .set_platform_profile(PlatformProfile::Performance.into())
+            .unwrap();
+        assert_eq!(
+            fix.read("sys/firmware/acpi/platform_profile"),
+            "performance"
+        );
+    }
+
+    #[test]
+    fn no_asus_nb_wmi() {
+        let fix = Fixture::new("platform_none");
+        fix.attr("sys/bus/platform/devices/PNP0C14:00/driver_override", "");
+        assert!(RogPlatform::with_root(fix.root()).is_err());
+    }
+}
@@ -1,9 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use log::{info, warn};
+use log::info;
 
 use crate::error::{PlatformError, Result};
-use crate::{attr_num, to_device};
+use crate::{attr_num, enumerate, get_attr_num, get_attr_string, has_attr, DEFAULT_ROOT};
 
 /// The "platform" device provides access to things like:
 /// - `dgpu_disable`
@@ -24,6 +24,22 @@ impl AsusPower {
 
     attr_num!("online", mains, u8);
 
+    has_attr!("capacity" battery);
+
+    get_attr_num!(
+        /// The battery charge in percent
+        "capacity" battery u8
+    );
+
+    has_attr!("status" battery);
+
+    get_attr_string!("status" battery);
+
+    /// If the battery `status` is `Charging`
+    pub fn is_charging(&self) -> Result<bool> {
+        Ok(self.get_status()?.trim() == "Charging")
+    }
+
     /// When checking for battery this will look in order:
     /// - if attr `manufacturer` contains `asus`
     /// - if attr `charge_control_end_threshold` exists and `energy_full_design`
@@ -31,26 +47,25 @@ impl AsusPower {
     /// - if syspath end conatins `BAT`
     /// - if attr `type` is `battery` (last resort)
     pub fn new() -> Result<Self> {
+        Self::with_root(Path::new(DEFAULT_ROOT))
+    }
+
+    /// The mains power supply device, if there is one. Changes to it are
+    /// announced by a `power_supply` uevent.
+    pub fn mains_path(&self) -> Option<&Path> {
+        (!self.mains.as_os_str().is_empty()).then_some(self.mains.as_path())
+    }
+
+    /// As `new()`, but find the devices under `root` instead of `/`
+    pub fn with_root(root: &Path) -> Result<Self> {
         let mut mains = PathBuf::new();
         let mut battery = None;
         let mut usb = None;
 
-        let mut enumerator = udev::Enumerator::new().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("enumerator failed".into(), err)
-        })?;
-        enumerator.match_subsystem("power_supply").map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("match_subsystem failed".into(), err)
-        })?;
-
-        for device in enumerator.scan_devices().map_err(|err| {
-            warn!("{}", err);
-            PlatformError::Udev("scan_devices failed".into(), err)
-        })? {
+        for device in enumerate(root, "power_supply")? {
             if let Some(attr) = device.attribute_value("type") {
                 info!("Power: Checking {:?}", device.syspath());
-                match attr.to_string_lossy().to_ascii_lowercase().trim() {
+                match attr.to_ascii_lowercase().trim() {
                     "mains" => {
                         info!("Found mains power at {:?}", device.sysname());
                         mains = device.syspath().to_path_buf();
@@ -69,7 +84,7 @@ impl AsusPower {
                                     device.sysname()
                                 );
                                 battery = Some(device.syspath().to_path_buf());
-                            } else if device.sysname().to_string_lossy().starts_with("BAT") {
+                            } else if device.sysname().starts_with("BAT") {
                                 info!(
                                     "Found battery power at {:?}, sysfs path ended with BAT<n>",
                                     device.sysname()
@@ -106,3 +121,72 @@ impl AsusPower {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::AsusPower;
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn find_battery_and_mains() {
+        let fix = Fixture::new("power");
+        fix.attr("sys/class/power_supply/ACAD/type", "Mains\n")
+            .attr("sys/class/power_supply/ACAD/online", "1\n")
+            .attr("sys/class/power_supply/hid-0003/type", "Battery\n")
+            .attr("sys/class/power_supply/BAT1/type", "Battery\n")
+            .attr(
+                "sys/class/power_supply/BAT1/charge_control_end_threshold",
+                "80\n",
+            )
+            .attr("sys/class/power_supply/BAT1/capacity", "67\n")
+            .attr("sys/class/power_supply/BAT1/status", "Charging\n")
+            .attr("sys/class/power_supply/ucsi-source-psy/type", "USB\n");
+
+        let power = AsusPower::with_root(fix.root()).unwrap();
+        assert!(power.has_charge_control_end_threshold());
+        assert_eq!(power.get_charge_control_end_threshold().unwrap(), 80);
+        assert_eq!(power.get_online().unwrap(), 1);
+        assert!(power.mains_path().unwrap().ends_with("ACAD"));
+        assert_eq!(power.get_capacity().unwrap(), 67);
+        assert!(power.is_charging().unwrap());
+        fix.attr("sys/class/power_supply/BAT1/status", "Discharging\n");
+        assert!(!power.is_charging().unwrap());
+
+        power.set_charge_control_end_threshold(60).unwrap();
+        assert_eq!(
+            fix.read("sys/class/power_supply/BAT1/charge_control_end_threshold"),
+            "60"
+        );
+        assert!(power.usb.is_some());
+    }
+
+    #[test]
+    fn monitor_online() {
+        let fix = Fixture::new("power_monitor");
+        fix.attr("sys/class/power_supply/AC0/type", "Mains")
+            .attr("sys/class/power_supply/AC0/online", "1")
+            .attr("sys/class/power_supply/BAT0/type", "Battery");
+
+        let power = AsusPower::with_root(fix.root()).unwrap();
+        assert!(!power.has_charge_control_end_threshold());
+        let mut watch = power.monitor_online().unwrap();
+        fix.attr("sys/class/power_supply/AC0/online", "0");
+        let mut buffer = [0; 32];
+        assert!(watch.read_events_blocking(&mut buffer).is_ok());
+        assert_eq!(power.get_online().unwrap(), 0);
+    }
+
+    #[test]
+    fn no_battery() {
+        let fix = Fixture::new("power_none");
+        fix.attr("sys/class/power_supply/AC0/type", "Mains");
+        assert!(AsusPower::with_root(fix.root()).is_err());
+
+        fix.attr("sys/class/power_supply/BAT0/type", "Battery");
+        let power = AsusPower::with_root(fix.root()).unwrap();
+        assert!(power.mains_path().is_some());
+        fix.attr("sys/class/power_supply/AC0/type", "USB");
+        let power = AsusPower::with_root(fix.root()).unwrap();
+        assert!(power.mains_path().is_none());
+    }
+}
@@ -6,7 +6,9 @@ use serde::{Deserialize, Serialize};
 use zbus::zvariant::{OwnedValue, Type, Value};
 
 use crate::error::Error;
-use crate::scsi::{apply_task, dir_task, mode_task, rgb_task, save_task, speed_task};
+use crate::scsi::{
+    apply_packet, dir_packet, mode_packet, rgb_packet, save_packet, speed_packet, task_from_packet,
+};
 
 #[cfg_attr(feature = "dbus", derive(Type, Value, OwnedValue))]
 #[derive(Debug, Clone, PartialEq, Eq, Copy, Deserialize, Serialize)]
@@ -360,23 +362,23 @@ impl Display for AuraEffect {
     }
 }
 
-impl From<&AuraEffect> for Vec<sg::Task> {
-    fn from(effect: &AuraEffect) -> Self {
-        let mut tasks = Vec::new();
-
-        tasks.append(&mut vec![
-            mode_task(effect.mode as u8),
-            rgb_task(0, &effect.colour1.into()),
-            rgb_task(1, &effect.colour2.into()),
-            rgb_task(2, &effect.colour3.into()),
-            rgb_task(3, &effect.colour4.into()),
-        ]);
-
-        if !matches!(effect.mode, AuraMode::Static | AuraMode::Off) {
-            tasks.push(speed_task(effect.speed as u8));
+impl AuraEffect {
+    /// The raw packets required to write and apply this effect. Each packet is
+    /// a 16 byte CDB followed by the register data.
+    pub fn to_packets(&self) -> Vec<Vec<u8>> {
+        let mut packets = vec![
+            mode_packet(self.mode as u8),
+            rgb_packet(0, &self.colour1.into()),
+            rgb_packet(1, &self.colour2.into()),
+            rgb_packet(2, &self.colour3.into()),
+            rgb_packet(3, &self.colour4.into()),
+        ];
+
+        if !matches!(self.mode, AuraMode::Static | AuraMode::Off) {
+            packets.push(speed_packet(self.speed as u8));
         }
         if matches!(
-            effect.mode,
+            self.mode,
             AuraMode::RainbowWave
                 | AuraMode::ChaseFade
                 | AuraMode::RainbowCycleChaseFade
@@ -385,13 +387,23 @@ impl From<&AuraEffect> for Vec<sg::Task> {
                 | AuraMode::RainbowCycleWave
                 | AuraMode::RainbowPulseChase
         ) {
-            tasks.push(dir_task(effect.direction as u8));
+            packets.push(dir_packet(self.direction as u8));
         }
 
-        tasks.append(&mut vec![
-            apply_task(),
-            save_task(),
+        packets.append(&mut vec![
+            apply_packet(),
+            save_packet(),
         ]);
-        tasks
+        packets
+    }
+}
+
+impl From<&AuraEffect> for Vec<sg::Task> {
+    fn from(effect: &AuraEffect) -> Self {
+        effect
+            .to_packets()
+            .iter()
+            .map(|pkt| task_from_packet(pkt))
+            .collect()
     }
 }
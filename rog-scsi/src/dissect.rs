@@ -0,0 +1,281 @@
+//! Decode raw SCSI packets (the 16 byte CDB followed by data, as created by
+//! `AuraEffect::to_packets()`) back in to the ENE register write they
+//! represent.
+//!
+//! Every decoded packet is re-encoded and compared against the original, any
+//! byte that does not match is flagged as unknown.
+
+use std::fmt::{self, Display};
+
+use crate::scsi::{
+    self, ENE_APPLY_VAL, ENE_REG_APPLY, ENE_REG_COLORS_DIRECT_V2, ENE_REG_COLORS_EFFECT_V2,
+    ENE_REG_DIRECTION, ENE_REG_MODE, ENE_REG_SPEED, ENE_SAVE_VAL, SCSI_CDB_LEN,
+};
+use crate::{AuraMode, Colour, Direction, Speed};
+
+/// The colour registers hold three bytes for each LED
+const ENE_COLOUR_REGS: u32 = 0x60;
+/// Signature of a USB mass storage Command Block Wrapper
+const CBW_SIGNATURE: [u8; 4] = *b"USBC";
+const CBW_LEN: usize = 31;
+const CBW_CDB_OFFSET: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScsiPacket {
+    Mode(AuraMode),
+    Speed(Speed),
+    Direction(Direction),
+    /// An effect colour
+    Colour {
+        led: u32,
+        colour: Colour,
+    },
+    /// A direct colour
+    DirectColour {
+        led: u32,
+        colour: Colour,
+    },
+    Apply,
+    Save,
+    /// A write to a register not known to rog-scsi
+    Write {
+        register: u32,
+        values: Vec<u8>,
+    },
+    /// Not an ENE register write
+    Unknown,
+}
+
+/// A decoded packet and the bytes in it that were not understood
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedPacket {
+    pub packet: ScsiPacket,
+    /// The index and value of each byte that differs from what encoding
+    /// `packet` would create
+    pub unknown: Vec<(usize, u8)>,
+}
+
+impl DecodedPacket {
+    pub fn is_unknown(&self) -> bool {
+        self.packet == ScsiPacket::Unknown
+    }
+}
+
+/// If the packet is a USB mass storage Command Block Wrapper, as seen in a
+/// usbmon capture, return the CDB within it. The data for the command is in
+/// the packet which follows.
+pub fn cdb_from_cbw(pkt: &[u8]) -> Option<&[u8]> {
+    if pkt.len() >= CBW_LEN && pkt[..4] == CBW_SIGNATURE {
+        return Some(&pkt[CBW_CDB_OFFSET..CBW_CDB_OFFSET + SCSI_CDB_LEN]);
+    }
+    None
+}
+
+/// The wire order of colour bytes is R, B, G
+fn colour(values: &[u8]) -> Colour {
+    Colour {
+        r: values.first().copied().unwrap_or(0),
+        g: values.get(2).copied().unwrap_or(0),
+        b: values.get(1).copied().unwrap_or(0),
+    }
+}
+
+fn decoded(packet: ScsiPacket, pkt: &[u8], expected: &[u8]) -> DecodedPacket {
+    let len = pkt.len().max(expected.len());
+    let unknown = (0..len)
+        .filter_map(|i| {
+            let b = pkt.get(i).copied().unwrap_or(0);
+            (b != expected.get(i).copied().unwrap_or(0)).then_some((i, b))
+        })
+        .collect();
+    DecodedPacket { packet, unknown }
+}
+
+fn unknown(pkt: &[u8]) -> DecodedPacket {
+    DecodedPacket {
+        packet: ScsiPacket::Unknown,
+        unknown: pkt.iter().copied().enumerate().collect(),
+    }
+}
+
+/// Decode a single packet of a CDB followed by the data written
+pub fn decode(pkt: &[u8]) -> DecodedPacket {
+    if pkt.len() < SCSI_CDB_LEN
+        || pkt[..3]
+            != [
+                0xec, 0x41, 0x53,
+            ]
+    {
+        return unknown(pkt);
+    }
+    let register = ((pkt[3] as u32) << 8) | pkt[4] as u32;
+    let values = &pkt[SCSI_CDB_LEN..];
+    let value = values.first().copied().unwrap_or(0);
+
+    let packet = if register == ENE_REG_MODE && values.len() == 1 {
+        ScsiPacket::Mode(AuraMode::from(value))
+    } else if register == ENE_REG_SPEED && values.len() == 1 {
+        ScsiPacket::Speed(Speed::from(value))
+    } else if register == ENE_REG_DIRECTION && values.len() == 1 {
+        ScsiPacket::Direction(Direction::from(value))
+    } else if register == ENE_REG_APPLY && values == [ENE_APPLY_VAL] {
+        ScsiPacket::Apply
+    } else if register == ENE_REG_APPLY && values == [ENE_SAVE_VAL] {
+        ScsiPacket::Save
+    } else if (ENE_REG_COLORS_EFFECT_V2..ENE_REG_COLORS_EFFECT_V2 + ENE_COLOUR_REGS)
+        .contains(&register)
+        && (register - ENE_REG_COLORS_EFFECT_V2) % 3 == 0
+        && values.len() == 3
+    {
+        ScsiPacket::Colour {
+            led: (register - ENE_REG_COLORS_EFFECT_V2) / 3,
+            colour: colour(values),
+        }
+    } else if (ENE_REG_COLORS_DIRECT_V2..ENE_REG_COLORS_DIRECT_V2 + ENE_COLOUR_REGS)
+        .contains(&register)
+        && (register - ENE_REG_COLORS_DIRECT_V2) % 3 == 0
+        && values.len() == 3
+    {
+        ScsiPacket::DirectColour {
+            led: (register - ENE_REG_COLORS_DIRECT_V2) / 3,
+            colour: colour(values),
+        }
+    } else {
+        ScsiPacket::Write {
+            register,
+            values: values.to_vec(),
+        }
+    };
+
+    let expected = match &packet {
+        ScsiPacket::Mode(mode) => scsi::packet(register, &[*mode as u8]),
+        ScsiPacket::Speed(speed) => scsi::packet(register, &[*speed as u8]),
+        ScsiPacket::Direction(direction) => scsi::packet(register, &[*direction as u8]),
+        ScsiPacket::Colour { colour, .. } | ScsiPacket::DirectColour { colour, .. } => {
+            scsi::packet(register, &<[u8; 3]>::from(*colour))
+        }
+        ScsiPacket::Apply | ScsiPacket::Save | ScsiPacket::Write { .. } => {
+            scsi::packet(register, values)
+        }
+        ScsiPacket::Unknown => Vec::new(),
+    };
+    decoded(packet, pkt, &expected)
+}
+
+impl Display for ScsiPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScsiPacket::Mode(mode) => write!(f, "ENE mode: {mode}"),
+            ScsiPacket::Speed(speed) => write!(f, "ENE speed: {speed:?}"),
+            ScsiPacket::Direction(direction) => write!(f, "ENE direction: {direction:?}"),
+            ScsiPacket::Colour { led, colour: c } => {
+                write!(f, "ENE colour {led}: #{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+            }
+            ScsiPacket::DirectColour { led, colour: c } => write!(
+                f,
+                "ENE direct colour {led}: #{:02x}{:02x}{:02x}",
+                c.r, c.g, c.b
+            ),
+            ScsiPacket::Apply => write!(f, "ENE apply"),
+            ScsiPacket::Save => write!(f, "ENE save"),
+            ScsiPacket::Write { register, values } => {
+                write!(f, "ENE write {register:#06x}: {values:02x?}")
+            }
+            ScsiPacket::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+impl Display for DecodedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.packet)?;
+        if !self.is_unknown() && !self.unknown.is_empty() {
+            write!(f, "\n  unknown bytes:")?;
+            for (i, b) in &self.unknown {
+                write!(f, " [{i}]={b:#04x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cdb_from_cbw, decode, ScsiPacket};
+    use crate::{AuraEffect, AuraMode, Colour, Direction, Speed};
+
+    #[test]
+    fn effect_round_trip() {
+        let effect = AuraEffect {
+            mode: AuraMode::Chase,
+            speed: Speed::Fast,
+            direction: Direction::Reverse,
+            colour1: Colour {
+                r: 0xff,
+                g: 0x10,
+                b: 0x20,
+            },
+            ..Default::default()
+        };
+        let decoded: Vec<_> = effect.to_packets().iter().map(|p| decode(p)).collect();
+        assert!(decoded.iter().all(|d| d.unknown.is_empty()));
+        let packets: Vec<_> = decoded.into_iter().map(|d| d.packet).collect();
+        assert_eq!(packets, vec![
+            ScsiPacket::Mode(AuraMode::Chase),
+            ScsiPacket::Colour {
+                led: 0,
+                colour: effect.colour1
+            },
+            ScsiPacket::Colour {
+                led: 1,
+                colour: effect.colour2
+            },
+            ScsiPacket::Colour {
+                led: 2,
+                colour: effect.colour3
+            },
+            ScsiPacket::Colour {
+                led: 3,
+                colour: effect.colour4
+            },
+            ScsiPacket::Speed(Speed::Fast),
+            ScsiPacket::Direction(Direction::Reverse),
+            ScsiPacket::Apply,
+            ScsiPacket::Save,
+        ]);
+    }
+
+    #[test]
+    fn unknown_bytes() {
+        let mut pkt = AuraEffect::default().to_packets()[0].clone();
+        // Mode 0x20 doesn't exist, and the CDB says 2 bytes follow
+        pkt[13] = 2;
+        pkt[16] = 0x20;
+        let decoded = decode(&pkt);
+        assert_eq!(decoded.packet, ScsiPacket::Mode(AuraMode::Static));
+        assert_eq!(decoded.unknown, vec![
+            (13, 2),
+            (16, 0x20)
+        ]);
+
+        let mut pkt = AuraEffect::default().to_packets()[0].clone();
+        pkt[4] = 0x30;
+        assert!(matches!(decode(&pkt).packet, ScsiPacket::Write {
+            register: 0x8030,
+            ..
+        }));
+        assert!(decode(&[0x5d, 0xb3]).is_unknown());
+    }
+
+    #[test]
+    fn command_block_wrapper() {
+        let cdb = &AuraEffect::default().to_packets()[0][..16];
+        let mut cbw = b"USBC".to_vec();
+        cbw.extend_from_slice(&[
+            0x01, 0, 0, 0, 0x01, 0, 0, 0, 0x00, 0x00, 0x10,
+        ]);
+        cbw.extend_from_slice(cdb);
+        assert_eq!(cdb_from_cbw(&cbw), Some(cdb));
+        assert_eq!(cdb_from_cbw(cdb), None);
+    }
+}
@@ -1,9 +1,11 @@
 mod builtin_modes;
+pub mod dissect;
 mod error;
 mod scsi;
 
 pub use builtin_modes::*;
 pub use error::*;
+pub use scsi::{task_from_packet, SCSI_CDB_LEN};
 use serde::{Deserialize, Serialize};
 pub use sg::{Device, Task};
 
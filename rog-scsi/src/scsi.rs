@@ -2,16 +2,16 @@ extern crate sg;
 
 pub use sg::Task;
 
-static ENE_APPLY_VAL: u8 = 0x01; // Value for Apply Changes Register
-static ENE_SAVE_VAL: u8 = 0xaa;
+pub(crate) static ENE_APPLY_VAL: u8 = 0x01; // Value for Apply Changes Register
+pub(crate) static ENE_SAVE_VAL: u8 = 0xaa;
 
-static ENE_REG_MODE: u32 = 0x8021; // Mode Selection Register
-static ENE_REG_SPEED: u32 = 0x8022; // Speed Control Register
-static ENE_REG_DIRECTION: u32 = 0x8023; // Direction Control Register
+pub(crate) static ENE_REG_MODE: u32 = 0x8021; // Mode Selection Register
+pub(crate) static ENE_REG_SPEED: u32 = 0x8022; // Speed Control Register
+pub(crate) static ENE_REG_DIRECTION: u32 = 0x8023; // Direction Control Register
 
-static ENE_REG_APPLY: u32 = 0x80a0;
-static _ENE_REG_COLORS_DIRECT_V2: u32 = 0x8100; // to read the colurs
-static ENE_REG_COLORS_EFFECT_V2: u32 = 0x8160;
+pub(crate) static ENE_REG_APPLY: u32 = 0x80a0;
+pub(crate) static ENE_REG_COLORS_DIRECT_V2: u32 = 0x8100; // to read the colurs
+pub(crate) static ENE_REG_COLORS_EFFECT_V2: u32 = 0x8160;
 
 fn data(reg: u32, arg_count: u8) -> [u8; 16] {
     let mut cdb = [0u8; 16];
@@ -34,47 +34,50 @@ fn data(reg: u32, arg_count: u8) -> [u8; 16] {
     cdb
 }
 
-pub(crate) fn rgb_task(led: u32, rgb: &[u8; 3]) -> Task {
+/// Length of the command descriptor block at the start of every packet
+pub const SCSI_CDB_LEN: usize = 16;
+
+/// Build the raw packet for an ENE register write. The packet is the 16 byte
+/// CDB followed by the data bytes written to the register.
+pub(crate) fn packet(reg: u32, values: &[u8]) -> Vec<u8> {
+    let mut pkt = data(reg, values.len() as u8).to_vec();
+    pkt.extend_from_slice(values);
+    pkt
+}
+
+/// Convert a raw packet (CDB followed by data) in to a task ready to be
+/// performed by the `sg` device
+pub fn task_from_packet(pkt: &[u8]) -> Task {
+    let (cdb, values) = pkt.split_at(pkt.len().min(SCSI_CDB_LEN));
     let mut task = Task::new();
-    task.set_cdb(data(led * 3 + ENE_REG_COLORS_EFFECT_V2, 3).as_slice());
-    task.set_data(rgb, sg::Direction::ToDevice);
+    task.set_cdb(cdb);
+    task.set_data(values, sg::Direction::ToDevice);
     task
 }
 
+pub(crate) fn rgb_packet(led: u32, rgb: &[u8; 3]) -> Vec<u8> {
+    packet(led * 3 + ENE_REG_COLORS_EFFECT_V2, rgb)
+}
+
 /// 0-13
-pub(crate) fn mode_task(mode: u8) -> Task {
-    let mut task = Task::new();
-    task.set_cdb(data(ENE_REG_MODE, 1).as_slice());
-    task.set_data(&[mode.min(13)], sg::Direction::ToDevice);
-    task
+pub(crate) fn mode_packet(mode: u8) -> Vec<u8> {
+    packet(ENE_REG_MODE, &[mode.min(13)])
 }
 
 /// 0-4, fast to slow
-pub(crate) fn speed_task(speed: u8) -> Task {
-    let mut task = Task::new();
-    task.set_cdb(data(ENE_REG_SPEED, 1).as_slice());
-    task.set_data(&[speed.min(4)], sg::Direction::ToDevice);
-    task
+pub(crate) fn speed_packet(speed: u8) -> Vec<u8> {
+    packet(ENE_REG_SPEED, &[speed.min(4)])
 }
 
 /// 0 = forward, 1 = backward
-pub(crate) fn dir_task(mode: u8) -> Task {
-    let mut task = Task::new();
-    task.set_cdb(data(ENE_REG_DIRECTION, 1).as_slice());
-    task.set_data(&[mode.min(1)], sg::Direction::ToDevice);
-    task
+pub(crate) fn dir_packet(mode: u8) -> Vec<u8> {
+    packet(ENE_REG_DIRECTION, &[mode.min(1)])
 }
 
-pub(crate) fn apply_task() -> Task {
-    let mut task = Task::new();
-    task.set_cdb(data(ENE_REG_APPLY, 1).as_slice());
-    task.set_data(&[ENE_APPLY_VAL], sg::Direction::ToDevice);
-    task
+pub(crate) fn apply_packet() -> Vec<u8> {
+    packet(ENE_REG_APPLY, &[ENE_APPLY_VAL])
 }
 
-pub(crate) fn save_task() -> Task {
-    let mut task = Task::new();
-    task.set_cdb(data(ENE_REG_APPLY, 1).as_slice());
-    task.set_data(&[ENE_SAVE_VAL], sg::Direction::ToDevice);
-    task
+pub(crate) fn save_packet() -> Vec<u8> {
+    packet(ENE_REG_APPLY, &[ENE_SAVE_VAL])
 }
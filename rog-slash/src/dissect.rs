@@ -0,0 +1,288 @@
+//! Decode raw Slash USB packets back in to the settings they were created
+//! from. This is the inverse of the `slash_pkt_*` builders in `usb`.
+//!
+//! Every decoded packet is re-encoded and compared against the original, any
+//! byte that does not match is flagged as unknown.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::usb::{
+    slash_pkt_battery_saver, slash_pkt_boot, slash_pkt_enable, slash_pkt_init,
+    slash_pkt_lid_closed, slash_pkt_low_battery, slash_pkt_options, slash_pkt_save,
+    slash_pkt_set_mode, slash_pkt_shutdown, slash_pkt_sleep,
+};
+use crate::{SlashMode, SlashType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlashPacket {
+    /// One of the two packets required on boot
+    Init,
+    Enable(bool),
+    Save,
+    /// Always sent directly before `SetMode`
+    SetModeStart,
+    SetMode(SlashMode),
+    Options {
+        enabled: bool,
+        brightness: u8,
+        interval: u8,
+    },
+    ShowOnBoot(bool),
+    ShowOnSleep(bool),
+    ShowOnLowBattery(bool),
+    ShowOnShutdown(bool),
+    BatterySaver(bool),
+    LidClosed(bool),
+    /// Not a packet known to rog-slash
+    Unknown,
+}
+
+/// A decoded packet and the bytes in it that were not understood
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedPacket {
+    pub packet: SlashPacket,
+    /// The index and value of each byte that differs from what encoding
+    /// `packet` would create
+    pub unknown: Vec<(usize, u8)>,
+}
+
+impl DecodedPacket {
+    pub fn is_unknown(&self) -> bool {
+        self.packet == SlashPacket::Unknown
+    }
+}
+
+fn byte(pkt: &[u8], index: usize) -> u8 {
+    pkt.get(index).copied().unwrap_or(0)
+}
+
+fn mode_from_byte(value: u8) -> Option<SlashMode> {
+    SlashMode::list()
+        .iter()
+        .filter_map(|s| SlashMode::from_str(s).ok())
+        .find(|mode| *mode as u8 == value)
+}
+
+/// Compare the captured packet against the re-encoded packet. Captured
+/// packets are often padded out to the full report size, the padding must be
+/// zero.
+fn decoded(packet: SlashPacket, pkt: &[u8], expected: &[u8]) -> DecodedPacket {
+    let unknown = pkt
+        .iter()
+        .enumerate()
+        .filter(|(i, b)| **b != expected.get(*i).copied().unwrap_or(0))
+        .map(|(i, b)| (i, *b))
+        .collect();
+    DecodedPacket { packet, unknown }
+}
+
+fn unknown(pkt: &[u8]) -> DecodedPacket {
+    DecodedPacket {
+        packet: SlashPacket::Unknown,
+        unknown: pkt.iter().copied().enumerate().collect(),
+    }
+}
+
+/// Decode a single packet. The report ID is used to pick a `SlashType` for
+/// re-encoding, as that is all the packets differ by between models.
+pub fn decode(pkt: &[u8]) -> DecodedPacket {
+    let slash_type = match byte(pkt, 0) {
+        0x5e => SlashType::GA403,
+        0x5d => SlashType::GA605,
+        _ => return unknown(pkt),
+    };
+    let status = |index| byte(pkt, index) != 0;
+    let status_inverted = |index| byte(pkt, index) == 0;
+
+    match (byte(pkt, 1), byte(pkt, 2), byte(pkt, 3), byte(pkt, 4)) {
+        (0xd7, 0x00, 0x00, 0x01) => decoded(SlashPacket::Init, pkt, &slash_pkt_init(slash_type)[0]),
+        (0xd2, 0x02, 0x01, 0x08) => decoded(SlashPacket::Init, pkt, &slash_pkt_init(slash_type)[1]),
+        (0xd2, 0x03, 0x00, 0x0c) => decoded(
+            SlashPacket::SetModeStart,
+            pkt,
+            &slash_pkt_set_mode(slash_type, SlashMode::Bounce)[0],
+        ),
+        (0xd3, 0x04, 0x00, 0x0c) => {
+            let Some(mode) = mode_from_byte(byte(pkt, 6)) else {
+                return unknown(pkt);
+            };
+            decoded(
+                SlashPacket::SetMode(mode),
+                pkt,
+                &slash_pkt_set_mode(slash_type, mode)[1],
+            )
+        }
+        (0xd4, 0x00, 0x00, 0x01) => decoded(SlashPacket::Save, pkt, &slash_pkt_save(slash_type)),
+        (0xd8, 0x02, 0x00, 0x01) => {
+            let enabled = status_inverted(5);
+            decoded(
+                SlashPacket::Enable(enabled),
+                pkt,
+                &slash_pkt_enable(slash_type, enabled),
+            )
+        }
+        (0xd8, 0x01, 0x00, 0x01) => {
+            let enabled = status_inverted(5);
+            decoded(
+                SlashPacket::BatterySaver(enabled),
+                pkt,
+                &slash_pkt_battery_saver(slash_type, enabled),
+            )
+        }
+        (0xd8, 0x00, 0x00, 0x02) => {
+            let enabled = status_inverted(6);
+            decoded(
+                SlashPacket::LidClosed(enabled),
+                pkt,
+                &slash_pkt_lid_closed(slash_type, enabled),
+            )
+        }
+        (0xd3, 0x03, 0x01, 0x08) => {
+            let enabled = status(8);
+            match byte(pkt, 5) {
+                0xab => {
+                    let (brightness, interval) = (byte(pkt, 10), byte(pkt, 12));
+                    decoded(
+                        SlashPacket::Options {
+                            enabled,
+                            brightness,
+                            interval,
+                        },
+                        pkt,
+                        &slash_pkt_options(slash_type, enabled, brightness, interval),
+                    )
+                }
+                0xa0 => decoded(
+                    SlashPacket::ShowOnBoot(enabled),
+                    pkt,
+                    &slash_pkt_boot(slash_type, enabled),
+                ),
+                0xa1 => {
+                    let enabled = status_inverted(8);
+                    decoded(
+                        SlashPacket::ShowOnSleep(enabled),
+                        pkt,
+                        &slash_pkt_sleep(slash_type, enabled),
+                    )
+                }
+                0xa2 => decoded(
+                    SlashPacket::ShowOnLowBattery(enabled),
+                    pkt,
+                    &slash_pkt_low_battery(slash_type, enabled),
+                ),
+                0xa4 => decoded(
+                    SlashPacket::ShowOnShutdown(enabled),
+                    pkt,
+                    &slash_pkt_shutdown(slash_type, enabled),
+                ),
+                _ => unknown(pkt),
+            }
+        }
+        _ => unknown(pkt),
+    }
+}
+
+impl Display for SlashPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlashPacket::Init => write!(f, "Slash init"),
+            SlashPacket::Enable(b) => write!(f, "Slash enable: {b}"),
+            SlashPacket::Save => write!(f, "Slash save"),
+            SlashPacket::SetModeStart => write!(f, "Slash set mode start"),
+            SlashPacket::SetMode(mode) => write!(f, "Slash set mode: {mode}"),
+            SlashPacket::Options {
+                enabled,
+                brightness,
+                interval,
+            } => write!(
+                f,
+                "Slash options: enabled {enabled}, brightness {brightness}, interval {interval}"
+            ),
+            SlashPacket::ShowOnBoot(b) => write!(f, "Slash show on boot: {b}"),
+            SlashPacket::ShowOnSleep(b) => write!(f, "Slash show on sleep: {b}"),
+            SlashPacket::ShowOnLowBattery(b) => write!(f, "Slash show on low battery: {b}"),
+            SlashPacket::ShowOnShutdown(b) => write!(f, "Slash show on shutdown: {b}"),
+            SlashPacket::BatterySaver(b) => write!(f, "Slash battery saver: {b}"),
+            SlashPacket::LidClosed(b) => write!(f, "Slash lid closed: {b}"),
+            SlashPacket::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+impl Display for DecodedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.packet)?;
+        if !self.is_unknown() && !self.unknown.is_empty() {
+            write!(f, "\n  unknown bytes:")?;
+            for (i, b) in &self.unknown {
+                write!(f, " [{i}]={b:#04x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, SlashPacket};
+    use crate::usb::{
+        slash_pkt_init, slash_pkt_lid_closed, slash_pkt_options, slash_pkt_set_mode,
+        slash_pkt_sleep,
+    };
+    use crate::{SlashMode, SlashType};
+
+    #[test]
+    fn round_trip() {
+        for slash_type in [
+            SlashType::GA403,
+            SlashType::GA605,
+        ] {
+            for pkt in slash_pkt_init(slash_type) {
+                let decoded = decode(&pkt);
+                assert_eq!(decoded.packet, SlashPacket::Init);
+                assert!(decoded.unknown.is_empty());
+            }
+
+            let [start, mode] = slash_pkt_set_mode(slash_type, SlashMode::Spectrum);
+            assert_eq!(decode(&start).packet, SlashPacket::SetModeStart);
+            let decoded = decode(&mode);
+            assert_eq!(decoded.packet, SlashPacket::SetMode(SlashMode::Spectrum));
+            assert!(decoded.unknown.is_empty());
+
+            let decoded = decode(&slash_pkt_options(slash_type, true, 200, 3));
+            assert_eq!(decoded.packet, SlashPacket::Options {
+                enabled: true,
+                brightness: 200,
+                interval: 3
+            });
+            assert!(decoded.unknown.is_empty());
+
+            assert_eq!(
+                decode(&slash_pkt_sleep(slash_type, true)).packet,
+                SlashPacket::ShowOnSleep(true)
+            );
+            assert_eq!(
+                decode(&slash_pkt_lid_closed(slash_type, false)).packet,
+                SlashPacket::LidClosed(false)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_bytes() {
+        // GA605 captures show 0x10 where the builder uses 0x19
+        let mut pkt = slash_pkt_set_mode(SlashType::GA605, SlashMode::Flow)[1];
+        pkt[8] = 0x10;
+        pkt[31] = 0x01;
+        let decoded = decode(&pkt);
+        assert_eq!(decoded.packet, SlashPacket::SetMode(SlashMode::Flow));
+        assert_eq!(decoded.unknown, vec![
+            (8, 0x10),
+            (31, 0x01)
+        ]);
+
+        assert!(decode(&[0x5d, 0xb3, 0x00]).is_unknown());
+        assert!(decode(&[0x5a, 0xd7, 0x00, 0x00, 0x01]).is_unknown());
+    }
+}
@@ -3,6 +3,9 @@
 mod data;
 pub use data::*;
 
+/// Decode raw USB packets back in to settings
+pub mod dissect;
+
 /// Base errors that are possible
 pub mod error;
 